@@ -1,6 +1,9 @@
-use crate::physics::{OrbitParameters, kepler_orbit};
+use crate::constants::{AU, SUN_MASS, SUN_RADIUS};
+use crate::physics::constraint::Constraint;
+use crate::physics::{DragLaw, OrbitParameters, kepler_orbit, kepler_orbit_barycentric};
 use rand::Rng;
 use raylib::color::Color;
+use rayon::prelude::*;
 use ringbuffer::{AllocRingBuffer, RingBuffer};
 use std::collections::HashMap;
 use std::sync::atomic::{AtomicUsize, Ordering};
@@ -11,19 +14,70 @@ const ASTEROID_LOW_SEMI_MAJOR_AXIS: f64 = 2.1;
 
 const MAXIMUM_POSITION_HISTORY: usize = 1000;
 
+/// Bytes taken by a point of a trail
+const TRAIL_POINT_BYTES: usize = size_of::<(f64, f64)>();
+
 /// Largest asteroid mass in Kg
 const ASTEROID_MASS_HIGH: f64 = 1E18;
 
 /// Smallest asteroid mass in Kg
 const ASTEROID_MASS_LOW: f64 = 1E5;
 
+/// Specific energy dissipated in a collision, in J/Kg, making a body glow at
+/// full temperature
+const GLOW_SPECIFIC_ENERGY: f64 = 1E6;
+
+/// Time in seconds for the glow of a body to fade by a factor `e`
+const GLOW_DECAY_SECONDS: f64 = 0.7;
+
 const ASTEROID_RADIUS_HIGH: f64 = 500_000.;
 const ASTEROID_RADIUS_LOW: f64 = 5.;
 
+/// Color of the asteroids, drawn in the one of the theme instead, see
+/// [Body::theme_color]
+pub const ASTEROID_COLOR: Color = Color::WHITESMOKE;
+
 pub type BodyId = usize;
 
 static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
 
+/// Restart the body ids from zero. Building the same system again then gives
+/// the same ids to the same bodies.
+///
+/// Only call this once every existing body has been dropped, otherwise new
+/// bodies will share ids with the old ones.
+pub fn reset_ids() {
+    NEXT_ID.store(0, Ordering::Relaxed);
+}
+
+/// Which bodies count in [OrbitalBodies::bounds]
+pub struct BoundsOptions {
+    /// Count the tier 1 bodies, not only the tier 0 ones
+    pub include_tier1: bool,
+    /// Fraction, in `[0, 1]`, of the bodies kept along each axis. The
+    /// others, the farthest out, half on either side, are left out as
+    /// outliers.
+    pub percentile: f64,
+}
+
+impl Default for BoundsOptions {
+    fn default() -> Self {
+        Self {
+            include_tier1: true,
+            percentile: 1.,
+        }
+    }
+}
+
+/// Tier of a body in [OrbitalBodies]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Tier {
+    /// Pulls and is pulled by all the bodies
+    Tier0,
+    /// Only pulled, by the tier 0 bodies
+    Tier1,
+}
+
 /// The collection of bodies being simulated
 pub struct OrbitalBodies {
     /// Tier 0 bodies have a gravity effect on all objects,
@@ -32,9 +86,20 @@ pub struct OrbitalBodies {
     /// Tier 2 bodies are influenced by tier 0, but do not
     /// influence other bodies
     pub tier1: HashMap<BodyId, Body>,
+    /// Springs between tier 0 bodies, on top of their gravity
+    pub constraints: Vec<Constraint>,
 }
 
 impl OrbitalBodies {
+    /// The bodies of both tiers, tied by no constraint
+    pub fn new(tier0: HashMap<BodyId, Body>, tier1: HashMap<BodyId, Body>) -> Self {
+        Self {
+            tier0,
+            tier1,
+            constraints: Vec::new(),
+        }
+    }
+
     pub fn iter(&self) -> impl Iterator<Item = &Body> {
         self.tier0.values().chain(self.tier1.values())
     }
@@ -43,6 +108,28 @@ impl OrbitalBodies {
         self.tier0.values_mut().chain(self.tier1.values_mut())
     }
 
+    /// Like [OrbitalBodies::iter], along with the tier of each body
+    pub fn iter_with_tier(&self) -> impl Iterator<Item = (Tier, &Body)> {
+        let tier0 = self.tier0.values().map(|body| (Tier::Tier0, body));
+        tier0.chain(self.tier1.values().map(|body| (Tier::Tier1, body)))
+    }
+
+    /// Like [OrbitalBodies::iter_mut], along with the tier of each body
+    #[allow(unused)]
+    pub fn iter_mut_with_tier(&mut self) -> impl Iterator<Item = (Tier, &mut Body)> {
+        let tier0 = self.tier0.values_mut().map(|body| (Tier::Tier0, body));
+        tier0.chain(self.tier1.values_mut().map(|body| (Tier::Tier1, body)))
+    }
+
+    /// Iterate over the bodies in parallel, for the updates of a body that
+    /// do not depend on the others
+    pub fn par_iter_mut(&mut self) -> impl ParallelIterator<Item = &mut Body> {
+        self.tier0
+            .par_iter_mut()
+            .chain(self.tier1.par_iter_mut())
+            .map(|(_, body)| body)
+    }
+
     pub fn get_by_id(&self, id: BodyId) -> Option<&Body> {
         self.tier0.get(&id).or_else(|| self.tier1.get(&id))
     }
@@ -56,23 +143,207 @@ impl OrbitalBodies {
         self.tier1.remove(&id);
     }
 
+    /// Sample the trails of the bodies that have one, see
+    /// [Body::sample_trail]
+    pub fn sample_trails(&mut self, min_distance: f64) {
+        for body in self
+            .iter_mut()
+            .filter(|body| body.trail_parameter == TrailParameter::Trail)
+        {
+            body.sample_trail(min_distance);
+        }
+    }
+
+    /// Share `budget` bytes of trail points between the bodies that have a
+    /// trail, each keeping at most the usual number of points. The others
+    /// keep room for a single point, the least a trail can hold.
+    pub fn apply_trail_budget(&mut self, budget: usize) {
+        let trails = self
+            .iter()
+            .filter(|body| body.trail_parameter == TrailParameter::Trail)
+            .count();
+        let share = (budget / TRAIL_POINT_BYTES / trails.max(1)).clamp(1, MAXIMUM_POSITION_HISTORY);
+
+        for body in self.iter_mut() {
+            body.set_trail_capacity(match body.trail_parameter {
+                TrailParameter::Trail => share,
+                TrailParameter::NoTrail => 1,
+            });
+        }
+    }
+
+    /// Keep only the bodies for which `f` returns `true`, in both tiers
+    #[allow(unused)]
+    pub fn retain<F: FnMut(&Body) -> bool>(&mut self, mut f: F) {
+        self.tier0.retain(|_, body| f(body));
+        self.tier1.retain(|_, body| f(body));
+    }
+
     pub fn len(&self) -> usize {
         self.tier0.len() + self.tier1.len()
     }
 
+    /// Ids of the bodies, of both tiers, whose center is within `radius`
+    /// meters of `center`, the edge included
+    pub fn within_radius(&self, center: (f64, f64), radius: f64) -> Vec<BodyId> {
+        let (cx, cy) = center;
+
+        self.iter()
+            .filter(|body| {
+                let (x, y) = body.pos();
+                (x - cx).powf(2.) + (y - cy).powf(2.) <= radius.powf(2.)
+            })
+            .map(Body::id)
+            .collect()
+    }
+
+    /// Move all the bodies of `other` into this system, each in its own
+    /// tier, along with its springs. Returns the number of bodies added.
+    pub fn append(&mut self, other: OrbitalBodies) -> usize {
+        let added = other.len();
+
+        for (id, body) in other.tier0 {
+            let previous = self.tier0.insert(id, body);
+            debug_assert!(previous.is_none(), "body id {id} already in use");
+        }
+
+        for (id, body) in other.tier1 {
+            let previous = self.tier1.insert(id, body);
+            debug_assert!(previous.is_none(), "body id {id} already in use");
+        }
+
+        self.constraints.extend(other.constraints);
+
+        added
+    }
+
+    /// Kinetic energy of all the bodies, in J. Fixed bodies do not move
+    /// whatever their velocity, so they carry none: every integrator
+    /// reports its energy with this convention.
+    pub fn total_kinetic_energy(&self) -> f64 {
+        self.iter()
+            .filter(|body| !body.fixed)
+            .map(Body::kinetic_energy)
+            .sum()
+    }
+
+    /// Number of bodies in each tier, as `(tier0, tier1)`
+    pub fn tier_counts(&self) -> (usize, usize) {
+        (self.tier0.len(), self.tier1.len())
+    }
+
+    /// Total mass of each tier in Kg, as `(tier0, tier1)`
+    pub fn tier_masses(&self) -> (f64, f64) {
+        let tier0 = self.tier0.values().map(|body| body.mass).sum();
+        let tier1 = self.tier1.values().map(|body| body.mass).sum();
+        (tier0, tier1)
+    }
+
+    /// Total mass of the system in Kg
+    pub fn total_mass(&self) -> f64 {
+        let (tier0, tier1) = self.tier_masses();
+        tier0 + tier1
+    }
+
+    /// Give the body the velocity that cancels out the momentum of all the
+    /// others, so that the system as a whole stays in place. The body still
+    /// moves, recoiling from the pull of the others.
     pub fn init_sun(&mut self, body_id: BodyId) {
         let mut cvx = 0.;
         let mut cvy = 0.;
 
         for body in self.iter().filter(|body| body.id != body_id) {
-            let (vx, vy) = body.velocity;
-            cvx += vx * body.mass;
-            cvy += vy * body.mass;
+            let (px, py) = body.momentum();
+            cvx += px;
+            cvy += py;
         }
 
         let sun = self.get_mut_by_id(body_id).unwrap();
         sun.velocity = (-1.0 / sun.mass * cvx, -1.0 / sun.mass * cvy);
     }
+
+    /// Total linear momentum of the tier 0 bodies, in Kg m/s. The fixed
+    /// bodies are left out, what holds them in place is outside of the
+    /// system, and so are the tier 1 bodies: pulled without pulling back,
+    /// their momentum is not conserved.
+    pub fn total_momentum(&self) -> (f64, f64) {
+        self.tier0
+            .values()
+            .filter(|body| !body.fixed)
+            .map(Body::momentum)
+            .fold((0., 0.), |(px, py), (bpx, bpy)| (px + bpx, py + bpy))
+    }
+
+    /// The most massive tier 0 body, the sun of the usual scenes
+    pub fn most_massive(&self) -> Option<&Body> {
+        self.tier0.values().max_by(|a, b| a.mass.total_cmp(&b.mass))
+    }
+
+    /// Position and velocity of the center of mass of all the bodies, `None`
+    /// without any mass
+    pub fn barycenter(&self) -> Option<((f64, f64), (f64, f64))> {
+        let total_mass = self.iter().map(|body| body.mass).sum::<f64>();
+        if total_mass <= 0. {
+            return None;
+        }
+
+        let (mut x, mut y, mut px, mut py) = (0., 0., 0., 0.);
+        for body in self.iter() {
+            let ((bx, by), (bpx, bpy)) = (body.pos(), body.momentum());
+            x += body.mass * bx;
+            y += body.mass * by;
+            px += bpx;
+            py += bpy;
+        }
+
+        Some((
+            (x / total_mass, y / total_mass),
+            (px / total_mass, py / total_mass),
+        ))
+    }
+
+    /// Pin the body in place, or let it move again. A pinned body is
+    /// stopped; a freed one gets the velocity balancing the momentum of the
+    /// system, see [OrbitalBodies::init_sun].
+    pub fn set_fixed(&mut self, body_id: BodyId, fixed: bool) {
+        let Some(body) = self.get_mut_by_id(body_id) else {
+            return;
+        };
+
+        body.fixed = fixed;
+
+        if fixed {
+            body.velocity = (0., 0.);
+        } else {
+            self.init_sun(body_id);
+        }
+    }
+
+    /// Smallest box holding the centers of the bodies, as the `(min, max)`
+    /// universe coordinates of its corners. Without bodies, the box is the
+    /// origin.
+    pub fn bounds(&self, options: BoundsOptions) -> ((f64, f64), (f64, f64)) {
+        let bodies: Box<dyn Iterator<Item = &Body>> = match options.include_tier1 {
+            true => Box::new(self.iter()),
+            false => Box::new(self.tier0.values()),
+        };
+
+        let (mut xs, mut ys): (Vec<f64>, Vec<f64>) = bodies.map(Body::pos).unzip();
+        if xs.is_empty() {
+            return ((0., 0.), (0., 0.));
+        }
+
+        xs.sort_by(f64::total_cmp);
+        ys.sort_by(f64::total_cmp);
+
+        // Each axis on its own, so an outlier on one is kept on the other
+        let last = xs.len() - 1;
+        let percentile = options.percentile.clamp(0., 1.);
+        let low = ((1. - percentile) / 2. * last as f64).round() as usize;
+        let high = last - low;
+
+        ((xs[low], ys[low]), (xs[high], ys[high]))
+    }
 }
 
 impl PartialEq for Body {
@@ -92,9 +363,22 @@ pub enum TrailParameter {
     NoTrail,
 }
 
+/// When the trails get a new point. Sampling by step would space the points
+/// by the speedup, whereas these look the same at any speedup.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TrailSampling {
+    /// Every this many simulated seconds, for all the bodies at once
+    Time(f64),
+    /// Whenever a body moved this many meters from the last point of its
+    /// trail
+    Distance(f64),
+}
+
 pub struct Body {
     /// The unique Id of the body, used for tracking it.
     id: BodyId,
+    /// Name shown wherever the body is identified, see [Body::name]
+    pub label: Option<String>,
     /// Mass of the body in KG
     pub mass: f64,
     /// Center position of the space body
@@ -105,14 +389,42 @@ pub struct Body {
     pub draw_radius: f64,
     /// Color to use for the body
     pub color: Color,
+    /// Drawn in the asteroid color of the theme rather than [Body::color],
+    /// see [crate::canvas::Theme::asteroid]
+    pub theme_color: bool,
     /// Velocity in m/s
     pub velocity: (f64, f64),
     /// Acceleration in m/s^2
     pub accel: (f64, f64),
     /// Drawing parameters
     pub trail_parameter: TrailParameter,
+    /// Color of the trail, the body's own color when `None`
+    pub trail_color: Option<Color>,
     /// The list of position of this body
     pub pos_list: AllocRingBuffer<(f64, f64)>,
+    /// Glow of the body from the heat of recent collisions, between 0
+    /// (cold) and 1. Only used for drawing.
+    pub temperature: f64,
+    /// Pinned in place: the body pulls on the others, but never moves
+    pub fixed: bool,
+    /// Strength of the drag slowing the body down, zero for none. The unit
+    /// depends on the drag law: 1/s when linear, 1/m when quadratic.
+    pub drag_coefficient: f64,
+    /// How the drag grows with the speed, only used with a nonzero
+    /// `drag_coefficient`
+    pub drag_law: DragLaw,
+    /// Steps left during which a throttled tier 1 body drifts without
+    /// acceleration, see [crate::physics::PhysicsConfig::tier1_throttle_distance]
+    pub accel_countdown: usize,
+    /// Potential energy of a tier 1 body in the field of the tier 0 bodies,
+    /// in J, as of the last time its acceleration was computed
+    pub tier1_potential_energy: f64,
+    /// Bodies only pull, and collide with, the bodies of their own group, to
+    /// run separate systems side by side. All in group 0 by default.
+    pub group_id: usize,
+    /// Mass lost to sublimation every second at 1 AU from the nearest star,
+    /// in Kg/s, zero for none. See [crate::physics::sublimation::sublimate].
+    pub mass_loss_rate: f64,
 }
 
 impl Body {
@@ -127,15 +439,26 @@ impl Body {
     ) -> Self {
         Self {
             id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            label: None,
             mass,
             pos,
             physical_radius,
             draw_radius,
             color,
+            theme_color: false,
             velocity,
             accel,
             trail_parameter: TrailParameter::Trail,
+            trail_color: None,
             pos_list: AllocRingBuffer::new(MAXIMUM_POSITION_HISTORY),
+            temperature: 0.,
+            fixed: false,
+            drag_coefficient: 0.,
+            drag_law: DragLaw::default(),
+            accel_countdown: 0,
+            tier1_potential_energy: 0.,
+            group_id: 0,
+            mass_loss_rate: 0.,
         }
     }
 
@@ -143,6 +466,30 @@ impl Body {
         self.id
     }
 
+    /// Name the body, see [Body::name]
+    pub fn with_label(mut self, label: &str) -> Self {
+        self.label = Some(label.to_string());
+        self
+    }
+
+    /// The label of the body, or its id as `#id` when it has none
+    pub fn name(&self) -> String {
+        match &self.label {
+            Some(label) => label.clone(),
+            None => format!("#{0}", self.id),
+        }
+    }
+
+    /// Give back to a loaded body the id it was saved with. Bodies created
+    /// afterwards get higher ids, so they never share it.
+    ///
+    /// Only call this on bodies replacing all the existing ones, as their
+    /// ids are not checked for collisions.
+    pub fn restore_id(&mut self, id: BodyId) {
+        self.id = id;
+        NEXT_ID.fetch_max(id + 1, Ordering::Relaxed);
+    }
+
     pub fn pos(&self) -> (f64, f64) {
         self.pos
     }
@@ -152,37 +499,205 @@ impl Body {
     }
 
     pub fn set_pos(&mut self, pos: (f64, f64)) {
-        self.pos_list.enqueue(pos);
         self.pos = pos
     }
 
+    /// Resize the trail to hold `capacity` points, keeping the most recent
+    /// ones
+    pub fn set_trail_capacity(&mut self, capacity: usize) {
+        if self.pos_list.capacity() == capacity {
+            return;
+        }
+
+        let mut pos_list = AllocRingBuffer::new(capacity);
+        pos_list.extend(self.pos_list.drain());
+        self.pos_list = pos_list;
+    }
+
+    /// Add the current position to the trail, unless it is within
+    /// `min_distance` meters of the last point
+    pub fn sample_trail(&mut self, min_distance: f64) {
+        let far_enough = match self.pos_list.back() {
+            Some(&(x, y)) => {
+                (self.pos.0 - x).powf(2.) + (self.pos.1 - y).powf(2.) >= min_distance.powf(2.)
+            }
+            None => true,
+        };
+
+        if far_enough {
+            self.pos_list.enqueue(self.pos);
+        }
+    }
+
+    /// Color to draw the trail of this body with, falling back on `default`
+    /// and then on the body's color
+    pub fn trail_color(&self, default: Option<Color>) -> Color {
+        self.trail_color.or(default).unwrap_or(self.color)
+    }
+
+    /// Heat the body with the energy, in J, dissipated by a collision
+    pub fn heat(&mut self, dissipated_energy: f64) {
+        let specific_energy = dissipated_energy.max(0.) / self.mass;
+        self.temperature = (self.temperature + specific_energy / GLOW_SPECIFIC_ENERGY).min(1.);
+    }
+
+    /// Let the glow of the body fade over `elapsed` seconds of wall time
+    pub fn cool(&mut self, elapsed: f64) {
+        self.temperature *= (-elapsed / GLOW_DECAY_SECONDS).exp();
+    }
+
+    /// Kinetic energy of the body, `1/2 m v^2`, in J
     pub fn kinetic_energy(&self) -> f64 {
         let (vx, vy) = self.velocity;
         0.5 * self.mass * (vx.powf(2.) + vy.powf(2.))
     }
 
-    #[allow(unused)]
+    /// Momentum of the body, `m v`, in Kg m/s
+    pub fn momentum(&self) -> (f64, f64) {
+        let (vx, vy) = self.velocity;
+        (self.mass * vx, self.mass * vy)
+    }
+
     pub fn actual_velocity(&self) -> f64 {
         let (vx, vy) = self.velocity;
         (vx.powf(2.) + vy.powf(2.)).sqrt()
     }
 
-    #[allow(unused)]
     pub fn actual_acceleration(&self) -> f64 {
         let (ax, ay) = self.accel;
         (ax.powf(2.) + ay.powf(2.)).sqrt()
     }
 }
 
-/// Create an asteroid belt of `asteroids` bodies. They will be randomly placed
-/// around the body.
+/// Create two stars of `m1` and `m2` Kg, `separation` meters apart, on
+/// circular orbits around their barycenter at `pos`, under the gravity
+/// constant `g`. The barycenter is at rest: the momenta of the stars cancel
+/// out.
+pub fn create_binary(m1: f64, m2: f64, separation: f64, pos: (f64, f64), g: f64) -> (Body, Body) {
+    // Main sequence stars, the radius grows roughly as the mass to the 0.8
+    let star = |mass: f64, color: Color| {
+        let radius = SUN_RADIUS * (mass / SUN_MASS).powf(0.8);
+        Body::new(mass, (0., 0.), radius, 8., color, (0., 0.), (0., 0.))
+    };
+
+    let mut primary = star(m1, Color::YELLOW);
+    let mut secondary = star(m2, Color::ORANGE);
+    primary.set_pos(pos);
+
+    kepler_orbit_barycentric(
+        OrbitParameters {
+            a: separation,
+            e: 0.,
+            theta: 0.,
+        },
+        &mut secondary,
+        &mut primary,
+        g,
+    );
+
+    for body in [&mut primary, &mut secondary] {
+        body.pos_list.clear();
+    }
+
+    (primary, secondary)
+}
+
+/// How the draw radius of generated bodies is derived from their physical
+/// radius
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DrawRadiusPolicy {
+    /// The same radius, in pixels, for every body
+    Fixed(f64),
+    /// `log10` of the physical radius in meters, clamped to `[min, max]`
+    /// pixels, so size differences show without large bodies dominating.
+    /// Bodies without a positive radius are drawn at `min`.
+    LogScaled { min: f64, max: f64 },
+}
+
+impl DrawRadiusPolicy {
+    /// Draw radius in pixels of a body of `physical_radius` meters
+    pub fn draw_radius(&self, physical_radius: f64) -> f64 {
+        match *self {
+            DrawRadiusPolicy::Fixed(radius) => radius,
+            // The log of a negative radius is NaN, which clamp lets through
+            DrawRadiusPolicy::LogScaled { min, .. } if physical_radius <= 0. => min,
+            DrawRadiusPolicy::LogScaled { min, max } => physical_radius.log10().clamp(min, max),
+        }
+    }
+}
+
+/// Highest eccentricity drawn for an asteroid, keeping the orbits bound
+const MAX_ASTEROID_ECCENTRICITY: f64 = 0.95;
+
+/// How the eccentricities of the asteroids of a belt are drawn
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum EccentricityDistribution {
+    /// Evenly spread in `[0, max)`
+    Uniform { max: f64 },
+    /// Rayleigh distributed, as in real belts, with a mean of
+    /// `scale * sqrt(pi / 2)`
+    Rayleigh { scale: f64 },
+}
+
+impl EccentricityDistribution {
+    /// Draw an eccentricity, at most [MAX_ASTEROID_ECCENTRICITY]
+    pub fn sample(&self, rng: &mut impl Rng) -> f64 {
+        let e = match *self {
+            EccentricityDistribution::Uniform { max } => rng.random::<f64>() * max,
+            EccentricityDistribution::Rayleigh { scale } => {
+                // Inverse of the cumulative distribution, 1 - u is never 0
+                scale * (-2. * (1. - rng.random::<f64>()).ln()).sqrt()
+            }
+        };
+
+        e.min(MAX_ASTEROID_ECCENTRICITY)
+    }
+}
+
+/// Shape of an asteroid belt
+#[derive(Debug, Copy, Clone)]
+pub struct BeltParameters {
+    /// Number of asteroids in the belt
+    pub asteroids: usize,
+    /// Scale of the semi-major axes, in meters
+    pub average_distance: f64,
+    /// Probability, in `[0, 1]`, of each asteroid orbiting in the opposite
+    /// direction of the others
+    pub retrograde_fraction: f64,
+    pub draw_radius_policy: DrawRadiusPolicy,
+    pub eccentricity: EccentricityDistribution,
+}
+
+impl Default for BeltParameters {
+    fn default() -> Self {
+        Self {
+            asteroids: 10_000,
+            average_distance: AU,
+            retrograde_fraction: 0.,
+            draw_radius_policy: DrawRadiusPolicy::Fixed(1.),
+            eccentricity: EccentricityDistribution::Uniform { max: 0.15 },
+        }
+    }
+}
+
+/// Create an asteroid belt around the body, under the gravity constant `g`.
+/// The asteroids are randomly placed, using `rng`, and move along with the
+/// body on top of their orbit.
 pub fn create_asteroid_belt(
     reference_body: &Body,
-    asteroids: usize,
-    average_distance: f64,
+    params: BeltParameters,
+    g: f64,
+    rng: &mut impl Rng,
 ) -> Vec<Body> {
+    let BeltParameters {
+        asteroids,
+        average_distance,
+        retrograde_fraction,
+        draw_radius_policy,
+        eccentricity,
+    } = params;
+
     let mut ret = Vec::with_capacity(asteroids);
-    let mut rng = rand::rng();
 
     macro_rules! rnd_rng {
         ($low:expr, $high:expr) => {
@@ -194,7 +709,7 @@ pub fn create_asteroid_belt(
         let a = rnd_rng!(ASTEROID_LOW_SEMI_MAJOR_AXIS, ASTEROID_HIGH_SEMI_MAJOR_AXIS)
             * average_distance;
         let theta = rng.random::<f64>() * 2.0 * std::f64::consts::PI;
-        let e = rng.random::<f64>() * 0.15;
+        let e = eccentricity.sample(rng);
 
         let mass = rnd_rng!(ASTEROID_MASS_LOW, ASTEROID_MASS_HIGH);
         let physical_radius = rnd_rng!(ASTEROID_RADIUS_LOW, ASTEROID_RADIUS_HIGH);
@@ -203,21 +718,372 @@ pub fn create_asteroid_belt(
             mass,
             (0., 0.),
             physical_radius,
-            1., // always 1px
-            Color::WHITESMOKE,
+            draw_radius_policy.draw_radius(physical_radius),
+            ASTEROID_COLOR,
             (0., 0.),
             (0., 0.),
         );
         asteroid.trail_parameter = TrailParameter::NoTrail;
+        asteroid.theme_color = true;
 
         kepler_orbit(
             OrbitParameters { a, e, theta },
             &mut asteroid,
             reference_body,
+            g,
         );
 
+        // Reverse the orbit only, not the motion of the body it is around
+        if rng.random::<f64>() < retrograde_fraction {
+            let ((vx, vy), (rvx, rvy)) = (asteroid.velocity, reference_body.velocity);
+            asteroid.velocity = (2. * rvx - vx, 2. * rvy - vy);
+        }
+
         ret.push(asteroid);
     }
 
     ret
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::body::{
+        BeltParameters, Body, BoundsOptions, DrawRadiusPolicy, EccentricityDistribution,
+        MAXIMUM_POSITION_HISTORY, OrbitalBodies, TRAIL_POINT_BYTES, Tier, TrailParameter,
+        bodies_to_map, create_asteroid_belt, create_binary,
+    };
+    use crate::constants::{AU, SUN_MASS, SUN_RADIUS};
+    use crate::physics::G;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use raylib::color::Color;
+    use ringbuffer::RingBuffer;
+
+    #[test]
+    fn test_retrograde_belt() {
+        let sun = Body::new(
+            SUN_MASS,
+            (0., 0.),
+            SUN_RADIUS,
+            1.,
+            Color::YELLOW,
+            (0., 0.),
+            (0., 0.),
+        );
+
+        let angular_momenta = |retrograde_fraction: f64| {
+            let params = BeltParameters {
+                asteroids: 100,
+                retrograde_fraction,
+                ..Default::default()
+            };
+
+            create_asteroid_belt(&sun, params, G, &mut StdRng::seed_from_u64(7))
+                .iter()
+                .map(|asteroid| {
+                    let (x, y) = asteroid.pos();
+                    let (vx, vy) = asteroid.velocity;
+                    asteroid.mass * (x * vy - y * vx)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let prograde = angular_momenta(0.);
+        let retrograde = angular_momenta(1.);
+
+        let sign = prograde[0].signum();
+        assert!(prograde.iter().all(|l| l.signum() == sign));
+        assert!(retrograde.iter().all(|l| l.signum() == -sign));
+    }
+
+    #[test]
+    fn test_belt_around_moving_body() {
+        let sun = |pos: (f64, f64), velocity: (f64, f64)| {
+            Body::new(
+                SUN_MASS,
+                pos,
+                SUN_RADIUS,
+                1.,
+                Color::YELLOW,
+                velocity,
+                (0., 0.),
+            )
+        };
+        let params = || BeltParameters {
+            asteroids: 100,
+            retrograde_fraction: 0.5,
+            ..Default::default()
+        };
+
+        let at_rest = create_asteroid_belt(
+            &sun((0., 0.), (0., 0.)),
+            params(),
+            G,
+            &mut StdRng::seed_from_u64(7),
+        );
+        let (offset, drift) = ((AU, -2. * AU), (5_000., -3_000.));
+        let moving = create_asteroid_belt(
+            &sun(offset, drift),
+            params(),
+            G,
+            &mut StdRng::seed_from_u64(7),
+        );
+
+        // The same belt, shifted and carried along by the sun, retrograde
+        // asteroids included
+        for (still, carried) in at_rest.iter().zip(&moving) {
+            let ((x, y), (cx, cy)) = (still.pos(), carried.pos());
+            assert!((cx - x - offset.0).abs() < 1e-3 && (cy - y - offset.1).abs() < 1e-3);
+
+            let ((vx, vy), (cvx, cvy)) = (still.velocity, carried.velocity);
+            assert!((cvx - vx - drift.0).abs() < 1e-6 && (cvy - vy - drift.1).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_retain_within_radius() {
+        let body =
+            |distance: f64| Body::new(1., (distance, 0.), 1., 1., Color::WHITE, (0., 0.), (0., 0.));
+
+        let mut bodies = OrbitalBodies::new(
+            bodies_to_map(vec![body(0.), body(150. * AU)]),
+            bodies_to_map(vec![body(50. * AU), body(-101. * AU), body(99. * AU)]),
+        );
+
+        bodies.retain(|body| body.pos().0.abs() <= 100. * AU);
+
+        assert_eq!(bodies.tier_counts(), (1, 2));
+        assert!(bodies.iter().all(|body| body.pos().0.abs() <= 100. * AU));
+    }
+
+    #[test]
+    fn test_within_radius() {
+        let body = |pos: (f64, f64)| Body::new(1., pos, 1., 1., Color::WHITE, (0., 0.), (0., 0.));
+
+        // Around (10, 10), at distances 0, 5 (on the edge), 5.1 and 50
+        let center = body((10., 10.));
+        let edge = body((13., 14.));
+        let outside = body((10., 15.1));
+        let far = body((-30., -20.));
+        let inside = [center.id(), edge.id()];
+
+        let bodies = OrbitalBodies::new(
+            bodies_to_map(vec![center, outside]),
+            bodies_to_map(vec![edge, far]),
+        );
+
+        let mut found = bodies.within_radius((10., 10.), 5.);
+        found.sort();
+        assert_eq!(found, inside);
+
+        assert_eq!(bodies.within_radius((10., 10.), 4.99).len(), 1);
+        assert_eq!(bodies.within_radius((10., 10.), 100.).len(), 4);
+    }
+
+    #[test]
+    fn test_kinetic_energy_and_momentum() {
+        let body = Body::new(2., (0., 0.), 1., 1., Color::WHITE, (3., -4.), (0., 0.));
+
+        // |v| = 5 m/s
+        assert_eq!(body.kinetic_energy(), 25.);
+        assert_eq!(body.momentum(), (6., -8.));
+    }
+
+    #[test]
+    fn test_trail_budget() {
+        let body = |trail: TrailParameter| {
+            let mut body = Body::new(1., (0., 0.), 1., 1., Color::WHITE, (0., 0.), (0., 0.));
+            body.trail_parameter = trail;
+            body
+        };
+        let capacity = |bodies: &OrbitalBodies| {
+            bodies
+                .iter()
+                .map(|body| body.pos_list.capacity() * TRAIL_POINT_BYTES)
+                .sum::<usize>()
+        };
+
+        let mut bodies = OrbitalBodies::new(
+            bodies_to_map(vec![body(TrailParameter::Trail)]),
+            bodies_to_map((0..100).map(|_| body(TrailParameter::NoTrail)).collect()),
+        );
+
+        // A lone trail keeps its full length
+        let budget = 1024 * 1024;
+        bodies.apply_trail_budget(budget);
+        let planet = bodies.tier0.values().next().unwrap();
+        assert_eq!(planet.pos_list.capacity(), MAXIMUM_POSITION_HISTORY);
+        assert!(
+            bodies
+                .tier1
+                .values()
+                .all(|body| body.pos_list.capacity() == 1)
+        );
+
+        // Enabling trails on many bodies shortens them all
+        for body in bodies.tier1.values_mut() {
+            body.trail_parameter = TrailParameter::Trail;
+        }
+        bodies.apply_trail_budget(budget);
+        assert!(capacity(&bodies) <= budget);
+        assert!(capacity(&bodies) > budget / 2);
+    }
+
+    #[test]
+    fn test_bounds() {
+        let body = |pos: (f64, f64)| Body::new(1., pos, 1., 1., Color::WHITE, (0., 0.), (0., 0.));
+
+        let mut bodies = OrbitalBodies::new(bodies_to_map(vec![]), bodies_to_map(vec![]));
+        assert_eq!(
+            bodies.bounds(BoundsOptions::default()),
+            ((0., 0.), (0., 0.))
+        );
+
+        bodies.tier0 = bodies_to_map((0..10).map(|i| body((i as f64, -i as f64))).collect());
+        bodies.tier1 = bodies_to_map(vec![body((1000., 0.5))]);
+
+        assert_eq!(
+            bodies.bounds(BoundsOptions::default()),
+            ((0., -9.), (1000., 0.5))
+        );
+
+        let tier0_only = BoundsOptions {
+            include_tier1: false,
+            ..Default::default()
+        };
+        assert_eq!(bodies.bounds(tier0_only), ((0., -9.), (9., 0.)));
+
+        // Eleven bodies, keeping 80% leaves out the farthest one on each
+        // side, and 60% the farthest two
+        let percentile = |percentile: f64| BoundsOptions {
+            percentile,
+            ..Default::default()
+        };
+        assert_eq!(bodies.bounds(percentile(0.8)), ((1., -8.), (9., 0.)));
+        assert_eq!(bodies.bounds(percentile(0.6)), ((2., -7.), (8., -1.)));
+    }
+
+    #[test]
+    fn test_draw_radius_policy() {
+        let fixed = DrawRadiusPolicy::Fixed(1.);
+        assert_eq!(fixed.draw_radius(5.), 1.);
+        assert_eq!(fixed.draw_radius(500_000.), 1.);
+
+        let log_scaled = DrawRadiusPolicy::LogScaled { min: 1., max: 5. };
+        assert_eq!(log_scaled.draw_radius(5.), 1.);
+        assert_eq!(log_scaled.draw_radius(100.), 2.);
+        assert_eq!(log_scaled.draw_radius(10_000.), 4.);
+        assert_eq!(log_scaled.draw_radius(500_000.), 5.);
+        assert_eq!(log_scaled.draw_radius(0.), 1.);
+        assert_eq!(log_scaled.draw_radius(-1.), 1.);
+    }
+
+    #[test]
+    fn test_eccentricity_distribution() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mean = |distribution: EccentricityDistribution, rng: &mut StdRng| {
+            let samples = (0..100_000)
+                .map(|_| distribution.sample(rng))
+                .collect::<Vec<_>>();
+            assert!(samples.iter().all(|e| (0. ..=0.95).contains(e)));
+            samples.iter().sum::<f64>() / samples.len() as f64
+        };
+
+        let scale = 0.1;
+        let expected = scale * (std::f64::consts::PI / 2.).sqrt();
+        let rayleigh = mean(EccentricityDistribution::Rayleigh { scale }, &mut rng);
+        assert!((rayleigh - expected).abs() / expected < 0.01);
+
+        let uniform = mean(BeltParameters::default().eccentricity, &mut rng);
+        assert!((uniform - 0.075).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_binary_orbits() {
+        let (m1, m2) = (SUN_MASS, 0.4 * SUN_MASS);
+        let separation = AU;
+        let center = (3. * AU, -2. * AU);
+
+        let (primary, secondary) = create_binary(m1, m2, separation, center, G);
+
+        let (px, py) = (
+            primary.momentum().0 + secondary.momentum().0,
+            primary.momentum().1 + secondary.momentum().1,
+        );
+        let scale = primary.mass * primary.actual_velocity();
+        assert!(px.abs() / scale < 1e-12 && py.abs() / scale < 1e-12);
+
+        let period = |body: &Body| {
+            let (x, y) = body.pos();
+            let r = ((x - center.0).powf(2.) + (y - center.1).powf(2.)).sqrt();
+            2. * std::f64::consts::PI * r / body.actual_velocity()
+        };
+
+        let expected = 2. * std::f64::consts::PI * (separation.powf(3.) / (G * (m1 + m2))).sqrt();
+        assert!((period(&primary) - expected).abs() / expected < 1e-9);
+        assert!((period(&secondary) - expected).abs() / expected < 1e-9);
+    }
+
+    #[test]
+    fn test_set_fixed() {
+        let sun = Body::new(
+            SUN_MASS,
+            (0., 0.),
+            1.,
+            1.,
+            Color::YELLOW,
+            (0., 0.),
+            (0., 0.),
+        );
+        let planet = Body::new(1E25, (AU, 0.), 1., 1., Color::BLUE, (0., 30_000.), (0., 0.));
+        let sun_id = sun.id();
+
+        let mut bodies =
+            OrbitalBodies::new(bodies_to_map(vec![sun, planet]), bodies_to_map(vec![]));
+        assert_eq!(bodies.most_massive().map(Body::id), Some(sun_id));
+
+        let momentum =
+            |bodies: &OrbitalBodies| bodies.iter().map(|body| body.momentum().1).sum::<f64>();
+
+        // Freed, the sun recoils so that the system stays in place
+        bodies.set_fixed(sun_id, false);
+        assert!(!bodies.get_by_id(sun_id).unwrap().fixed);
+        assert!(momentum(&bodies).abs() < 1E-9 * 1E25 * 30_000.);
+
+        bodies.set_fixed(sun_id, true);
+        let sun = bodies.get_by_id(sun_id).unwrap();
+        assert!(sun.fixed);
+        assert_eq!(sun.velocity, (0., 0.));
+    }
+
+    #[test]
+    fn test_iter_with_tier() {
+        let body = || Body::new(1., (0., 0.), 1., 1., Color::GRAY, (0., 0.), (0., 0.));
+        let mut bodies = OrbitalBodies::new(
+            bodies_to_map(vec![body(), body()]),
+            bodies_to_map(vec![body(), body(), body()]),
+        );
+
+        let tiers = bodies
+            .iter_with_tier()
+            .map(|(tier, body)| (body.id(), tier))
+            .collect::<Vec<_>>();
+        assert_eq!(tiers.len(), bodies.len());
+        for (id, tier) in tiers {
+            let map = match tier {
+                Tier::Tier0 => &bodies.tier0,
+                Tier::Tier1 => &bodies.tier1,
+            };
+            assert!(map.contains_key(&id));
+        }
+
+        for (tier, body) in bodies.iter_mut_with_tier() {
+            body.mass = match tier {
+                Tier::Tier0 => 10.,
+                Tier::Tier1 => 0.,
+            };
+        }
+        assert!(bodies.tier0.values().all(|body| body.mass == 10.));
+        assert!(bodies.tier1.values().all(|body| body.mass == 0.));
+    }
+}
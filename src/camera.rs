@@ -1,7 +1,24 @@
-use crate::body::{Body, OrbitalBodies, TrailParameter};
+use crate::body::{Body, BodyId, BoundsOptions, OrbitalBodies, Tier, TrailParameter};
+use crate::canvas::Theme;
+use crate::constants::AU;
+use crate::physics::orbit::OrbitalElements;
 use raylib::color::Color;
 use raylib::drawing::{RaylibDraw, RaylibDrawHandle};
+use raylib::math::Vector2;
 use ringbuffer::RingBuffer;
+use std::collections::HashMap;
+
+/// Color of the acceleration arrows
+const ACCEL_COLOR: Color = Color::MAGENTA;
+
+/// Smallest acceleration drawn, in m/s^2. Arrows grow by
+/// [ACCEL_PIXELS_PER_DECADE] for every factor of ten above it.
+const ACCEL_REFERENCE: f64 = 1E-9;
+
+const ACCEL_PIXELS_PER_DECADE: f64 = 8.;
+
+/// Longest acceleration arrow, in pixels
+const ACCEL_MAX_PIXELS: f64 = 120.;
 
 /// Converts coordinates from the universe into coordinates to the screen
 #[inline]
@@ -40,71 +57,1013 @@ pub fn screen_coords_to_universe(
     (ux + scaled_dx, uy + scaled_dy)
 }
 
+/// Pixels from the screen center up to which the [Projection::Logarithmic]
+/// is about linear, and past which distances are compressed
+const LOG_KNEE_PIXELS: f64 = 100.;
+
+/// How distances from the screen center are mapped to the screen
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum Projection {
+    /// At the scale, the true picture
+    #[default]
+    Linear,
+    /// At the scale near the center, compressed logarithmically further out
+    /// so that the inner planets and the outer bodies show at once. The
+    /// angles are kept, only the distances from the center are squeezed.
+    Logarithmic,
+}
+
+impl Projection {
+    /// Screen offset from the center of a point `offset` pixels away from it
+    /// at the linear scale
+    fn compress(self, offset: (f64, f64)) -> (f64, f64) {
+        self.map_radius(offset, |r| LOG_KNEE_PIXELS * (r / LOG_KNEE_PIXELS).ln_1p())
+    }
+
+    /// Inverse of [Projection::compress]
+    fn expand(self, offset: (f64, f64)) -> (f64, f64) {
+        self.map_radius(offset, |r| LOG_KNEE_PIXELS * (r / LOG_KNEE_PIXELS).exp_m1())
+    }
+
+    fn map_radius(self, (dx, dy): (f64, f64), f: impl Fn(f64) -> f64) -> (f64, f64) {
+        let r = (dx.powf(2.) + dy.powf(2.)).sqrt();
+        match self {
+            Projection::Logarithmic if r > 0. => {
+                let factor = f(r) / r;
+                (dx * factor, dy * factor)
+            }
+            _ => (dx, dy),
+        }
+    }
+}
+
+/// Compute the universe center that keeps the universe point under `cursor`
+/// at the same screen position when the scale changes from `old_scale` to
+/// `new_scale`. The center is solved for directly from the anchored point,
+/// so repeated zooms do not accumulate error.
+pub fn anchored_center(
+    cursor: (i32, i32),
+    screen_center: i32,
+    universe_center: (f64, f64),
+    old_scale: f64,
+    new_scale: f64,
+) -> (f64, f64) {
+    let (ax, ay) = screen_coords_to_universe(cursor, old_scale, universe_center, screen_center);
+    let (dx, dy) = (cursor.0 - screen_center, cursor.1 - screen_center);
+
+    (ax - dx as f64 / new_scale, ay - dy as f64 / new_scale)
+}
+
+/// Most zoomed out scale, one pixel is a thousand AU
+pub const MIN_SCALE: f64 = 1. / (AU * 1000.);
+
+/// Most zoomed in scale, one pixel is a meter
+pub const MAX_SCALE: f64 = 1.;
+
+/// Fraction of half the screen the farthest body is placed at when fitting
+/// the bodies in it
+const FIT_MARGIN: f64 = 0.9;
+
+/// Scale at which all the tier 0 bodies fit in a square screen of
+/// `screen_size` pixels centered on `universe_center`, within
+/// [MIN_SCALE, MAX_SCALE], `None` when they are all at the center
+pub fn fit_scale(
+    bodies: &OrbitalBodies,
+    universe_center: (f64, f64),
+    screen_size: i32,
+) -> Option<f64> {
+    let ((min_x, min_y), (max_x, max_y)) = bodies.bounds(BoundsOptions {
+        include_tier1: false,
+        ..Default::default()
+    });
+    let (cx, cy) = universe_center;
+
+    let farthest = [min_x - cx, max_x - cx, min_y - cy, max_y - cy]
+        .into_iter()
+        .map(f64::abs)
+        .fold(0., f64::max);
+
+    (farthest > 0.)
+        .then(|| (FIT_MARGIN * screen_size as f64 / 2. / farthest).clamp(MIN_SCALE, MAX_SCALE))
+}
+
+/// Whether any part of the body is drawn inside the square screen of the
+/// view, counting its draw radius so that bodies straddling an edge are
+/// still drawn
+pub fn is_on_screen(body: &Body, view: View) -> bool {
+    let screen_size = view.screen_center * 2;
+    let (x, y) = view.to_screen(body.pos());
+    let radius = body.draw_radius.ceil() as i32;
+
+    // Saturated far off screen, the sums must not overflow
+    let visible = |coord: i32| {
+        coord.saturating_add(radius) >= 0 && coord.saturating_sub(radius) < screen_size
+    };
+    visible(x) && visible(y)
+}
+
+/// Linear interpolation between two colors, `t` in `[0, 1]`
+fn lerp_color(from: Color, to: Color, t: f64) -> Color {
+    let lerp = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * t).round() as u8;
+    Color::new(
+        lerp(from.r, to.r),
+        lerp(from.g, to.g),
+        lerp(from.b, to.b),
+        lerp(from.a, to.a),
+    )
+}
+
+/// Color of a body, tinted toward orange then white as it heats up. The
+/// asteroids take the one of the theme, to stand out from its background.
+fn body_color(body: &Body, theme: &Theme) -> Color {
+    let color = match body.theme_color {
+        true => theme.asteroid,
+        false => body.color,
+    };
+
+    let t = body.temperature.clamp(0., 1.);
+    let heat = lerp_color(Color::ORANGE, Color::WHITE, t);
+    lerp_color(color, heat, t)
+}
+
+/// Tint of the bodies by their radial velocity as seen from the camera
+/// center, mimicking a Doppler shift: receding bodies are red, approaching
+/// bodies are blue
+pub struct Doppler {
+    /// Velocity of the observer at the camera center, in m/s
+    pub observer_velocity: (f64, f64),
+    /// Radial speed, in m/s, at which the tint is the most saturated
+    pub max_radial_speed: f64,
+}
+
+impl Doppler {
+    /// Speed of the body away from the observer at `center`, negative when
+    /// approaching
+    fn radial_velocity(&self, body: &Body, center: (f64, f64)) -> f64 {
+        let (x, y) = body.pos();
+        let (dx, dy) = (x - center.0, y - center.1);
+        let distance = (dx.powf(2.) + dy.powf(2.)).sqrt();
+        if distance == 0. {
+            return 0.;
+        }
+
+        let (vx, vy) = body.velocity;
+        let (ox, oy) = self.observer_velocity;
+        ((vx - ox) * dx + (vy - oy) * dy) / distance
+    }
+
+    fn color(&self, body: &Body, center: (f64, f64)) -> Color {
+        let t = (self.radial_velocity(body, center) / self.max_radial_speed).clamp(-1., 1.);
+        if t >= 0. {
+            lerp_color(Color::WHITE, Color::RED, t)
+        } else {
+            lerp_color(Color::WHITE, Color::BLUE, -t)
+        }
+    }
+}
+
 fn draw_body_lines(
     handle: &mut RaylibDrawHandle,
     body_lines: &[(f64, f64)],
-    universe_center: (f64, f64),
-    scale: f64,
+    view: View,
+    color: Color,
 ) {
-    let boundary = handle.get_screen_height();
-    let screen_center = boundary / 2;
-
     for i in 1..body_lines.len() {
-        let prev =
-            universe_coord_to_screen(body_lines[i - 1], scale, universe_center, screen_center);
-        let next = universe_coord_to_screen(body_lines[i], scale, universe_center, screen_center);
+        let prev = view.to_screen(body_lines[i - 1]);
+        let next = view.to_screen(body_lines[i]);
+
+        handle.draw_line(prev.0, prev.1, next.0, next.1, color)
+    }
+}
 
-        handle.draw_line(prev.0, prev.1, next.0, next.1, Color::WHITE)
+/// Points of the trail of the body as seen from the frame body, when there is
+/// one: each point is moved by how far the frame body is from where it was
+/// when the point was taken, so that the trail shows the motion relative to
+/// it, such as the retrograde loops of Mars seen from the Earth.
+///
+/// The points of both trails are paired from the most recent one, which
+/// only matches them in time with [crate::body::TrailSampling::Time].
+pub fn trail_in_frame(body: &Body, frame: Option<&Body>) -> Vec<(f64, f64)> {
+    let Some(frame) = frame else {
+        return body.pos_list.iter().copied().collect();
+    };
+
+    let (fx, fy) = frame.pos();
+    let mut points = body
+        .pos_list
+        .iter()
+        .rev()
+        .zip(frame.pos_list.iter().rev())
+        .map(|(&(x, y), &(frame_x, frame_y))| (x - frame_x + fx, y - frame_y + fy))
+        .collect::<Vec<_>>();
+
+    points.reverse();
+    points
+}
+
+/// Master switches of the trails of each tier. A body has a trail when both
+/// its tier's switch and its own [Body::trail_parameter] allow it.
+#[derive(Debug, Copy, Clone)]
+pub struct TrailToggles {
+    pub tier0: bool,
+    pub tier1: bool,
+}
+
+impl Default for TrailToggles {
+    fn default() -> Self {
+        Self {
+            tier0: true,
+            tier1: true,
+        }
     }
 }
 
+/// How the trails are drawn
+#[derive(Copy, Clone)]
+pub struct TrailView<'a> {
+    pub toggles: TrailToggles,
+    /// Body the trails are drawn relative to, see [trail_in_frame]
+    pub frame: Option<&'a Body>,
+}
+
+/// Lightness of the center of a shaded body, between its color and white
+const GRADIENT_HIGHLIGHT: f64 = 0.6;
+
+/// Smallest drawn radius, in pixels, of the tier 1 bodies that get shaded
+const GRADIENT_MIN_PIXELS: f64 = 4.;
+
+/// Pixels per AU under which only some of the tier 1 bodies are drawn, see
+/// [tier1_draw_stride]
+const SUBSAMPLE_SCALE: f64 = 100.;
+
+/// Most tier 1 bodies a drawn one stands for, when zoomed out the farthest
+const MAX_DRAW_STRIDE: usize = 1000;
+
+/// Draw one tier 1 body out of the returned count at `scale`, as zoomed out
+/// they are sub-pixel and overdraw each other. All of them are drawn above
+/// [SUBSAMPLE_SCALE]; under it, the fewer the farther out, as the area they
+/// cover on screen shrinks with the square of the scale.
+pub fn tier1_draw_stride(scale: f64) -> usize {
+    let zoom_out = SUBSAMPLE_SCALE / (scale * AU);
+    (zoom_out.powf(2.).round() as usize).clamp(1, MAX_DRAW_STRIDE)
+}
+
+/// How the bodies themselves are drawn
+#[derive(Copy, Clone, Default)]
+pub struct BodyView<'a> {
+    /// Tint the bodies by their radial velocity instead of their color
+    pub doppler: Option<&'a Doppler>,
+    /// Shade the bodies with a radial gradient, lighter at the center, for a
+    /// sphere look. Only the tier 0 bodies and the large enough tier 1 ones,
+    /// to keep the belt cheap to draw.
+    pub gradient: bool,
+    /// Only draw some of the tier 1 bodies when zoomed out, see
+    /// [tier1_draw_stride]
+    pub subsample: bool,
+    /// Draw the density of the tier 1 bodies in square cells of this many
+    /// pixels, instead of the bodies themselves
+    pub heatmap: Option<i32>,
+    /// Bodies drawn even when the subsampling or the heatmap would leave them
+    /// out, such as the followed one
+    pub always_drawn: &'a [BodyId],
+}
+
+/// Side of the cells of the tier 1 heatmap by default, in pixels
+pub const HEATMAP_CELL_PIXELS: i32 = 8;
+
+/// Color of the cells of the heatmap with a single body
+const HEATMAP_COLD: Color = Color::new(40, 60, 200, 96);
+
+/// Color of the densest cell of the heatmap
+const HEATMAP_HOT: Color = Color::new(255, 230, 120, 255);
+
+/// Number of tier 1 bodies on the screen in each square cell of `cell`
+/// pixels, keyed by the position of the cell in the grid
+fn tier1_density(bodies: &OrbitalBodies, view: View, cell: i32) -> HashMap<(i32, i32), usize> {
+    let mut density = HashMap::new();
+
+    for body in bodies
+        .tier1
+        .values()
+        .filter(|body| is_on_screen(body, view))
+    {
+        let (x, y) = view.to_screen(body.pos());
+        *density
+            .entry((x.div_euclid(cell), y.div_euclid(cell)))
+            .or_default() += 1;
+    }
+
+    density
+}
+
+/// Draw the density of the tier 1 bodies as a 2D histogram, from
+/// [HEATMAP_COLD] to [HEATMAP_HOT] on a logarithmic scale, to show the gaps
+/// and clumps of large belts
+fn draw_tier1_heatmap(
+    handle: &mut RaylibDrawHandle,
+    bodies: &OrbitalBodies,
+    view: View,
+    cell: i32,
+) {
+    let density = tier1_density(bodies, view, cell);
+    let Some(&densest) = density.values().max() else {
+        return;
+    };
+
+    for (&(cell_x, cell_y), &count) in &density {
+        let t = match densest {
+            1 => 1.,
+            _ => (count as f64).ln() / (densest as f64).ln(),
+        };
+        handle.draw_rectangle(
+            cell_x * cell,
+            cell_y * cell,
+            cell,
+            cell,
+            lerp_color(HEATMAP_COLD, HEATMAP_HOT, t),
+        );
+    }
+}
+
+/// Whether the body is drawn, one tier 1 body out of `stride`, see
+/// [tier1_draw_stride], unless it is one of [BodyView::always_drawn]
+fn is_drawn(tier: Tier, body: &Body, body_view: &BodyView, stride: usize) -> bool {
+    match tier {
+        Tier::Tier0 => true,
+        Tier::Tier1 => {
+            body_view.always_drawn.contains(&body.id())
+                || (body_view.heatmap.is_none() && body.id().is_multiple_of(stride))
+        }
+    }
+}
+
+/// Sort the bodies in the order they are drawn: the largest first, so that
+/// the smaller ones stay visible on top of them, then by id to draw the same
+/// way from a frame to the next
+fn sort_draw_order(bodies: &mut [(Tier, &Body)]) {
+    bodies.sort_by(|(_, a), (_, b)| {
+        b.draw_radius
+            .total_cmp(&a.draw_radius)
+            .then(a.id().cmp(&b.id()))
+    });
+}
+
 pub fn draw_universe_relative(
     handle: &mut RaylibDrawHandle,
     bodies: &OrbitalBodies,
-    universe_center: (f64, f64),
-    scale: f64,
+    view: View,
+    theme: &Theme,
+    body_view: BodyView,
+    trails: TrailView,
 ) {
-    let boundary = handle.get_screen_height();
-    let screen_center = boundary / 2;
+    let stride = match body_view.subsample {
+        true => tier1_draw_stride(view.scale),
+        false => 1,
+    };
 
-    for body in bodies.iter() {
-        let (screen_x, screen_y) =
-            universe_coord_to_screen(body.pos(), scale, universe_center, screen_center);
+    // All the trails first, beneath all the bodies
+    for (tier, body) in bodies.iter_with_tier() {
+        let tier_trail = match tier {
+            Tier::Tier0 => trails.toggles.tier0,
+            Tier::Tier1 => trails.toggles.tier1,
+        };
 
         // TODO: you can do this better
-        if body.trail_parameter == TrailParameter::Trail {
+        if tier_trail && body.trail_parameter == TrailParameter::Trail {
             draw_body_lines(
                 handle,
-                body.pos_list.iter().cloned().collect::<Vec<_>>().as_ref(),
-                universe_center,
-                scale,
+                &trail_in_frame(body, trails.frame),
+                view,
+                body.trail_color(theme.trail),
             );
         }
+    }
+
+    if let Some(cell) = body_view.heatmap {
+        draw_tier1_heatmap(handle, bodies, view, cell);
+    }
+
+    // By id, so that the same bodies are drawn from a frame to the next
+    let mut visible = bodies
+        .iter_with_tier()
+        .filter(|(tier, body)| is_drawn(*tier, body, &body_view, stride))
+        .filter(|(_, body)| is_on_screen(body, view))
+        .collect::<Vec<_>>();
+    sort_draw_order(&mut visible);
+
+    for (tier, body) in visible {
+        let gradient_min = match tier {
+            Tier::Tier0 => 0.,
+            Tier::Tier1 => GRADIENT_MIN_PIXELS,
+        };
+
+        let (screen_x, screen_y) = view.to_screen(body.pos());
+
+        let color = match body_view.doppler {
+            Some(doppler) => doppler.color(body, view.universe_center),
+            None => body_color(body, theme),
+        };
+
+        if body_view.gradient && body.draw_radius >= gradient_min {
+            handle.draw_circle_gradient(
+                screen_x,
+                screen_y,
+                body.draw_radius as f32,
+                lerp_color(color, Color::WHITE, GRADIENT_HIGHLIGHT),
+                color,
+            );
+        } else {
+            handle.draw_circle(screen_x, screen_y, body.draw_radius as f32, color);
+        }
+    }
+}
+
+/// Pixels between the followed body and its ring
+const MARKER_GAP: f64 = 4.;
+
+/// Pixels the ring grows by at the height of its pulse
+const MARKER_PULSE: f64 = 2.;
+
+/// Wall-clock seconds of a pulse of the ring
+const MARKER_PULSE_PERIOD: f64 = 1.5;
+
+/// Draw a pulsing ring around the followed body, so that it stands out from
+/// the bodies near the center of the screen
+pub fn draw_follow_marker(handle: &mut RaylibDrawHandle, body: &Body, view: View, theme: &Theme) {
+    if !is_on_screen(body, view) {
+        return;
+    }
+
+    let (screen_x, screen_y) = view.to_screen(body.pos());
+
+    let phase = handle.get_time() / MARKER_PULSE_PERIOD * 2. * std::f64::consts::PI;
+    let radius = body.draw_radius + MARKER_GAP + MARKER_PULSE * (phase.sin() + 1.) / 2.;
+
+    handle.draw_circle_lines(screen_x, screen_y, radius as f32, theme.marker);
+    handle.draw_circle_lines(screen_x, screen_y, radius as f32 + 1., theme.marker);
+}
+
+/// Alpha of the analytic orbit of the followed body, faint next to its trail
+const ORBIT_ALPHA: u8 = 64;
+
+/// Points drawn along the analytic orbit
+const ORBIT_POINTS: usize = 256;
+
+/// Draw the whole Keplerian ellipse the body is on around its attractor,
+/// from their current state under the gravity constant `g`, faintly in the
+/// body's color. Nothing is drawn when the body is not bound to the attractor.
+pub fn draw_analytic_orbit(
+    handle: &mut RaylibDrawHandle,
+    body: &Body,
+    attractor: &Body,
+    g: f64,
+    view: View,
+) {
+    let Some(elements) = OrbitalElements::of(body, attractor, g) else {
+        return;
+    };
+
+    let Color { r, g, b, .. } = body.color;
+    draw_body_lines(
+        handle,
+        &elements.ellipse_points(attractor.pos(), ORBIT_POINTS),
+        view,
+        Color::new(r, g, b, ORBIT_ALPHA),
+    );
+}
+
+/// Draw the body being spawned where it will appear, tied to the body it
+/// will orbit, with the eccentricity of its orbit
+pub fn draw_spawn_preview(
+    handle: &mut RaylibDrawHandle,
+    position: (f64, f64),
+    reference: Option<&Body>,
+    eccentricity: f64,
+    view: View,
+    theme: &Theme,
+) {
+    let (screen_x, screen_y) = view.to_screen(position);
+
+    handle.draw_circle_lines(screen_x, screen_y, MARKER_GAP as f32, theme.marker);
+
+    let label = match reference {
+        Some(reference) => {
+            let (reference_x, reference_y) = view.to_screen(reference.pos());
+            handle.draw_line(screen_x, screen_y, reference_x, reference_y, theme.marker);
+
+            format!("e = {eccentricity:.2}")
+        }
+        None => "at rest".to_string(),
+    };
+
+    handle.draw_text(&label, screen_x + 8, screen_y + 8, 14, theme.marker);
+}
+
+/// Length in pixels of the arrow of an acceleration of `magnitude` m/s^2.
+/// Accelerations span many orders of magnitude, from the sun's wobble to a
+/// close flyby, so the length is logarithmic.
+fn accel_arrow_length(magnitude: f64) -> f64 {
+    if magnitude <= ACCEL_REFERENCE {
+        return 0.;
+    }
+
+    ((magnitude / ACCEL_REFERENCE).log10() * ACCEL_PIXELS_PER_DECADE).min(ACCEL_MAX_PIXELS)
+}
 
-        // Outside the range
-        if screen_x >= boundary || screen_y >= boundary || screen_x < 0 || screen_y < 0 {
+/// Draw the acceleration of each tier 0 body as an arrow starting at the body
+pub fn draw_accel_vectors(handle: &mut RaylibDrawHandle, bodies: &OrbitalBodies, view: View) {
+    for body in bodies.tier0.values() {
+        let (screen_x, screen_y) = view.to_screen(body.pos());
+
+        if !is_on_screen(body, view) {
+            continue;
+        }
+
+        let magnitude = body.actual_acceleration();
+        let length = accel_arrow_length(magnitude);
+        if length == 0. {
             continue;
         }
 
-        handle.draw_circle(screen_x, screen_y, body.draw_radius as f32, body.color);
+        let (ax, ay) = body.accel;
+        let (ux, uy) = (ax / magnitude, ay / magnitude);
+
+        let start = Vector2::new(screen_x as f32, screen_y as f32);
+        let end = Vector2::new(
+            (screen_x as f64 + ux * length) as f32,
+            (screen_y as f64 + uy * length) as f32,
+        );
+        handle.draw_line_ex(start, end, 2., ACCEL_COLOR);
+
+        // Arrow head, two strokes at 30 degrees from the shaft
+        let head = (length / 4.).min(8.);
+        let (sin, cos) = (std::f64::consts::PI / 6.).sin_cos();
+        for side in [-1., 1.] {
+            let (hx, hy) = (-(ux * cos - side * uy * sin), -(side * ux * sin + uy * cos));
+            let tip = Vector2::new(
+                (end.x as f64 + hx * head) as f32,
+                (end.y as f64 + hy * head) as f32,
+            );
+            handle.draw_line_ex(end, tip, 2., ACCEL_COLOR);
+        }
     }
 }
 
-pub fn click_in_body(
+/// Whether the click lands on the body as drawn, within its draw radius
+pub fn click_in_body(screen_pos: (i32, i32), view: View, body: &Body) -> bool {
+    let (x, y) = view.to_screen(body.pos());
+    let (dx, dy) = ((screen_pos.0 - x) as f64, (screen_pos.1 - y) as f64);
+
+    (dx.powf(2.) + dy.powf(2.)).sqrt() <= body.draw_radius
+}
+
+/// Side of the cells of a [PickIndex], in pixels
+const PICK_CELL_SIZE: i32 = 32;
+
+/// Where the camera looks, to go from the universe to the screen
+#[derive(Copy, Clone)]
+pub struct View {
+    pub universe_center: (f64, f64),
+    pub screen_center: i32,
+    pub scale: f64,
+    pub projection: Projection,
+}
+
+impl View {
+    /// Screen coordinates of the universe point
+    pub fn to_screen(self, universe_coords: (f64, f64)) -> (i32, i32) {
+        if self.projection == Projection::Linear {
+            return universe_coord_to_screen(
+                universe_coords,
+                self.scale,
+                self.universe_center,
+                self.screen_center,
+            );
+        }
+
+        let (x, y) = universe_coords;
+        let (ux, uy) = self.universe_center;
+        let (dx, dy) = self
+            .projection
+            .compress(((x - ux) * self.scale, (y - uy) * self.scale));
+        let center = self.screen_center as f64;
+
+        ((center + dx) as i32, (center + dy) as i32)
+    }
+
+    /// Universe point at the screen coordinates, the inverse of
+    /// [View::to_screen]
+    pub fn to_universe(self, screen_coords: (i32, i32)) -> (f64, f64) {
+        let (x, y) = screen_coords;
+        let (dx, dy) = self.projection.expand((
+            (x - self.screen_center) as f64,
+            (y - self.screen_center) as f64,
+        ));
+        let (ux, uy) = self.universe_center;
+
+        (ux + dx / self.scale, uy + dy / self.scale)
+    }
+}
+
+/// The body under the click among `candidates`, the one whose center is
+/// closest when several overlap, then the lowest id
+fn closest_clicked<'a>(
+    candidates: impl Iterator<Item = &'a Body>,
     screen_pos: (i32, i32),
-    universe_center: (f64, f64),
-    screen_center: i32,
-    scale: f64,
-    body: &Body,
-) -> bool {
-    // TODO: this will take into account the scaling, but the drawing of the radius does not.
-    let universe_diameter = body.draw_radius / scale;
+    view: View,
+) -> Option<&'a Body> {
+    let (cx, cy) = view.to_universe(screen_pos);
+
+    candidates
+        .filter(|body| click_in_body(screen_pos, view, body))
+        .map(|body| {
+            let (bx, by) = body.pos();
+            ((bx - cx).powf(2.) + (by - cy).powf(2.), body)
+        })
+        .min_by(|(d1, b1), (d2, b2)| d1.total_cmp(d2).then(b1.id().cmp(&b2.id())))
+        .map(|(_, body)| body)
+}
 
-    // Convert the click to universe pos
-    let (cx, cy) = screen_coords_to_universe(screen_pos, scale, universe_center, screen_center);
+/// Grid of the bodies drawn on the screen, keyed by screen cell, so that a
+/// click only checks the bodies near it. Bodies move every frame: build it
+/// when a click needs it, and drop it after.
+pub struct PickIndex<'a> {
+    view: View,
+    cells: HashMap<(i32, i32), Vec<&'a Body>>,
+}
+
+impl<'a> PickIndex<'a> {
+    pub fn new(bodies: impl Iterator<Item = &'a Body>, view: View) -> Self {
+        let mut cells: HashMap<(i32, i32), Vec<&'a Body>> = HashMap::new();
+        for body in bodies.filter(|body| is_on_screen(body, view)) {
+            let (x, y) = view.to_screen(body.pos());
+
+            // One more pixel, the screen position is truncated
+            let radius = body.draw_radius.ceil() as i32 + 1;
+            let cell = |coord: i32| coord.div_euclid(PICK_CELL_SIZE);
+
+            for cell_x in cell(x - radius)..=cell(x + radius) {
+                for cell_y in cell(y - radius)..=cell(y + radius) {
+                    cells.entry((cell_x, cell_y)).or_default().push(body);
+                }
+            }
+        }
+
+        Self { view, cells }
+    }
 
-    let (bx, by) = body.pos();
+    /// The body under the click, see [click_in_body]
+    pub fn pick(&self, screen_pos: (i32, i32)) -> Option<&'a Body> {
+        let cell = (
+            screen_pos.0.div_euclid(PICK_CELL_SIZE),
+            screen_pos.1.div_euclid(PICK_CELL_SIZE),
+        );
 
-    // Check if within radius
-    ((bx - cx).powf(2.) + (by - cy).powf(2.)).sqrt() <= universe_diameter
-}
\ No newline at end of file
+        let candidates = self.cells.get(&cell)?;
+        closest_clicked(candidates.iter().copied(), screen_pos, self.view)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::body::{ASTEROID_COLOR, Body, OrbitalBodies, Tier, bodies_to_map};
+    use crate::camera::{
+        BodyView, Doppler, MAX_SCALE, MIN_SCALE, PickIndex, Projection, View, anchored_center,
+        body_color, closest_clicked, fit_scale, is_drawn, is_on_screen, screen_coords_to_universe,
+        sort_draw_order, tier1_density, tier1_draw_stride, trail_in_frame,
+        universe_coord_to_screen,
+    };
+    use crate::canvas::{DARK_THEME, LIGHT_THEME};
+    use crate::constants::AU;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use raylib::color::Color;
+
+    #[test]
+    fn test_anchored_center() {
+        let cursor = (1200, 300);
+        let screen_center = 750;
+        let start_scale = 1e-9;
+        let start_center = (1e11, -5e10);
+
+        let anchor = screen_coords_to_universe(cursor, start_scale, start_center, screen_center);
+
+        let mut center = start_center;
+        let mut scale = start_scale;
+        for factor in [1.1, 1.1, 1.1, 1. / 1.1, 1. / 1.1, 1. / 1.1].repeat(50) {
+            center = anchored_center(cursor, screen_center, center, scale, scale * factor);
+            scale *= factor;
+
+            assert_eq!(
+                universe_coord_to_screen(anchor, scale, center, screen_center),
+                cursor
+            );
+        }
+    }
+
+    #[test]
+    fn test_is_on_screen_edge() {
+        let body = |x: f64, y: f64| Body::new(1., (x, y), 1., 3., Color::WHITE, (0., 0.), (0., 0.));
+        let view = View {
+            universe_center: (0., 0.),
+            screen_center: 500,
+            scale: 1.,
+            projection: Projection::Linear,
+        };
+
+        // Centered exactly on the edges, half of the body is visible
+        for (x, y) in [(-500., 0.), (500., 0.), (0., -500.), (0., 500.)] {
+            assert!(is_on_screen(&body(x, y), view));
+        }
+
+        // Further out than the draw radius
+        for (x, y) in [(-504., 0.), (504., 0.), (0., -504.), (0., 504.)] {
+            assert!(!is_on_screen(&body(x, y), view));
+        }
+
+        // So far out the screen coordinates saturate
+        let mut huge = body(1e300, -1e300);
+        huge.draw_radius = 1e12;
+        assert!(!is_on_screen(&huge, view));
+    }
+
+    #[test]
+    fn test_asteroid_color() {
+        let body = |color, theme_color| {
+            let mut body = Body::new(1., (0., 0.), 1., 1., color, (0., 0.), (0., 0.));
+            body.theme_color = theme_color;
+            body
+        };
+
+        let asteroid = body(ASTEROID_COLOR, true);
+        assert_eq!(body_color(&asteroid, &DARK_THEME), ASTEROID_COLOR);
+        assert_eq!(body_color(&asteroid, &LIGHT_THEME), Color::GRAY);
+        assert_eq!(
+            body_color(&body(Color::RED, false), &LIGHT_THEME),
+            Color::RED
+        );
+
+        // Only by choice, not because of the color it was given
+        let white = body(ASTEROID_COLOR, false);
+        assert_eq!(body_color(&white, &LIGHT_THEME), ASTEROID_COLOR);
+    }
+
+    #[test]
+    fn test_doppler_color() {
+        let doppler = Doppler {
+            observer_velocity: (0., 0.),
+            max_radial_speed: 1000.,
+        };
+        let body = |velocity| Body::new(1., (10., 0.), 1., 1., Color::WHITE, velocity, (0., 0.));
+
+        assert_eq!(doppler.color(&body((2000., 0.)), (0., 0.)), Color::RED);
+        assert_eq!(doppler.color(&body((-2000., 0.)), (0., 0.)), Color::BLUE);
+        assert_eq!(doppler.color(&body((0., 2000.)), (0., 0.)), Color::WHITE);
+
+        // An observer moving along with the body sees no shift
+        let comoving = Doppler {
+            observer_velocity: (2000., 0.),
+            ..doppler
+        };
+        assert_eq!(comoving.color(&body((2000., 0.)), (0., 0.)), Color::WHITE);
+    }
+
+    #[test]
+    fn test_pick_matches_brute_force() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let view = View {
+            universe_center: (0., 0.),
+            screen_center: 500,
+            scale: 1e-6,
+            projection: Projection::Linear,
+        };
+
+        // Bodies well past the edges too, some large enough to overlap
+        let bodies = (0..5_000)
+            .map(|_| {
+                let pos = (rng.random_range(-6e8..6e8), rng.random_range(-6e8..6e8));
+                let draw_radius = rng.random_range(1. ..20.);
+                Body::new(1., pos, 1., draw_radius, Color::WHITE, (0., 0.), (0., 0.))
+            })
+            .collect::<Vec<_>>();
+
+        let index = PickIndex::new(bodies.iter(), view);
+
+        let mut hits = 0;
+        for _ in 0..2_000 {
+            let click = (rng.random_range(0..1000), rng.random_range(0..1000));
+            let brute_force = closest_clicked(bodies.iter(), click, view);
+
+            assert_eq!(index.pick(click).map(Body::id), brute_force.map(Body::id));
+            hits += brute_force.is_some() as usize;
+        }
+
+        assert!(hits > 0);
+    }
+
+    #[test]
+    fn test_trail_in_frame() {
+        let body = || Body::new(1., (0., 0.), 1., 1., Color::WHITE, (0., 0.), (0., 0.));
+        let (mut earth, mut mars) = (body(), body());
+
+        // Mars keeps 10 m ahead of the Earth, moving along x
+        for t in 0..5 {
+            let x = t as f64 * 100.;
+            earth.set_pos((x, 0.));
+            earth.sample_trail(0.);
+            mars.set_pos((x + 10., 0.));
+            mars.sample_trail(0.);
+        }
+
+        assert_eq!(trail_in_frame(&mars, None)[0], (10., 0.));
+
+        // Seen from the Earth, Mars stands still
+        let trail = trail_in_frame(&mars, Some(&earth));
+        assert_eq!(trail.len(), 5);
+        assert!(trail.iter().all(|&point| point == (410., 0.)));
+    }
+
+    #[test]
+    fn test_fit_scale() {
+        let scene = |size: f64| {
+            OrbitalBodies::new(
+                bodies_to_map(
+                    [(0., 0.), (size, 0.), (-size / 2., size / 2.)]
+                        .into_iter()
+                        .map(|pos| Body::new(1., pos, 1., 1., Color::WHITE, (0., 0.), (0., 0.)))
+                        .collect(),
+                ),
+                bodies_to_map(vec![Body::new(
+                    1.,
+                    (100. * size, 0.),
+                    1.,
+                    1.,
+                    Color::WHITE,
+                    (0., 0.),
+                    (0., 0.),
+                )]),
+            )
+        };
+
+        let scale = fit_scale(&scene(1e11), (0., 0.), 1000).unwrap();
+        let larger = fit_scale(&scene(2e11), (0., 0.), 1000).unwrap();
+        assert_eq!(larger, scale / 2.);
+
+        // The farthest tier 0 body ends up inside the screen
+        let (x, _) = universe_coord_to_screen((1e11, 0.), scale, (0., 0.), 500);
+        assert!((900..1000).contains(&x));
+
+        assert_eq!(fit_scale(&scene(0.), (0., 0.), 1000), None);
+
+        // Bodies a few meters apart cannot be zoomed on further
+        assert_eq!(fit_scale(&scene(1.), (0., 0.), 1000), Some(MAX_SCALE));
+        assert_eq!(fit_scale(&scene(1e30), (0., 0.), 1000), Some(MIN_SCALE));
+    }
+
+    #[test]
+    fn test_tier1_density() {
+        let body = |pos: (f64, f64)| Body::new(1., pos, 1., 1., Color::WHITE, (0., 0.), (0., 0.));
+        let view = View {
+            universe_center: (0., 0.),
+            screen_center: 100,
+            scale: 1.,
+            projection: Projection::Linear,
+        };
+
+        // Screen pixels 100 + x, three bodies in the cell at 104, one at 96
+        // and the one off the screen left out
+        let bodies = OrbitalBodies::new(
+            bodies_to_map(vec![body((5., 5.))]),
+            bodies_to_map(vec![
+                body((4., 4.)),
+                body((5., 6.)),
+                body((7., 7.)),
+                body((-3., 1.)),
+                body((500., 0.)),
+            ]),
+        );
+
+        let density = tier1_density(&bodies, view, 8);
+        assert_eq!(density.len(), 2);
+        assert_eq!(density[&(13, 13)], 3);
+        assert_eq!(density[&(12, 12)], 1);
+        assert_eq!(density.values().sum::<usize>(), 4);
+    }
+
+    #[test]
+    fn test_draw_order() {
+        let body = |draw_radius: f64| {
+            Body::new(
+                1.,
+                (0., 0.),
+                1.,
+                draw_radius,
+                Color::WHITE,
+                (0., 0.),
+                (0., 0.),
+            )
+        };
+        let (moon, planet, asteroid, twin) = (body(2.), body(8.), body(1.), body(2.));
+        let expected = [planet.id(), moon.id(), twin.id(), asteroid.id()];
+
+        // Whatever order they come in
+        let mut bodies = vec![
+            (Tier::Tier1, &asteroid),
+            (Tier::Tier0, &twin),
+            (Tier::Tier0, &moon),
+            (Tier::Tier0, &planet),
+        ];
+        sort_draw_order(&mut bodies);
+
+        let order = bodies.iter().map(|(_, body)| body.id()).collect::<Vec<_>>();
+        assert_eq!(order, expected);
+    }
+
+    #[test]
+    fn test_tier1_draw_stride() {
+        let bodies = (0..10_000)
+            .map(|_| Body::new(1., (0., 0.), 1., 1., Color::GRAY, (0., 0.), (0., 0.)))
+            .collect::<Vec<_>>();
+        let drawn = |scale: f64| {
+            let stride = tier1_draw_stride(scale);
+            bodies.iter().filter(|body| body.id() % stride == 0).count()
+        };
+
+        // Zoomed in, all of them
+        assert_eq!(drawn(200. / AU), bodies.len());
+        assert_eq!(drawn(100. / AU), bodies.len());
+
+        // Zoomed out, the fewer the farther
+        let wide = drawn(10. / AU);
+        assert!(wide <= bodies.len() / 100 + 1);
+        assert!(drawn(1. / AU) <= wide);
+        assert!(drawn(1e-6 / AU) > 0);
+    }
+
+    #[test]
+    fn test_always_drawn() {
+        let bodies = (0..10)
+            .map(|_| Body::new(1., (0., 0.), 1., 1., Color::GRAY, (0., 0.), (0., 0.)))
+            .collect::<Vec<_>>();
+        let followed = bodies
+            .iter()
+            .map(Body::id)
+            .find(|id| !id.is_multiple_of(1000))
+            .unwrap();
+        let drawn = |body_view: &BodyView| {
+            bodies
+                .iter()
+                .filter(|body| is_drawn(Tier::Tier1, body, body_view, 1000))
+                .map(Body::id)
+                .collect::<Vec<_>>()
+        };
+
+        // Left out by the stride, or the heatmap, unless followed
+        let mut body_view = BodyView::default();
+        assert!(!drawn(&body_view).contains(&followed));
+        let always_drawn = [followed];
+        body_view.always_drawn = &always_drawn;
+        assert!(drawn(&body_view).contains(&followed));
+        body_view.heatmap = Some(8);
+        assert_eq!(drawn(&body_view), vec![followed]);
+    }
+
+    #[test]
+    fn test_logarithmic_projection() {
+        let view = |projection: Projection| View {
+            universe_center: (AU, -AU),
+            screen_center: 500,
+            scale: 100. / AU,
+            projection,
+        };
+        let (linear, log) = (view(Projection::Linear), view(Projection::Logarithmic));
+
+        // The linear view is the plain conversion
+        let point = (1.5 * AU, -0.5 * AU);
+        assert_eq!(
+            linear.to_screen(point),
+            universe_coord_to_screen(point, 100. / AU, (AU, -AU), 500)
+        );
+
+        // Clicks land back where the bodies are drawn, up to a pixel
+        for (x, y) in [(0, 0), (500, 500), (120, 830), (999, 3), (510, 497)] {
+            let (ux, uy) = log.to_universe((x, y));
+            let (sx, sy) = log.to_screen((ux, uy));
+            assert!((sx - x).abs() <= 1 && (sy - y).abs() <= 1);
+        }
+
+        // About linear near the center, squeezed far out, along the same
+        // direction
+        let near = (AU + 0.05 * AU, -AU);
+        let ((log_x, _), (linear_x, _)) = (log.to_screen(near), linear.to_screen(near));
+        assert!((log_x - linear_x).abs() <= 1);
+
+        let far = (AU + 30. * AU, -AU + 30. * AU);
+        let (x, y) = log.to_screen(far);
+        assert!(x < 900 && x == y);
+        assert!(is_on_screen(
+            &Body::new(1., far, 1., 1., Color::WHITE, (0., 0.), (0., 0.)),
+            log
+        ));
+    }
+}
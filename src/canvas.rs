@@ -1,24 +1,332 @@
 use crate::SimulationState;
-use crate::body::OrbitalBodies;
-use crate::constants::SPACE_SIZE;
-use crate::physics::Kinematics;
+use crate::body::{ASTEROID_COLOR, Body, OrbitalBodies};
+use crate::constants::{AU, SECONDS_PER_DAY, SPACE_SIZE, SUN_MASS};
+use crate::physics::Gravity;
+use crate::physics::apsides::{Apsis, ApsisTracker};
+use crate::physics::collisions::MergeCondition;
+use crate::simulation::Simulation;
 use raylib::color::Color;
 use raylib::drawing::{RaylibDraw, RaylibDrawHandle};
+use raylib::text::measure_text;
 use std::time::Duration;
 
+const HUD_FONT_SIZE: i32 = 14;
+
+/// Relative energy drift below which the integration is accurate, shown in
+/// green
+const ENERGY_DRIFT_GOOD: f64 = 0.001;
+
+/// Relative energy drift below which the integration is still usable, shown
+/// in yellow. Anything above is shown in red.
+const ENERGY_DRIFT_FAIR: f64 = 0.01;
+
+/// Relative momentum drift below which only the round-off of the sums
+/// moved it, shown in green. Gravity between free bodies conserves it
+/// exactly, far tighter than the energy.
+const MOMENTUM_DRIFT_GOOD: f64 = 1e-9;
+
+/// Relative momentum drift below which the bodies are still about a closed
+/// system, shown in yellow. Anything above is shown in red.
+const MOMENTUM_DRIFT_FAIR: f64 = 1e-6;
+
+/// How long a HUD message stays on screen
+const MESSAGE_DURATION: Duration = Duration::from_secs(3);
+
+/// Seconds in a Julian year, long durations are shown in years
+const SECONDS_PER_YEAR: f64 = 365.25 * SECONDS_PER_DAY;
+
+/// Colors used to render the simulation
+#[derive(Debug, Copy, Clone)]
+pub struct Theme {
+    pub name: &'static str,
+    /// Color of the empty space
+    pub background: Color,
+    /// Color of the trails of bodies without their own trail color, the
+    /// body's color when `None`
+    pub trail: Option<Color>,
+    /// Color of the HUD text
+    pub text: Color,
+    /// Color of the ring around the followed body
+    pub marker: Color,
+    /// Color the asteroids are drawn in, the bodies with a
+    /// [crate::body::Body::theme_color]
+    pub asteroid: Color,
+}
+
+pub const DARK_THEME: Theme = Theme {
+    name: "dark",
+    background: Color::BLACK,
+    trail: None,
+    text: Color::WHITE,
+    marker: Color::LIME,
+    asteroid: ASTEROID_COLOR,
+};
+
+pub const LIGHT_THEME: Theme = Theme {
+    name: "light",
+    background: Color::RAYWHITE,
+    trail: Some(Color::DARKGRAY),
+    text: Color::BLACK,
+    marker: Color::DARKGREEN,
+    asteroid: Color::GRAY,
+};
+
+/// The available themes, the first one is the default
+pub const THEMES: [Theme; 2] = [DARK_THEME, LIGHT_THEME];
+
+/// Color of a relative drift, telling at a glance how much it drifted
+/// against its `good` and `fair` thresholds
+fn drift_color(delta: f64, good: f64, fair: f64) -> Color {
+    match delta.abs() {
+        drift if drift < good => Color::GREEN,
+        drift if drift < fair => Color::GOLD,
+        _ => Color::RED,
+    }
+}
+
+/// Color of the energy delta, see [ENERGY_DRIFT_GOOD]
+fn energy_drift_color(energy_delta: f64) -> Color {
+    drift_color(energy_delta, ENERGY_DRIFT_GOOD, ENERGY_DRIFT_FAIR)
+}
+
+/// Color of the momentum drift, see [MOMENTUM_DRIFT_GOOD]
+fn momentum_drift_color(momentum_drift: f64) -> Color {
+    drift_color(momentum_drift, MOMENTUM_DRIFT_GOOD, MOMENTUM_DRIFT_FAIR)
+}
+
 pub struct HudParams {
     pub compute_time: Duration,
     pub energy_delta: f64,
+    /// Energy delta without the energy lost in the merges, see
+    /// [crate::simulation::SimulationDiagnostics::corrected_energy_drift]
+    pub corrected_energy_delta: f64,
+    /// Energy lost in the collisions, in J, see
+    /// [crate::simulation::Simulation::dissipated_energy]
+    pub dissipated_energy: f64,
+    /// Drift of the total momentum, see
+    /// [crate::simulation::SimulationDiagnostics::momentum_drift]
+    pub momentum_drift: f64,
+    /// Virial ratio of the last step, see
+    /// [crate::physics::KinematicsDiagnostic::virial_ratio]
+    pub virial_ratio: Option<f64>,
+}
+
+/// Format a distance in meters, in km and in AU
+fn format_distance(meters: f64) -> String {
+    format!("{0:.0} km ({1:.5} AU)", meters / 1000., meters / AU)
+}
+
+/// Format a simulated duration in seconds with the unit that suits it best
+fn format_duration(seconds: f64) -> String {
+    let seconds = seconds.abs();
+
+    if seconds < 3_600. {
+        format!("{0:.1} min", seconds / 60.)
+    } else if seconds < 2. * SECONDS_PER_DAY {
+        format!("{0:.1} h", seconds / 3_600.)
+    } else if seconds < 2. * SECONDS_PER_YEAR {
+        format!("{0:.1} days", seconds / SECONDS_PER_DAY)
+    } else {
+        format!("{0:.1} years", seconds / SECONDS_PER_YEAR)
+    }
+}
+
+/// Days from 1970-01-01 to 2000-01-01, close enough to the J2000 epoch the
+/// built-in system is set at
+pub const J2000_DAY: i64 = 10_957;
+
+/// Beyond this many years from the epoch, the HUD shows the year only
+const DATE_MAX_YEARS: f64 = 10_000.;
+
+/// Days from 1970-01-01 to the given date of the proleptic Gregorian
+/// calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Date of the proleptic Gregorian calendar `days` days after 1970-01-01, as
+/// (year, month, day)
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+/// Number of days in the month of the proleptic Gregorian calendar
+fn days_in_month(year: i64, month: i64) -> i64 {
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Parse a `YYYY-MM-DD` date into days from 1970-01-01
+pub fn parse_date(text: &str) -> Option<i64> {
+    let mut parts = text.splitn(3, '-').map(str::parse::<i64>);
+    let (year, month, day) = (
+        parts.next()?.ok()?,
+        parts.next()?.ok()?,
+        parts.next()?.ok()?,
+    );
+
+    if !(1..=12).contains(&month) || !(1..=days_in_month(year, month)).contains(&day) {
+        return None;
+    }
+
+    Some(days_from_civil(year, month, day))
+}
+
+/// Format the date `seconds` simulated seconds after the start day, before
+/// it when negative, as the time runs backward when reversed
+pub fn format_date(start_day: i64, seconds: f64) -> String {
+    if seconds.abs() / SECONDS_PER_YEAR > DATE_MAX_YEARS {
+        let (start_year, ..) = civil_from_days(start_day);
+        return format!(
+            "Year {0:.0}",
+            start_year as f64 + seconds / SECONDS_PER_YEAR
+        );
+    }
+
+    let elapsed_days = (seconds / SECONDS_PER_DAY).floor();
+    let (year, month, day) = civil_from_days(start_day + elapsed_days as i64);
+
+    let minutes = ((seconds - elapsed_days * SECONDS_PER_DAY) / 60.) as i64;
+    format!(
+        "{year:04}-{month:02}-{day:02} {0:02}:{1:02}",
+        minutes / 60,
+        minutes % 60
+    )
+}
+
+/// Format the speedup with as many decimals as it takes to tell the small
+/// ones apart
+fn format_speedup(speedup: f64) -> String {
+    match speedup {
+        s if s >= 100. => format!("x{s:.0}"),
+        s if s >= 1. => format!("x{s:.1}"),
+        s => format!("x{s:.2}"),
+    }
+}
+
+/// Format a mass in Kg, both in scientific notation and in solar masses
+fn format_mass(mass: f64) -> String {
+    format!("{0:.3e} kg ({1:.3e} Msun)", mass, mass / SUN_MASS)
+}
+
+fn draw_mass_line(dh: &mut RaylibDrawHandle, bodies: &OrbitalBodies, theme: &Theme) {
+    let (tier0_count, tier1_count) = bodies.tier_counts();
+    let (tier0_mass, tier1_mass) = bodies.tier_masses();
+
+    let mass_text = format!(
+        "Tier 0: {0} @ {1}  Tier 1: {2} @ {3}  Total: {4}",
+        tier0_count,
+        format_mass(tier0_mass),
+        tier1_count,
+        format_mass(tier1_mass),
+        format_mass(bodies.total_mass()),
+    );
+
+    dh.draw_text(
+        &mass_text,
+        HUD_FONT_SIZE,
+        SPACE_SIZE as i32 - HUD_FONT_SIZE * 4,
+        HUD_FONT_SIZE,
+        theme.text,
+    );
+}
+
+fn format_apsis(apsis: Option<Apsis>) -> String {
+    match apsis {
+        Some(Apsis { distance, time, .. }) => format!(
+            "{0:.4} AU at day {1:.1}",
+            distance / AU,
+            time / SECONDS_PER_DAY
+        ),
+        None => "not yet".to_string(),
+    }
+}
+
+/// Draw the information panel of the followed body, in the top left corner.
+/// `tisserand` is the planet the Tisserand parameter of the body is taken
+/// relative to, with its value, see [crate::physics::orbit::tisserand].
+/// `accreted` is the mass the body gained in merges, see
+/// [crate::physics::collision_stats::CollisionStats::accreted].
+pub fn draw_body_info(
+    dh: &mut RaylibDrawHandle,
+    body: &Body,
+    apsis_tracker: Option<&ApsisTracker>,
+    tisserand: Option<(&Body, Option<f64>)>,
+    accreted: f64,
+    theme: &Theme,
+) {
+    let mut lines = vec![
+        body.name(),
+        format!("Mass: {0}", format_mass(body.mass)),
+        format!("Speed: {0:.3} km/s", body.actual_velocity() / 1000.),
+    ];
+
+    if accreted > 0. {
+        lines.push(format!("Accreted: {0}", format_mass(accreted)));
+    }
+
+    if let Some(tracker) = apsis_tracker {
+        lines.push(format!(
+            "Last perihelion: {0}",
+            format_apsis(tracker.last_perihelion)
+        ));
+        lines.push(match tracker.bound {
+            true => format!("Last aphelion: {0}", format_apsis(tracker.last_aphelion)),
+            false => "Last aphelion: none, unbound orbit".to_string(),
+        });
+    }
+
+    if let Some((planet, value)) = tisserand {
+        lines.push(match value {
+            Some(value) => format!("Tisserand to {0}: {value:.3}", planet.name()),
+            None => format!("Tisserand to {0}: n/a, not orbiting", planet.name()),
+        });
+    }
+
+    for (i, line) in lines.iter().enumerate() {
+        dh.draw_text(
+            line,
+            HUD_FONT_SIZE,
+            HUD_FONT_SIZE + i as i32 * (HUD_FONT_SIZE + 4),
+            HUD_FONT_SIZE,
+            theme.text,
+        );
+    }
 }
 
 pub fn draw_hud(
     dh: &mut RaylibDrawHandle,
     simulation_state: &SimulationState,
-    bodies: &OrbitalBodies,
-    kin: &Box<dyn Kinematics>,
+    simulation: &Simulation,
     params: HudParams,
 ) {
-    let mut all_text = vec![kin.name()];
+    let bodies = &simulation.bodies;
+    let mut all_text = vec![simulation.kinematics.name()];
 
     let n_bodies_text = format!("{0} bodies", bodies.len());
     all_text.push(&n_bodies_text);
@@ -28,12 +336,53 @@ pub fn draw_hud(
         false => "Collisions off",
     });
 
+    if simulation_state.merge_condition == MergeCondition::Approaching {
+        all_text.push("Merge on approach");
+    }
+
+    if simulation_state.physics.gravity == Gravity::Exact {
+        all_text.push("Exact gravity");
+    }
+
+    if simulation_state.physics.tier1_throttle_distance.is_some() {
+        all_text.push("Throttled tier 1");
+    }
+
+    if simulation_state.physics.freeze_tier1 {
+        all_text.push("Frozen tier 1");
+    }
+
+    if simulation.comet_tails {
+        all_text.push("Comet tails");
+    }
+
+    let exponent_text = format!("F ~ 1/r^{0:.1}", simulation_state.physics.exponent);
+    all_text.push(&exponent_text);
+
+    let date_text = format_date(simulation_state.start_day, simulation.time());
+    all_text.push(&date_text);
+
     all_text.push(match simulation_state.paused {
         true => "Paused",
         false => "",
     });
 
-    let speedup_text = format!("Speedup: {0:.1}", simulation_state.speedup);
+    if simulation_state.lagging() {
+        all_text.push("LAGGING");
+    }
+
+    let speedup_text = match simulation_state.step_seconds {
+        Some(seconds) => format!(
+            "Step: {0} s ({1}/frame)",
+            seconds,
+            format_duration(simulation_state.dt())
+        ),
+        None => format!(
+            "Speedup: {0} ({1}/frame)",
+            format_speedup(simulation_state.speedup),
+            format_duration(simulation_state.dt())
+        ),
+    };
     all_text.push(&speedup_text);
 
     all_text.push(if simulation_state.dt_factor < 0. {
@@ -45,19 +394,156 @@ pub fn draw_hud(
     let HudParams {
         compute_time,
         energy_delta,
+        corrected_energy_delta,
+        dissipated_energy,
+        momentum_drift,
+        virial_ratio,
     } = params;
 
     let compute_text = format!("{0}ms", compute_time.as_millis());
     all_text.push(&compute_text);
 
-    let energy_delta_text = format!("E: {0:.2} (%)", energy_delta * 100.);
-    all_text.push(&energy_delta_text);
+    let virial_text = match virial_ratio {
+        Some(ratio) => format!("2T/|U|: {ratio:.3}"),
+        None => "2T/|U|: n/a".to_string(),
+    };
+    if simulation_state.show_virial {
+        all_text.push(&virial_text);
+    }
+
+    // The energy deltas go last, in their own color
+    all_text.push("");
+    let hud_text = all_text.join("  ");
+    let hud_y = SPACE_SIZE as i32 - HUD_FONT_SIZE * 2;
+
+    dh.draw_text(
+        &hud_text,
+        HUD_FONT_SIZE,
+        hud_y,
+        HUD_FONT_SIZE,
+        simulation_state.theme().text,
+    );
+
+    // Then the one without the merges, telling the integrator error apart
+    // from the energy the collisions dissipated
+    let mut energy_x = HUD_FONT_SIZE + measure_text(&hud_text, HUD_FONT_SIZE);
+    for (label, delta) in [
+        ("E", energy_delta),
+        ("E w/o merges", corrected_energy_delta),
+    ] {
+        let energy_text = format!("{label}: {0:.2} (%)  ", delta * 100.);
+        dh.draw_text(
+            &energy_text,
+            energy_x,
+            hud_y,
+            HUD_FONT_SIZE,
+            energy_drift_color(delta),
+        );
+        energy_x += measure_text(&energy_text, HUD_FONT_SIZE);
+    }
+
+    let dissipated_text = format!("Dissipated: {dissipated_energy:.2e} J  ");
+    dh.draw_text(
+        &dissipated_text,
+        energy_x,
+        hud_y,
+        HUD_FONT_SIZE,
+        simulation_state.theme().text,
+    );
+    energy_x += measure_text(&dissipated_text, HUD_FONT_SIZE);
 
     dh.draw_text(
-        &all_text.join("  "),
-        14,
-        SPACE_SIZE as i32 - 14 * 2,
-        14,
-        Color::WHITE,
+        &format!("P: {momentum_drift:.1e}"),
+        energy_x,
+        hud_y,
+        HUD_FONT_SIZE,
+        momentum_drift_color(momentum_drift),
     );
+
+    draw_mass_line(dh, bodies, simulation_state.theme());
+
+    if simulation.divergence().is_some() {
+        let banner = "DIVERGED: reset, and lower the speedup or change the integrator";
+        dh.draw_text(
+            banner,
+            (SPACE_SIZE as i32 - measure_text(banner, HUD_FONT_SIZE * 2)) / 2,
+            HUD_FONT_SIZE * 4,
+            HUD_FONT_SIZE * 2,
+            Color::RED,
+        );
+    }
+
+    if let Some(approach) = &simulation_state.approach {
+        let name = |id| bodies.get_by_id(id).map_or(format!("#{id}"), Body::name);
+        let (first, second) = (name(approach.pair.0), name(approach.pair.1));
+        let closest = match approach.closest() {
+            Some((distance, time)) => format!(
+                "{0} on day {1:.1}",
+                format_distance(distance),
+                time / SECONDS_PER_DAY
+            ),
+            None => "n/a".to_string(),
+        };
+        let current = approach
+            .current()
+            .map_or("n/a".to_string(), format_distance);
+
+        dh.draw_text(
+            &format!("{first} - {second}  Closest: {closest}  Now: {current}"),
+            HUD_FONT_SIZE,
+            SPACE_SIZE as i32 - HUD_FONT_SIZE * 8,
+            HUD_FONT_SIZE,
+            simulation_state.theme().text,
+        );
+    }
+
+    if let Some((message, posted)) = &simulation_state.message
+        && posted.elapsed() < MESSAGE_DURATION
+    {
+        dh.draw_text(
+            message,
+            HUD_FONT_SIZE,
+            SPACE_SIZE as i32 - HUD_FONT_SIZE * 6,
+            HUD_FONT_SIZE,
+            simulation_state.theme().text,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::canvas::{J2000_DAY, SECONDS_PER_YEAR, format_date, parse_date};
+    use crate::constants::SECONDS_PER_DAY;
+
+    #[test]
+    fn test_format_date() {
+        assert_eq!(parse_date("2000-01-01"), Some(J2000_DAY));
+        assert_eq!(parse_date("1970-01-01"), Some(0));
+        assert_eq!(parse_date("2000-13-01"), None);
+        assert_eq!(
+            parse_date("2024-02-29"),
+            Some(parse_date("2024-03-01").unwrap() - 1)
+        );
+        assert_eq!(parse_date("2000-02-29"), Some(J2000_DAY + 59));
+        assert_eq!(parse_date("2023-02-29"), None);
+        assert_eq!(parse_date("2024-02-30"), None);
+        assert_eq!(parse_date("2023-04-31"), None);
+        assert_eq!(parse_date("1900-02-29"), None);
+        assert_eq!(parse_date("tomorrow"), None);
+
+        assert_eq!(format_date(J2000_DAY, 0.), "2000-01-01 00:00");
+        assert_eq!(
+            format_date(J2000_DAY, 59. * SECONDS_PER_DAY + 13.5 * 3_600.),
+            "2000-02-29 13:30"
+        );
+        assert_eq!(format_date(J2000_DAY, SECONDS_PER_YEAR), "2000-12-31 06:00");
+
+        // Reversed past the start
+        assert_eq!(format_date(J2000_DAY, -3_600.), "1999-12-31 23:00");
+
+        assert_eq!(
+            format_date(J2000_DAY, 1e6 * SECONDS_PER_YEAR),
+            "Year 1002000"
+        );
+    }
 }
@@ -4,6 +4,7 @@ pub const MOON_MASS: f64 = 7.32 * 1E22;
 pub const SUN_MASS: f64 = 1.989 * 1E30;
 pub const MARS_MASS: f64 = 6.39 * 1E23;
 pub const HALEYS_COMET_MASS: f64 = 2.2 * 1E14;
+pub const HALEYS_MASS_LOSS_RATE: f64 = 1E4; // Kg/s at 1 AU
 pub const MARS_VELOCITY: f64 = 24.077 * 1000.0; // m/s
 pub const EARTH_SUN_VELOCITY: f64 = 29_784.8;
 pub const MOON_EARTH_VELOCITY: f64 = 1023.;
@@ -19,3 +20,6 @@ pub const MOON_RADIUS: f64 = 1737400.;
 pub const HALEYS_RADIUS: f64 = 5500.;
 
 pub const AU: f64 = SUN_EARTH_DISTANCE;
+
+/// Seconds in a day
+pub const SECONDS_PER_DAY: f64 = 86_400.;
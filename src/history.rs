@@ -0,0 +1,207 @@
+use crate::body::{BodyId, OrbitalBodies};
+use std::collections::VecDeque;
+
+/// Dynamic state of a body, enough to resume the integration from it
+struct BodyState {
+    id: BodyId,
+    pos: (f64, f64),
+    velocity: (f64, f64),
+    accel: (f64, f64),
+}
+
+struct Snapshot {
+    /// Simulated time of the snapshot, in seconds
+    time: f64,
+    bodies: Vec<BodyState>,
+}
+
+impl Snapshot {
+    fn size(&self) -> usize {
+        size_of::<Snapshot>() + self.bodies.capacity() * size_of::<BodyState>()
+    }
+}
+
+/// The last frames of the simulation, to scrub through them without
+/// integrating again. The oldest snapshots are dropped to stay within both a
+/// number of frames and a memory budget.
+pub struct SnapshotHistory {
+    snapshots: VecDeque<Snapshot>,
+    /// Most snapshots kept
+    max_frames: usize,
+    /// Most memory used by the snapshots, in bytes
+    max_bytes: usize,
+    /// Memory currently used by the snapshots, in bytes
+    bytes: usize,
+    /// Whether tier 1 bodies are recorded. When they are not, they stay where
+    /// they are while scrubbing.
+    include_tier1: bool,
+    /// Snapshot shown while scrubbing, `None` when live
+    cursor: Option<usize>,
+}
+
+impl SnapshotHistory {
+    pub fn new(max_frames: usize, max_bytes: usize, include_tier1: bool) -> Self {
+        Self {
+            snapshots: VecDeque::new(),
+            max_frames,
+            max_bytes,
+            bytes: 0,
+            include_tier1,
+            cursor: None,
+        }
+    }
+
+    /// Record the state of the bodies after a step. Pushing while scrubbing
+    /// forgets the snapshots after the one shown, as the simulation resumes
+    /// from there.
+    pub fn push(&mut self, bodies: &OrbitalBodies, time: f64) {
+        if let Some(cursor) = self.cursor.take() {
+            for dropped in self.snapshots.drain(cursor + 1..) {
+                self.bytes -= dropped.size();
+            }
+        }
+
+        let recorded = match self.include_tier1 {
+            true => bodies
+                .tier0
+                .values()
+                .chain(bodies.tier1.values())
+                .collect::<Vec<_>>(),
+            false => bodies.tier0.values().collect::<Vec<_>>(),
+        };
+
+        let snapshot = Snapshot {
+            time,
+            bodies: recorded
+                .into_iter()
+                .map(|body| BodyState {
+                    id: body.id(),
+                    pos: body.pos(),
+                    velocity: body.velocity,
+                    accel: body.accel,
+                })
+                .collect(),
+        };
+
+        self.bytes += snapshot.size();
+        self.snapshots.push_back(snapshot);
+
+        while self.snapshots.len() > self.max_frames || self.bytes > self.max_bytes {
+            match self.snapshots.pop_front() {
+                Some(dropped) => self.bytes -= dropped.size(),
+                None => break,
+            }
+        }
+    }
+
+    /// Forget all the snapshots, for when the bodies are replaced
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+        self.bytes = 0;
+        self.cursor = None;
+    }
+
+    /// Move `frames` snapshots forward in time, or back when negative, and
+    /// put the bodies in the state of that snapshot. Bodies destroyed since
+    /// are not brought back. Returns the simulated time of the snapshot, or
+    /// `None` when there is nothing to scrub through.
+    pub fn scrub(&mut self, frames: isize, bodies: &mut OrbitalBodies) -> Option<f64> {
+        let last = self.snapshots.len().checked_sub(1)?;
+        let current = self.cursor.unwrap_or(last) as isize;
+        let target = (current + frames).clamp(0, last as isize) as usize;
+
+        self.cursor = Some(target);
+
+        let snapshot = &self.snapshots[target];
+        for state in &snapshot.bodies {
+            if let Some(body) = bodies.get_mut_by_id(state.id) {
+                body.set_pos(state.pos);
+                body.velocity = state.velocity;
+                body.accel = state.accel;
+            }
+        }
+
+        Some(snapshot.time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::body::{Body, OrbitalBodies, bodies_to_map};
+    use crate::constants::{EARTH_MASS, EARTH_SUN_VELOCITY, SUN_EARTH_DISTANCE, SUN_MASS};
+    use crate::history::SnapshotHistory;
+    use crate::physics::leapfrog::Leapfrog;
+    use crate::physics::{Kinematics, PhysicsConfig};
+    use raylib::color::Color;
+
+    type State = (usize, (f64, f64), (f64, f64), (f64, f64));
+
+    fn states(bodies: &OrbitalBodies) -> Vec<State> {
+        let mut states = bodies
+            .iter()
+            .map(|body| (body.id(), body.pos(), body.velocity, body.accel))
+            .collect::<Vec<_>>();
+        states.sort_by_key(|state| state.0);
+        states
+    }
+
+    #[test]
+    fn test_scrub_back_and_forward() {
+        let sun = Body::new(
+            SUN_MASS,
+            (0., 0.),
+            1.,
+            1.,
+            Color::YELLOW,
+            (0., 0.),
+            (0., 0.),
+        );
+        let earth = Body::new(
+            EARTH_MASS,
+            (0., SUN_EARTH_DISTANCE),
+            1.,
+            1.,
+            Color::BLUE,
+            (EARTH_SUN_VELOCITY, 0.),
+            (0., 0.),
+        );
+        let mut bodies = OrbitalBodies::new(bodies_to_map(vec![sun, earth]), bodies_to_map(vec![]));
+
+        let mut history = SnapshotHistory::new(10, usize::MAX, true);
+        let dt = 3600.;
+        for step in 1..=20 {
+            Leapfrog.step(&mut bodies, dt, &PhysicsConfig::default());
+            history.push(&bodies, step as f64 * dt);
+        }
+
+        let live = states(&bodies);
+
+        // Only the last 10 frames are kept
+        assert_eq!(history.scrub(-100, &mut bodies), Some(11. * dt));
+        assert_ne!(states(&bodies), live);
+
+        assert_eq!(history.scrub(100, &mut bodies), Some(20. * dt));
+        assert_eq!(states(&bodies), live);
+    }
+
+    #[test]
+    fn test_memory_budget() {
+        let bodies = OrbitalBodies::new(
+            bodies_to_map(
+                (0..100)
+                    .map(|i| {
+                        Body::new(1., (i as f64, 0.), 1., 1., Color::WHITE, (0., 0.), (0., 0.))
+                    })
+                    .collect(),
+            ),
+            bodies_to_map(vec![]),
+        );
+
+        let budget = 20_000;
+        let mut history = SnapshotHistory::new(1000, budget, false);
+        for step in 0..100 {
+            history.push(&bodies, step as f64);
+            assert!(history.bytes <= budget);
+        }
+    }
+}
@@ -1,86 +1,783 @@
-use crate::body::OrbitalBodies;
-use crate::camera::{click_in_body, screen_coords_to_universe};
-use crate::constants::SPACE_SIZE;
-use crate::physics::Kinematics;
-use crate::{CameraPosition, SimulationState};
+use crate::body::{Body, BodyId, OrbitalBodies, bodies_to_map};
+use crate::camera::{MAX_SCALE, MIN_SCALE, PickIndex, Projection, anchored_center};
+use crate::canvas::THEMES;
+use crate::constants::{AU, EARTH_MOON_DISTANCE, MOON_MASS, MOON_RADIUS, SPACE_SIZE};
+use crate::keybindings::{Action, PRESET_KEYS};
+use crate::physics::approach::ClosestApproach;
+use crate::physics::collisions::MergeCondition;
+use crate::physics::{Gravity, OrbitParameters, kepler_orbit};
+use crate::simulation::Simulation;
+use crate::{CameraPosition, INTEGRATOR_COUNT, SimulationState, integrator};
 use raylib::RaylibHandle;
+use raylib::color::Color;
 use raylib::consts::{KeyboardKey, MouseButton};
 
-/// Handle inputs, return if the window should be closed immediately.
-pub fn handle_input<'k>(
-    rl: &mut RaylibHandle,
+/// Change of the gravity exponent of a single `[` or `]` press
+const GRAVITY_EXPONENT_STEP: f64 = 0.1;
+
+/// Range of the gravity exponent, with a pull weakening with distance
+const GRAVITY_EXPONENT_RANGE: (f64, f64) = (1., 4.);
+
+/// Throttle distance of the tier 1 accelerations toggled by `X`, see
+/// [crate::physics::PhysicsConfig::tier1_throttle_distance]
+const TIER1_THROTTLE_DISTANCE: f64 = AU;
+
+/// Speedup change of a single `=` or `-` press
+const SPEEDUP_STEP: f64 = 1.5;
+
+/// Slowest speedup, a frame covers about 7 minutes
+const MIN_SPEEDUP: f64 = 1E-2;
+
+/// Fastest speedup, a frame covers over a millennium
+const MAX_SPEEDUP: f64 = 1E6;
+
+/// Index of the integrator after `index`, or before it going `backward`,
+/// wrapping around
+fn cycled_integrator(index: usize, backward: bool) -> usize {
+    match backward {
+        true => (index + INTEGRATOR_COUNT - 1) % INTEGRATOR_COUNT,
+        false => (index + 1) % INTEGRATOR_COUNT,
+    }
+}
+
+/// Speedup after `steps` presses of `=`, or of `-` when negative. The steps
+/// multiply the speedup, so it never reaches zero: reversing is done with
+/// `R`.
+fn stepped_speedup(speedup: f64, steps: i32) -> f64 {
+    (speedup * SPEEDUP_STEP.powi(steps)).clamp(MIN_SPEEDUP, MAX_SPEEDUP)
+}
+
+/// Speed the time up by `steps` presses of `=`, or down when negative. With
+/// the step of a frame set in seconds, it changes in the same ratios and
+/// bounds as the speedup would.
+fn step_time(simulation_state: &mut SimulationState, steps: i32) {
+    let base_dt = simulation_state.physics.base_dt;
+
+    match simulation_state.step_seconds.as_mut() {
+        Some(seconds) => *seconds = base_dt * stepped_speedup(*seconds / base_dt, steps),
+        None => simulation_state.speedup = stepped_speedup(simulation_state.speedup, steps),
+    }
+}
+
+/// A camera and time setting recalled with a number key
+struct Preset {
+    key: KeyboardKey,
+    /// Landmark followed by the camera, see [crate::Landmarks]
+    target: &'static str,
+    /// Distance shown between the target and the edges of the screen, in
+    /// meters
+    radius: f64,
+    speedup: f64,
+}
+
+/// The presets, edit this table to change the views
+const PRESETS: [Preset; 5] = [
+    // Whole system, up to the comet's aphelion
+    Preset {
+        key: PRESET_KEYS[0],
+        target: "sun",
+        radius: 36. * AU,
+        speedup: 5.,
+    },
+    // Inner planets
+    Preset {
+        key: PRESET_KEYS[1],
+        target: "sun",
+        radius: 1.8 * AU,
+        speedup: 1.,
+    },
+    // Earth and Moon
+    Preset {
+        key: PRESET_KEYS[2],
+        target: "earth",
+        radius: 2. * EARTH_MOON_DISTANCE,
+        speedup: 0.1,
+    },
+    // Haley's comet
+    Preset {
+        key: PRESET_KEYS[3],
+        target: "comet",
+        radius: 0.5 * AU,
+        speedup: 1.,
+    },
+    // Asteroid belt
+    Preset {
+        key: PRESET_KEYS[4],
+        target: "sun",
+        radius: 4. * AU,
+        speedup: 1.,
+    },
+];
+
+/// Move the camera and set the speed to the preset. When the target is not
+/// in the scene, or did not survive a collision, look at the origin instead.
+fn apply_preset(simulation_state: &mut SimulationState, bodies: &OrbitalBodies, preset: &Preset) {
+    simulation_state.camera_position = match simulation_state.landmarks.get(preset.target) {
+        Some(&id) if bodies.get_by_id(id).is_some() => CameraPosition::BodyRelative(id),
+        _ => CameraPosition::UniverseAbsolute((0., 0.)),
+    };
+
+    simulation_state.scale = (SPACE_SIZE / 2) as f64 / preset.radius;
+    simulation_state.speedup = preset.speedup;
+    if simulation_state.step_seconds.is_some() {
+        simulation_state.step_seconds = Some(preset.speedup * simulation_state.physics.base_dt);
+    }
+}
+
+/// Velocity added to the followed body by a kick, in m/s
+const IMPULSE_DELTA_V: f64 = 500.;
+
+/// Direction of the kick given with Shift and an arrow key, in universe space
+/// where `y` grows downward like on the screen
+fn impulse_direction(key: KeyboardKey) -> Option<((f64, f64), &'static str)> {
+    match key {
+        KeyboardKey::KEY_UP => Some(((0., -1.), "up")),
+        KeyboardKey::KEY_DOWN => Some(((0., 1.), "down")),
+        KeyboardKey::KEY_LEFT => Some(((-1., 0.), "left")),
+        KeyboardKey::KEY_RIGHT => Some(((1., 0.), "right")),
+        _ => None,
+    }
+}
+
+/// Kick the followed body in the direction, and measure the energy the
+/// drift is relative to again since the kick changed it
+fn apply_impulse(
+    simulation_state: &mut SimulationState,
+    simulation: &mut Simulation,
+    (direction, name): ((f64, f64), &str),
+) {
+    let followed = match simulation_state.camera_position {
+        CameraPosition::BodyRelative(id) => simulation.bodies.get_mut_by_id(id),
+        CameraPosition::UniverseAbsolute(_) => None,
+    };
+
+    let Some(body) = followed else {
+        simulation_state.post_message("Follow a body to kick it".to_string());
+        return;
+    };
+
+    body.velocity.0 += direction.0 * IMPULSE_DELTA_V;
+    body.velocity.1 += direction.1 * IMPULSE_DELTA_V;
+    let id = body.id();
+
+    simulation.rebaseline();
+    simulation_state.post_message(format!("Kicked body #{id} {name} by {IMPULSE_DELTA_V} m/s"));
+}
+
+/// Farthest a spawned body can be from a tier 0 body to orbit it, in pixels
+const SPAWN_REACH: f64 = 200.;
+
+/// Eccentricity change of the spawned orbit of a single wheel notch
+const SPAWN_ECCENTRICITY_STEP: f64 = 0.05;
+
+/// Most eccentric spawned orbit, which stays closed
+const MAX_SPAWN_ECCENTRICITY: f64 = 0.95;
+
+/// Mass of the spawned bodies, in Kg
+const SPAWN_MASS: f64 = MOON_MASS;
+
+/// Draw radius of the spawned bodies, in pixels
+const SPAWN_DRAW_RADIUS: f64 = 3.;
+
+/// A body being placed with the mouse, spawned when the button is released
+#[derive(Debug, Copy, Clone)]
+pub struct SpawnDraft {
+    /// Where the body appears, in universe space
+    pub position: (f64, f64),
+    /// Eccentricity of its orbit, changed with the wheel
+    pub eccentricity: f64,
+}
+
+impl SpawnDraft {
+    /// The tier 0 body the spawned body orbits: the nearest one within
+    /// [SPAWN_REACH], unless the body would appear inside it
+    pub fn reference<'a>(&self, bodies: &'a OrbitalBodies, scale: f64) -> Option<&'a Body> {
+        let (x, y) = self.position;
+        let distance = |body: &Body| {
+            let (bx, by) = body.pos();
+            ((x - bx).powf(2.) + (y - by).powf(2.)).sqrt()
+        };
+
+        bodies
+            .within_radius(self.position, SPAWN_REACH / scale)
+            .into_iter()
+            .filter_map(|id| bodies.tier0.get(&id))
+            .map(|body| (distance(body), body))
+            .filter(|(d, body)| *d > body.physical_radius)
+            .min_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map(|(_, body)| body)
+    }
+
+    /// The body to spawn. Near a tier 0 body, it is placed on an orbit of
+    /// the chosen eccentricity around it, with its periapsis along the x
+    /// axis; elsewhere it starts at rest. The orbit is taken under the
+    /// gravity constant `g`.
+    fn body(&self, bodies: &OrbitalBodies, scale: f64, g: f64) -> Body {
+        let mut body = Body::new(
+            SPAWN_MASS,
+            self.position,
+            MOON_RADIUS,
+            SPAWN_DRAW_RADIUS,
+            Color::SKYBLUE,
+            (0., 0.),
+            (0., 0.),
+        );
+
+        if let Some(reference) = self.reference(bodies, scale) {
+            let (px, py) = reference.pos();
+            let (dx, dy) = (self.position.0 - px, self.position.1 - py);
+            let distance = (dx.powf(2.) + dy.powf(2.)).sqrt();
+            let (e, theta) = (self.eccentricity, dy.atan2(dx));
+
+            // The semi-major axis putting the body right where it was placed
+            let a = distance * (1. + e * theta.cos()) / (1. - e.powf(2.));
+
+            kepler_orbit(OrbitParameters { a, e, theta }, &mut body, reference, g);
+        }
+
+        body
+    }
+}
+
+/// Spawn the body being placed, once the mouse button is released
+fn release_spawn(
+    rl: &RaylibHandle,
+    simulation_state: &mut SimulationState,
+    simulation: &mut Simulation,
+) {
+    if !rl.is_mouse_button_released(MouseButton::MOUSE_BUTTON_LEFT) {
+        return;
+    }
+
+    let Some(draft) = simulation_state.spawn_draft.take() else {
+        return;
+    };
+
+    let body = draft.body(
+        &simulation.bodies,
+        simulation_state.scale,
+        simulation.config.g,
+    );
+    let message = match draft.reference(&simulation.bodies, simulation_state.scale) {
+        Some(reference) => format!(
+            "Spawned body #{0} around #{1}, e = {2:.2}",
+            body.id(),
+            reference.id(),
+            draft.eccentricity
+        ),
+        None => format!("Spawned body #{0} at rest", body.id()),
+    };
+
+    simulation.bodies.append(OrbitalBodies::new(
+        bodies_to_map(vec![body]),
+        bodies_to_map(vec![]),
+    ));
+    simulation.rebaseline();
+    simulation_state.post_message(message);
+}
+
+/// Scale change of a single wheel notch
+const ZOOM_STEP: f64 = 1.1;
+
+/// Scale after a wheel movement of `mouse_wheel` notches, which may be
+/// fractional with smooth scrolling. Zooming in and out by the same amount
+/// are inverses of each other, and notches compound.
+fn zoomed_scale(scale: f64, mouse_wheel: f64) -> f64 {
+    (scale * ZOOM_STEP.powf(mouse_wheel)).clamp(MIN_SCALE, MAX_SCALE)
+}
+
+/// Apply a mouse wheel movement to the scale, keeping the universe point
+/// under the cursor at the same place on the screen. Zooming anywhere but the
+/// screen center stops following a body.
+fn zoom(
+    rl: &RaylibHandle,
     simulation_state: &mut SimulationState,
-    kin: &mut &'k Box<dyn Kinematics>,
     bodies: &OrbitalBodies,
-    kinematics: &'k [Box<dyn Kinematics>],
+    mouse_wheel: f64,
+) {
+    let old_scale = simulation_state.scale;
+
+    let new_scale = zoomed_scale(old_scale, mouse_wheel);
+
+    // Pinned to a bound, moving the camera would only accumulate float error
+    if new_scale == old_scale {
+        return;
+    }
+
+    let cursor = (rl.get_mouse_x(), rl.get_mouse_y());
+    let screen_center = (SPACE_SIZE / 2) as i32;
+
+    // The logarithmic projection zooms around the center of the screen
+    if cursor != (screen_center, screen_center) && simulation_state.projection == Projection::Linear
+    {
+        let center = simulation_state.get_universe_center(bodies);
+        simulation_state.camera_position = CameraPosition::UniverseAbsolute(anchored_center(
+            cursor,
+            screen_center,
+            center,
+            old_scale,
+            new_scale,
+        ));
+    }
+
+    simulation_state.scale = new_scale;
+}
+
+/// Track the closest approach of the followed body and the one picked with
+/// Ctrl + click, see [ClosestApproach]
+fn track_approach(simulation_state: &mut SimulationState, picked: Option<BodyId>) {
+    let message = match (&simulation_state.camera_position, picked) {
+        (&CameraPosition::BodyRelative(followed), Some(picked)) if followed != picked => {
+            simulation_state.approach = Some(ClosestApproach::new(followed, picked));
+            format!("Tracking the closest approach of #{followed} and #{picked}")
+        }
+        _ => "Follow a body, then Ctrl + click another to track their approach".to_string(),
+    };
+
+    simulation_state.post_message(message);
+}
+
+/// Change the gravity exponent by `step`, within [GRAVITY_EXPONENT_RANGE]
+fn step_gravity_exponent(simulation_state: &mut SimulationState, step: f64) {
+    let (min, max) = GRAVITY_EXPONENT_RANGE;
+
+    // Rounded, so that stepping back lands exactly on 2
+    let exponent = &mut simulation_state.physics.exponent;
+    *exponent = ((*exponent + step).clamp(min, max) * 10.).round() / 10.;
+}
+
+/// Handle inputs, return if the window should be closed immediately. The
+/// presets and the kicks have fixed keys, the other keys are looked up in
+/// the [crate::keybindings::KeyBindings].
+pub fn handle_input(
+    rl: &mut RaylibHandle,
+    simulation_state: &mut SimulationState,
+    simulation: &mut Simulation,
 ) -> bool {
+    release_spawn(rl, simulation_state, simulation);
+
+    let bodies = &simulation.bodies;
     let mouse_wheel = rl.get_mouse_wheel_move() as f64;
 
-    if mouse_wheel > 0. {
-        simulation_state.scale *= mouse_wheel * 1.1;
-    } else if mouse_wheel < 0. {
-        simulation_state.scale /= mouse_wheel.abs() * 1.1;
+    if let Some(draft) = simulation_state.spawn_draft.as_mut() {
+        draft.eccentricity = (draft.eccentricity + mouse_wheel * SPAWN_ECCENTRICITY_STEP)
+            .clamp(0., MAX_SPAWN_ECCENTRICITY);
+    } else if mouse_wheel != 0. {
+        zoom(rl, simulation_state, bodies, mouse_wheel);
     }
 
-    // Center to the selection position
-    if rl.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_LEFT) {
+    if simulation_state.spawn_mode && rl.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_LEFT) {
         let screen_position = (rl.get_mouse_x(), rl.get_mouse_y());
-        let universe_center = simulation_state.get_universe_center(&bodies);
-        let screen_center = (SPACE_SIZE / 2) as i32;
-
-        let mut set = false;
-        for (i, body) in bodies.tier0.iter() {
-            if click_in_body(
-                screen_position,
-                universe_center,
-                screen_center,
-                simulation_state.scale,
-                body,
-            ) {
-                simulation_state.camera_position = CameraPosition::BodyRelative(*i);
-                set = true;
-                break;
-            }
-        }
 
-        if !set {
-            let universe_center = screen_coords_to_universe(
-                screen_position,
-                simulation_state.scale,
-                universe_center,
-                screen_center,
-            );
+        simulation_state.spawn_draft = Some(SpawnDraft {
+            position: simulation_state.view(bodies).to_universe(screen_position),
+            eccentricity: 0.,
+        });
+    } else if rl.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_LEFT) {
+        // Center to the selection position
+        let screen_position = (rl.get_mouse_x(), rl.get_mouse_y());
+        let view = simulation_state.view(bodies);
+        let picked = PickIndex::new(bodies.tier0.values(), view).pick(screen_position);
 
-            simulation_state.camera_position = CameraPosition::UniverseAbsolute(universe_center);
+        let control = rl.is_key_down(KeyboardKey::KEY_LEFT_CONTROL)
+            || rl.is_key_down(KeyboardKey::KEY_RIGHT_CONTROL);
+
+        if control {
+            track_approach(simulation_state, picked.map(Body::id));
+        } else if let Some(body) = picked {
+            simulation_state.camera_position = CameraPosition::BodyRelative(body.id());
+        } else {
+            simulation_state.camera_position =
+                CameraPosition::UniverseAbsolute(view.to_universe(screen_position));
         }
     }
 
-    match rl.get_key_pressed() {
-        Some(KeyboardKey::KEY_K) => {
+    let key = rl.get_key_pressed();
+
+    if let Some(preset) = PRESETS.iter().find(|preset| Some(preset.key) == key) {
+        apply_preset(simulation_state, bodies, preset);
+    }
+
+    let shift =
+        rl.is_key_down(KeyboardKey::KEY_LEFT_SHIFT) || rl.is_key_down(KeyboardKey::KEY_RIGHT_SHIFT);
+    if shift && let Some(impulse) = key.and_then(impulse_direction) {
+        apply_impulse(simulation_state, simulation, impulse);
+        return false;
+    }
+
+    let action = key.and_then(|key| simulation_state.keybindings.action(key));
+
+    match action {
+        Some(Action::CycleIntegrator) => {
             simulation_state.kinematics_index =
-                (simulation_state.kinematics_index + 1) % kinematics.len();
-            *kin = &kinematics[simulation_state.kinematics_index];
+                cycled_integrator(simulation_state.kinematics_index, shift);
+            simulation.kinematics = integrator(simulation_state.kinematics_index);
         }
-        Some(KeyboardKey::KEY_C) => {
+        Some(Action::ToggleCollisions) => {
             simulation_state.compute_collisions = !simulation_state.compute_collisions;
         }
-        Some(KeyboardKey::KEY_Q) => {
+        Some(Action::ToggleMergeCondition) => {
+            simulation_state.merge_condition = match simulation_state.merge_condition {
+                MergeCondition::Overlap => MergeCondition::Approaching,
+                MergeCondition::Approaching => MergeCondition::Overlap,
+            };
+        }
+        Some(Action::ToggleExactGravity) => {
+            simulation_state.physics.gravity = match simulation_state.physics.gravity {
+                Gravity::Softened => Gravity::Exact,
+                Gravity::Exact => Gravity::Softened,
+            };
+        }
+        Some(Action::ToggleThrottle) => {
+            let physics = &mut simulation_state.physics;
+            physics.tier1_throttle_distance = match physics.tier1_throttle_distance {
+                Some(_) => None,
+                None => Some(TIER1_THROTTLE_DISTANCE),
+            };
+        }
+        Some(Action::ToggleFreezeTier1) => {
+            let physics = &mut simulation_state.physics;
+            physics.freeze_tier1 = !physics.freeze_tier1;
+        }
+        Some(Action::DecreaseExponent) => {
+            step_gravity_exponent(simulation_state, -GRAVITY_EXPONENT_STEP);
+        }
+        Some(Action::IncreaseExponent) => {
+            step_gravity_exponent(simulation_state, GRAVITY_EXPONENT_STEP);
+        }
+        Some(Action::CycleTheme) => {
+            simulation_state.theme_index = (simulation_state.theme_index + 1) % THEMES.len();
+        }
+        Some(Action::ToggleAccel) => {
+            simulation_state.show_accel = !simulation_state.show_accel;
+        }
+        Some(Action::ToggleTrailFrame) => {
+            let message = match (
+                simulation_state.frame_body,
+                &simulation_state.camera_position,
+            ) {
+                (None, CameraPosition::BodyRelative(id)) => {
+                    simulation_state.frame_body = Some(*id);
+                    format!("Trails relative to body #{id}")
+                }
+                (None, CameraPosition::UniverseAbsolute(_)) => {
+                    "Follow a body to draw the trails relative to it".to_string()
+                }
+                (Some(_), _) => {
+                    simulation_state.frame_body = None;
+                    "Trails relative to the universe".to_string()
+                }
+            };
+            simulation_state.post_message(message);
+        }
+        Some(Action::ToggleTier0Trails) => {
+            simulation_state.trails.tier0 = !simulation_state.trails.tier0;
+        }
+        Some(Action::ToggleTier1Trails) => {
+            simulation_state.trails.tier1 = !simulation_state.trails.tier1;
+        }
+        Some(Action::ToggleVirial) => {
+            simulation_state.show_virial = !simulation_state.show_virial;
+        }
+        Some(Action::ToggleDoppler) => {
+            simulation_state.doppler = !simulation_state.doppler;
+        }
+        Some(Action::ToggleGradient) => {
+            simulation_state.gradient = !simulation_state.gradient;
+        }
+        Some(Action::ToggleProjection) => {
+            simulation_state.projection = match simulation_state.projection {
+                Projection::Linear => Projection::Logarithmic,
+                Projection::Logarithmic => Projection::Linear,
+            };
+            simulation_state.post_message(match simulation_state.projection {
+                Projection::Linear => "Distances to scale".to_string(),
+                Projection::Logarithmic => "Distances compressed from the center".to_string(),
+            });
+        }
+        Some(Action::ToggleSubsample) => {
+            simulation_state.subsample = !simulation_state.subsample;
+            simulation_state.post_message(match simulation_state.subsample {
+                true => "Drawing fewer asteroids when zoomed out".to_string(),
+                false => "Drawing all the asteroids".to_string(),
+            });
+        }
+        Some(Action::ToggleHeatmap) => {
+            simulation_state.heatmap = !simulation_state.heatmap;
+            simulation_state.post_message(match simulation_state.heatmap {
+                true => "Drawing the density of the asteroids".to_string(),
+                false => "Drawing the asteroids".to_string(),
+            });
+        }
+        Some(Action::AddBinary) => {
+            simulation_state.binary_requested = true;
+        }
+        Some(Action::ToggleFixedSun) => {
+            simulation_state.fixed_sun = !simulation_state.fixed_sun;
+            simulation_state.post_message(match simulation_state.fixed_sun {
+                true => "Sun fixed in place".to_string(),
+                false => "Sun free to recoil".to_string(),
+            });
+        }
+        Some(Action::ToggleSpawnMode) => {
+            simulation_state.spawn_mode = !simulation_state.spawn_mode;
+            simulation_state.spawn_draft = None;
+            simulation_state.post_message(match simulation_state.spawn_mode {
+                true => "Click to spawn a body, scroll to change its eccentricity".to_string(),
+                false => "Click to follow a body".to_string(),
+            });
+        }
+        Some(Action::ResetApproach) => {
+            if let Some(approach) = simulation_state.approach.as_mut() {
+                approach.reset();
+                simulation_state.post_message("Closest approach reset".to_string());
+            }
+        }
+        Some(Action::CycleCameraPolicy) => {
+            simulation_state.camera_policy = simulation_state.camera_policy.next();
+            simulation_state.auto_target = None;
+            simulation_state.big_collision = None;
+            simulation_state.post_message(format!(
+                "Camera policy: {0}",
+                simulation_state.camera_policy.name()
+            ));
+        }
+        Some(Action::WatchBinding) => {
+            simulation_state.watch_requested = true;
+        }
+        Some(Action::PrintPeriods) => {
+            simulation_state.periods_requested = true;
+        }
+        Some(Action::ScrubBack) => {
+            simulation_state.scrub_requested -= 1;
+        }
+        Some(Action::ScrubForward) => {
+            simulation_state.scrub_requested += 1;
+        }
+        Some(Action::Save) => {
+            simulation_state.save_requested = true;
+        }
+        Some(Action::Load) => {
+            simulation_state.load_requested = true;
+        }
+        Some(Action::Inject) => {
+            simulation_state.inject_requested = true;
+        }
+        Some(Action::Export) => {
+            simulation_state.export_requested = true;
+        }
+        Some(Action::ToggleCometTails) => {
+            simulation.comet_tails = !simulation.comet_tails;
+        }
+        Some(Action::Reset) => {
+            simulation_state.reset_requested = true;
+        }
+        Some(Action::Quit) => {
             return true;
         }
-        Some(KeyboardKey::KEY_P) => {
+        Some(Action::TogglePause) => {
             simulation_state.paused = !simulation_state.paused;
         }
-        Some(KeyboardKey::KEY_R) => {
+        Some(Action::Reverse) => {
             simulation_state.dt_factor = -1. * simulation_state.dt_factor;
         }
-        Some(KeyboardKey::KEY_EQUAL) => {
-            simulation_state.speedup += 0.1;
+        Some(Action::SpeedUp) => {
+            step_time(simulation_state, 1);
+        }
+        Some(Action::SlowDown) => {
+            step_time(simulation_state, -1);
         }
-        Some(KeyboardKey::KEY_MINUS) => {
-            simulation_state.speedup -= 0.1;
+        Some(Action::ToggleStepSeconds) => {
+            let base_dt = simulation_state.physics.base_dt;
+            simulation_state.step_seconds = match simulation_state.step_seconds {
+                Some(seconds) => {
+                    simulation_state.speedup = (seconds / base_dt).clamp(MIN_SPEEDUP, MAX_SPEEDUP);
+                    None
+                }
+                None => Some((simulation_state.speedup * base_dt).round().max(1.)),
+            };
+            simulation_state.post_message(match simulation_state.step_seconds {
+                Some(seconds) => format!("Frames step {seconds} s"),
+                None => "Frames step by the speedup".to_string(),
+            });
         }
         _ => (),
     };
 
     false
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::body::{Body, OrbitalBodies, bodies_to_map};
+    use crate::camera::{MAX_SCALE, MIN_SCALE};
+    use crate::constants::{
+        AU, EARTH_MASS, EARTH_SUN_VELOCITY, SUN_EARTH_DISTANCE, SUN_MASS, SUN_RADIUS,
+    };
+    use crate::input::{
+        IMPULSE_DELTA_V, MAX_SPEEDUP, MIN_SPEEDUP, SPAWN_MASS, SpawnDraft, apply_impulse,
+        cycled_integrator, impulse_direction, step_time, stepped_speedup, zoomed_scale,
+    };
+    use crate::physics::G;
+    use crate::physics::leapfrog::Leapfrog;
+    use crate::simulation::Simulation;
+    use crate::{CameraPosition, INTEGRATOR_COUNT, SimulationState};
+    use raylib::color::Color;
+    use raylib::consts::KeyboardKey;
+
+    #[test]
+    fn test_zoom_round_trip() {
+        let original = 1e-9;
+
+        for notches in [1., 3., 0.5, 2.25] {
+            let mut scale = original;
+            for _ in 0..10 {
+                scale = zoomed_scale(scale, notches);
+            }
+            for _ in 0..10 {
+                scale = zoomed_scale(scale, -notches);
+            }
+
+            // Back to the start, up to the rounding of the products
+            assert!((scale - original).abs() / original < 1e-12);
+        }
+
+        // Several notches at once compound like single ones
+        let single = (0..3).fold(original, |scale, _| zoomed_scale(scale, 1.));
+        assert!((zoomed_scale(original, 3.) - single).abs() / single < 1e-12);
+
+        assert_eq!(zoomed_scale(MAX_SCALE, 1.), MAX_SCALE);
+        assert_eq!(zoomed_scale(MIN_SCALE, -1.), MIN_SCALE);
+    }
+
+    #[test]
+    fn test_speedup_steps() {
+        // Each press multiplies, a press back undoes it
+        let faster = stepped_speedup(1., 1);
+        assert_eq!(faster, 1.5);
+        assert!((stepped_speedup(faster, -1) - 1.).abs() < 1e-12);
+
+        // Slowing down stops at the bound instead of crossing zero
+        let mut speedup = 1.;
+        for _ in 0..100 {
+            speedup = stepped_speedup(speedup, -1);
+            assert!(speedup > 0.);
+        }
+        assert_eq!(speedup, MIN_SPEEDUP);
+
+        assert_eq!(stepped_speedup(MAX_SPEEDUP, 1), MAX_SPEEDUP);
+    }
+
+    #[test]
+    fn test_step_time_in_seconds() {
+        let mut simulation_state = SimulationState {
+            step_seconds: Some(3600.),
+            ..Default::default()
+        };
+
+        step_time(&mut simulation_state, 1);
+        assert_eq!(simulation_state.step_seconds, Some(5400.));
+        assert_eq!(simulation_state.dt(), 5400.);
+        assert_eq!(simulation_state.speedup, 1.);
+
+        simulation_state.step_seconds = None;
+        step_time(&mut simulation_state, 1);
+        assert_eq!(simulation_state.speedup, 1.5);
+    }
+
+    #[test]
+    fn test_cycled_integrator() {
+        assert_eq!(cycled_integrator(0, false), 1);
+        assert_eq!(cycled_integrator(1, true), 0);
+        assert_eq!(cycled_integrator(0, true), INTEGRATOR_COUNT - 1);
+        assert_eq!(cycled_integrator(INTEGRATOR_COUNT - 1, false), 0);
+    }
+
+    #[test]
+    fn test_impulse() {
+        let sun = Body::new(
+            SUN_MASS,
+            (0., 0.),
+            1.,
+            1.,
+            Color::YELLOW,
+            (0., 0.),
+            (0., 0.),
+        );
+        let earth = Body::new(
+            EARTH_MASS,
+            (0., SUN_EARTH_DISTANCE),
+            1.,
+            1.,
+            Color::BLUE,
+            (EARTH_SUN_VELOCITY, 0.),
+            (0., 0.),
+        );
+        let earth_id = earth.id();
+
+        let mut simulation = Simulation::new(
+            OrbitalBodies::new(bodies_to_map(vec![sun, earth]), bodies_to_map(vec![])),
+            Box::new(Leapfrog),
+        );
+        let mut simulation_state = SimulationState {
+            camera_position: CameraPosition::BodyRelative(earth_id),
+            ..Default::default()
+        };
+
+        let before = simulation.bodies.get_by_id(earth_id).unwrap().velocity;
+        let up = impulse_direction(KeyboardKey::KEY_UP).unwrap();
+        apply_impulse(&mut simulation_state, &mut simulation, up);
+
+        let after = simulation.bodies.get_by_id(earth_id).unwrap().velocity;
+        // Within the tiny step measuring the energy again
+        assert!((after.0 - before.0).abs() < 1e-3);
+        assert!((after.1 - (before.1 - IMPULSE_DELTA_V)).abs() < 1e-3);
+
+        // The drift is measured from the kicked orbit
+        assert_eq!(simulation.diagnostics().energy_drift, 0.);
+        assert!(simulation_state.message.is_some());
+    }
+
+    #[test]
+    fn test_spawned_body() {
+        let sun = Body::new(
+            SUN_MASS,
+            (0., 0.),
+            SUN_RADIUS,
+            1.,
+            Color::YELLOW,
+            (0., 0.),
+            (0., 0.),
+        );
+        let bodies = OrbitalBodies::new(bodies_to_map(vec![sun]), bodies_to_map(vec![]));
+
+        // A hundred pixels per AU, the sun is within reach
+        let scale = 100. / AU;
+        let circular = SpawnDraft {
+            position: (0., -AU),
+            eccentricity: 0.,
+        };
+        let body = circular.body(&bodies, scale, G);
+
+        let expected_speed = (G * (SUN_MASS + SPAWN_MASS) / AU).sqrt();
+        assert!((body.pos().0).abs() < 1e-3 && (body.pos().1 + AU).abs() < 1e-3);
+        assert!((body.actual_velocity() - expected_speed).abs() / expected_speed < 1e-9);
+
+        // At periapsis, an eccentric orbit is faster
+        let eccentric = SpawnDraft {
+            position: (AU, 0.),
+            eccentricity: 0.5,
+        };
+        let body = eccentric.body(&bodies, scale, G);
+        assert!((body.pos().0 - AU).abs() < 1e-3);
+        assert!((body.actual_velocity() / expected_speed - 1.5_f64.sqrt()).abs() < 1e-9);
+
+        // Out of reach, the body starts at rest
+        let far = SpawnDraft {
+            position: (10. * AU, 0.),
+            eccentricity: 0.5,
+        };
+        assert!(far.reference(&bodies, scale).is_none());
+        assert_eq!(far.body(&bodies, scale, G).velocity, (0., 0.));
+    }
+}
@@ -0,0 +1,433 @@
+use raylib::consts::KeyboardKey;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// File the key bindings are read from at startup, in the working directory
+pub const KEYBINDINGS_PATH: &str = "keybindings.toml";
+
+/// Keys of the camera presets, handled before the bindings so that they
+/// cannot be bound. The kicks only take Shift + arrow, leaving the arrows
+/// free for the scrubbing.
+pub const PRESET_KEYS: [KeyboardKey; 5] = [
+    KeyboardKey::KEY_ONE,
+    KeyboardKey::KEY_TWO,
+    KeyboardKey::KEY_THREE,
+    KeyboardKey::KEY_FOUR,
+    KeyboardKey::KEY_FIVE,
+];
+
+/// What a key press does, see [KeyBindings]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Action {
+    Quit,
+    TogglePause,
+    Reverse,
+    SpeedUp,
+    SlowDown,
+    ToggleStepSeconds,
+    CycleIntegrator,
+    ToggleCollisions,
+    ToggleMergeCondition,
+    ToggleExactGravity,
+    ToggleThrottle,
+    ToggleFreezeTier1,
+    DecreaseExponent,
+    IncreaseExponent,
+    CycleTheme,
+    ToggleAccel,
+    ToggleTrailFrame,
+    ToggleTier0Trails,
+    ToggleTier1Trails,
+    ToggleVirial,
+    ToggleDoppler,
+    ToggleGradient,
+    ToggleSubsample,
+    ToggleHeatmap,
+    ToggleProjection,
+    AddBinary,
+    ToggleFixedSun,
+    ToggleSpawnMode,
+    WatchBinding,
+    ResetApproach,
+    CycleCameraPolicy,
+    PrintPeriods,
+    ScrubBack,
+    ScrubForward,
+    Save,
+    Load,
+    Inject,
+    Export,
+    ToggleCometTails,
+    Reset,
+}
+
+/// Every action, with its name in the bindings file and its default key
+const ACTIONS: [(Action, &str, KeyboardKey); 40] = [
+    (Action::Quit, "quit", KeyboardKey::KEY_Q),
+    (Action::TogglePause, "toggle_pause", KeyboardKey::KEY_P),
+    (Action::Reverse, "reverse", KeyboardKey::KEY_R),
+    (Action::SpeedUp, "speed_up", KeyboardKey::KEY_EQUAL),
+    (Action::SlowDown, "slow_down", KeyboardKey::KEY_MINUS),
+    (
+        Action::ToggleStepSeconds,
+        "toggle_step_seconds",
+        KeyboardKey::KEY_COMMA,
+    ),
+    (
+        Action::CycleIntegrator,
+        "cycle_integrator",
+        KeyboardKey::KEY_K,
+    ),
+    (
+        Action::ToggleCollisions,
+        "toggle_collisions",
+        KeyboardKey::KEY_C,
+    ),
+    (
+        Action::ToggleMergeCondition,
+        "toggle_merge_condition",
+        KeyboardKey::KEY_M,
+    ),
+    (
+        Action::ToggleExactGravity,
+        "toggle_exact_gravity",
+        KeyboardKey::KEY_G,
+    ),
+    (
+        Action::ToggleThrottle,
+        "toggle_throttle",
+        KeyboardKey::KEY_X,
+    ),
+    (
+        Action::ToggleFreezeTier1,
+        "toggle_freeze_tier1",
+        KeyboardKey::KEY_PERIOD,
+    ),
+    (
+        Action::DecreaseExponent,
+        "decrease_exponent",
+        KeyboardKey::KEY_LEFT_BRACKET,
+    ),
+    (
+        Action::IncreaseExponent,
+        "increase_exponent",
+        KeyboardKey::KEY_RIGHT_BRACKET,
+    ),
+    (Action::CycleTheme, "cycle_theme", KeyboardKey::KEY_T),
+    (Action::ToggleAccel, "toggle_accel", KeyboardKey::KEY_A),
+    (
+        Action::ToggleTrailFrame,
+        "toggle_trail_frame",
+        KeyboardKey::KEY_E,
+    ),
+    (
+        Action::ToggleTier0Trails,
+        "toggle_tier0_trails",
+        KeyboardKey::KEY_Y,
+    ),
+    (
+        Action::ToggleTier1Trails,
+        "toggle_tier1_trails",
+        KeyboardKey::KEY_U,
+    ),
+    (Action::ToggleVirial, "toggle_virial", KeyboardKey::KEY_V),
+    (Action::ToggleDoppler, "toggle_doppler", KeyboardKey::KEY_D),
+    (
+        Action::ToggleGradient,
+        "toggle_gradient",
+        KeyboardKey::KEY_H,
+    ),
+    (
+        Action::ToggleSubsample,
+        "toggle_subsample",
+        KeyboardKey::KEY_J,
+    ),
+    (
+        Action::ToggleHeatmap,
+        "toggle_heatmap",
+        KeyboardKey::KEY_APOSTROPHE,
+    ),
+    (
+        Action::ToggleProjection,
+        "toggle_projection",
+        KeyboardKey::KEY_Z,
+    ),
+    (Action::AddBinary, "add_binary", KeyboardKey::KEY_B),
+    (
+        Action::ToggleFixedSun,
+        "toggle_fixed_sun",
+        KeyboardKey::KEY_F,
+    ),
+    (
+        Action::ToggleSpawnMode,
+        "toggle_spawn_mode",
+        KeyboardKey::KEY_N,
+    ),
+    (Action::WatchBinding, "watch_binding", KeyboardKey::KEY_W),
+    (
+        Action::ResetApproach,
+        "reset_approach",
+        KeyboardKey::KEY_BACKSPACE,
+    ),
+    (
+        Action::CycleCameraPolicy,
+        "cycle_camera_policy",
+        KeyboardKey::KEY_SEMICOLON,
+    ),
+    (Action::PrintPeriods, "print_periods", KeyboardKey::KEY_O),
+    (Action::ScrubBack, "scrub_back", KeyboardKey::KEY_LEFT),
+    (
+        Action::ScrubForward,
+        "scrub_forward",
+        KeyboardKey::KEY_RIGHT,
+    ),
+    (Action::Save, "save", KeyboardKey::KEY_S),
+    (Action::Load, "load", KeyboardKey::KEY_L),
+    (Action::Inject, "inject", KeyboardKey::KEY_I),
+    (Action::Export, "export", KeyboardKey::KEY_F6),
+    (
+        Action::ToggleCometTails,
+        "toggle_comet_tails",
+        KeyboardKey::KEY_F7,
+    ),
+    (Action::Reset, "reset", KeyboardKey::KEY_F5),
+];
+
+/// Keys the bindings file can name, besides the letters, the digits and the
+/// function keys
+const NAMED_KEYS: [(&str, KeyboardKey); 20] = [
+    ("=", KeyboardKey::KEY_EQUAL),
+    ("-", KeyboardKey::KEY_MINUS),
+    ("[", KeyboardKey::KEY_LEFT_BRACKET),
+    ("]", KeyboardKey::KEY_RIGHT_BRACKET),
+    (",", KeyboardKey::KEY_COMMA),
+    (".", KeyboardKey::KEY_PERIOD),
+    ("/", KeyboardKey::KEY_SLASH),
+    (";", KeyboardKey::KEY_SEMICOLON),
+    ("'", KeyboardKey::KEY_APOSTROPHE),
+    ("`", KeyboardKey::KEY_GRAVE),
+    ("space", KeyboardKey::KEY_SPACE),
+    ("enter", KeyboardKey::KEY_ENTER),
+    ("tab", KeyboardKey::KEY_TAB),
+    ("backspace", KeyboardKey::KEY_BACKSPACE),
+    ("insert", KeyboardKey::KEY_INSERT),
+    ("delete", KeyboardKey::KEY_DELETE),
+    ("home", KeyboardKey::KEY_HOME),
+    ("end", KeyboardKey::KEY_END),
+    ("left", KeyboardKey::KEY_LEFT),
+    ("right", KeyboardKey::KEY_RIGHT),
+];
+
+const LETTER_KEYS: [KeyboardKey; 26] = [
+    KeyboardKey::KEY_A,
+    KeyboardKey::KEY_B,
+    KeyboardKey::KEY_C,
+    KeyboardKey::KEY_D,
+    KeyboardKey::KEY_E,
+    KeyboardKey::KEY_F,
+    KeyboardKey::KEY_G,
+    KeyboardKey::KEY_H,
+    KeyboardKey::KEY_I,
+    KeyboardKey::KEY_J,
+    KeyboardKey::KEY_K,
+    KeyboardKey::KEY_L,
+    KeyboardKey::KEY_M,
+    KeyboardKey::KEY_N,
+    KeyboardKey::KEY_O,
+    KeyboardKey::KEY_P,
+    KeyboardKey::KEY_Q,
+    KeyboardKey::KEY_R,
+    KeyboardKey::KEY_S,
+    KeyboardKey::KEY_T,
+    KeyboardKey::KEY_U,
+    KeyboardKey::KEY_V,
+    KeyboardKey::KEY_W,
+    KeyboardKey::KEY_X,
+    KeyboardKey::KEY_Y,
+    KeyboardKey::KEY_Z,
+];
+
+const DIGIT_KEYS: [KeyboardKey; 10] = [
+    KeyboardKey::KEY_ZERO,
+    KeyboardKey::KEY_ONE,
+    KeyboardKey::KEY_TWO,
+    KeyboardKey::KEY_THREE,
+    KeyboardKey::KEY_FOUR,
+    KeyboardKey::KEY_FIVE,
+    KeyboardKey::KEY_SIX,
+    KeyboardKey::KEY_SEVEN,
+    KeyboardKey::KEY_EIGHT,
+    KeyboardKey::KEY_NINE,
+];
+
+const FUNCTION_KEYS: [KeyboardKey; 12] = [
+    KeyboardKey::KEY_F1,
+    KeyboardKey::KEY_F2,
+    KeyboardKey::KEY_F3,
+    KeyboardKey::KEY_F4,
+    KeyboardKey::KEY_F5,
+    KeyboardKey::KEY_F6,
+    KeyboardKey::KEY_F7,
+    KeyboardKey::KEY_F8,
+    KeyboardKey::KEY_F9,
+    KeyboardKey::KEY_F10,
+    KeyboardKey::KEY_F11,
+    KeyboardKey::KEY_F12,
+];
+
+/// Parse a key name, case insensitive: a letter, a digit, a function key
+/// such as `F5`, or one of [NAMED_KEYS]
+pub fn parse_key(name: &str) -> Option<KeyboardKey> {
+    let name = name.to_lowercase();
+
+    if let [c] = name.as_bytes() {
+        match c {
+            b'a'..=b'z' => return Some(LETTER_KEYS[(c - b'a') as usize]),
+            b'0'..=b'9' => return Some(DIGIT_KEYS[(c - b'0') as usize]),
+            _ => {}
+        }
+    }
+
+    if let Some(number) = name.strip_prefix('f')
+        && let Ok(number @ 1..=12) = number.parse::<usize>()
+    {
+        return Some(FUNCTION_KEYS[number - 1]);
+    }
+
+    NAMED_KEYS
+        .iter()
+        .find(|(known, _)| *known == name)
+        .map(|(_, key)| *key)
+}
+
+/// The key of each [Action]
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyBindings {
+    keys: HashMap<Action, KeyboardKey>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            keys: ACTIONS
+                .iter()
+                .map(|(action, _, key)| (*action, *key))
+                .collect(),
+        }
+    }
+}
+
+impl KeyBindings {
+    /// Parse bindings of the form `action_name = "key"`, one per line, as in
+    /// a flat TOML file, see [parse_key] for the key names. Empty lines, `#`
+    /// comments and section headers are skipped. The actions left out keep
+    /// their default key, and no key can be bound to two actions nor to a
+    /// camera preset, see [PRESET_KEYS].
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let mut bindings = Self::default();
+
+        for (index, line) in content.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() || line.starts_with('[') {
+                continue;
+            }
+
+            let error = |message: String| format!("line {0}: {message}", index + 1);
+
+            let Some((name, key)) = line.split_once('=') else {
+                return Err(error(format!("expected `action = \"key\"`, got `{line}`")));
+            };
+            let (name, key) = (name.trim(), key.trim().trim_matches('"'));
+
+            let (action, _, _) = ACTIONS
+                .iter()
+                .find(|(_, known, _)| *known == name)
+                .ok_or_else(|| error(format!("unknown action `{name}`")))?;
+            let key = match parse_key(key) {
+                Some(parsed) if PRESET_KEYS.contains(&parsed) => {
+                    return Err(error(format!("`{key}` is taken by a camera preset")));
+                }
+                Some(parsed) => parsed,
+                None => return Err(error(format!("unknown key `{key}`"))),
+            };
+
+            bindings.keys.insert(*action, key);
+        }
+
+        let mut bound = HashMap::new();
+        for (action, name, _) in ACTIONS {
+            if let Some(other) = bound.insert(bindings.keys[&action], name) {
+                return Err(format!("`{other}` and `{name}` are bound to the same key"));
+            }
+        }
+
+        Ok(bindings)
+    }
+
+    /// Read the bindings from the file at `path`, the defaults when there is
+    /// none
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        match std::fs::read_to_string(path) {
+            Ok(content) => Self::parse(&content),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    /// The action bound to the key, if any
+    pub fn action(&self, key: KeyboardKey) -> Option<Action> {
+        self.keys
+            .iter()
+            .find(|(_, bound)| **bound == key)
+            .map(|(action, _)| *action)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::keybindings::{Action, KeyBindings, parse_key};
+    use raylib::consts::KeyboardKey;
+
+    #[test]
+    fn test_parse_keybindings() {
+        assert_eq!(parse_key("k"), Some(KeyboardKey::KEY_K));
+        assert_eq!(parse_key("7"), Some(KeyboardKey::KEY_SEVEN));
+        assert_eq!(parse_key("F12"), Some(KeyboardKey::KEY_F12));
+        assert_eq!(parse_key("Space"), Some(KeyboardKey::KEY_SPACE));
+        assert_eq!(parse_key("F13"), None);
+        assert_eq!(parse_key("kk"), None);
+
+        let defaults = KeyBindings::default();
+        assert_eq!(
+            defaults.action(KeyboardKey::KEY_K),
+            Some(Action::CycleIntegrator)
+        );
+        assert_eq!(defaults.action(KeyboardKey::KEY_SLASH), None);
+        assert_eq!(KeyBindings::parse("# Nothing changed\n"), Ok(defaults));
+
+        let bindings = KeyBindings::parse(
+            "[keys]\ntoggle_pause = \"space\" # Easier to reach\nquit = \"P\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            bindings.action(KeyboardKey::KEY_SPACE),
+            Some(Action::TogglePause)
+        );
+        assert_eq!(bindings.action(KeyboardKey::KEY_P), Some(Action::Quit));
+        assert_eq!(bindings.action(KeyboardKey::KEY_Q), None);
+        assert_eq!(
+            bindings.action(KeyboardKey::KEY_K),
+            Some(Action::CycleIntegrator)
+        );
+
+        // P is still the default of the pause
+        assert!(KeyBindings::parse("quit = \"P\"").is_err());
+
+        // The presets would fire along with the action
+        assert!(KeyBindings::parse("quit = \"3\"").is_err());
+        assert!(KeyBindings::parse("quit = \"6\"").is_ok());
+        assert!(KeyBindings::parse("fly = \"Z\"").is_err());
+        assert!(KeyBindings::parse("quit = \"F42\"").is_err());
+        assert!(KeyBindings::parse("quit").is_err());
+    }
+}
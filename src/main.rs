@@ -2,39 +2,212 @@ mod body;
 mod camera;
 mod canvas;
 mod constants;
+mod history;
 mod input;
+mod keybindings;
 mod physics;
+mod recording;
+mod scene;
+mod simulation;
 
-use crate::body::{Body, BodyId, OrbitalBodies, bodies_to_map, create_asteroid_belt};
-use crate::camera::draw_universe_relative;
-use crate::canvas::{HudParams, draw_hud};
-use crate::constants::{SPACE_SIZE, SUN_EARTH_DISTANCE, SUN_MASS};
-use crate::input::handle_input;
-use crate::physics::Kinematics;
-use crate::physics::collisions::handle_collisions;
+use crate::body::{
+    BeltParameters, Body, BodyId, DrawRadiusPolicy, EccentricityDistribution, OrbitalBodies,
+    TrailSampling, bodies_to_map, create_asteroid_belt, create_binary, reset_ids,
+};
+use crate::camera::{
+    BodyView, Doppler, HEATMAP_CELL_PIXELS, MAX_SCALE, MIN_SCALE, Projection, TrailToggles,
+    TrailView, View, draw_accel_vectors, draw_analytic_orbit, draw_follow_marker,
+    draw_spawn_preview, draw_universe_relative, fit_scale,
+};
+use crate::canvas::{HudParams, J2000_DAY, THEMES, Theme, draw_body_info, draw_hud, parse_date};
+use crate::constants::{SECONDS_PER_DAY, SPACE_SIZE, SUN_EARTH_DISTANCE, SUN_MASS};
+use crate::history::SnapshotHistory;
+use crate::input::{SpawnDraft, handle_input};
+use crate::keybindings::{KEYBINDINGS_PATH, KeyBindings};
+use crate::physics::approach::ClosestApproach;
+use crate::physics::apsides::ApsisTracker;
+use crate::physics::binding::{BindingTransition, BindingWatcher};
+use crate::physics::collisions::{CollisionEvent, MergeCondition};
 use crate::physics::euler::Euler;
 use crate::physics::leapfrog::{Leapfrog, LeapfrogKDK};
+use crate::physics::orbit::tisserand;
+use crate::physics::periods::{PeriodAnalyzer, PeriodEstimate};
+use crate::physics::tiered::Tiered;
+use crate::physics::{G, Kinematics, PhysicsConfig, dominant_attractor};
+use crate::recording::FrameRecorder;
+use crate::scene::{
+    HorizonsOptions, SavedCamera, SavedTarget, load_bodies, load_horizons, load_scene, save_bodies,
+    save_scene,
+};
+use crate::simulation::{Divergence, Simulation};
 use constants::{
     AU, EARTH_MASS, EARTH_MOON_DISTANCE, EARTH_RADIUS, EARTH_SUN_VELOCITY, HALEYS_COMET_MASS,
-    HALEYS_COMET_VELOCITY, HALEYS_RADIUS, MARS_MASS, MARS_RADIUS, MARS_VELOCITY,
-    MOON_EARTH_VELOCITY, MOON_MASS, MOON_RADIUS, SUN_HALEY_DISTANCE, SUN_MARS_DISTANCE, SUN_RADIUS,
+    HALEYS_COMET_VELOCITY, HALEYS_MASS_LOSS_RATE, HALEYS_RADIUS, MARS_MASS, MARS_RADIUS,
+    MARS_VELOCITY, MOON_EARTH_VELOCITY, MOON_MASS, MOON_RADIUS, SUN_HALEY_DISTANCE,
+    SUN_MARS_DISTANCE, SUN_RADIUS,
 };
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use raylib::prelude::*;
+use std::collections::HashMap;
+use std::ops::ControlFlow;
+use std::sync::mpsc;
 use std::time::Instant;
 
+/// Mass of the heavier star of the binaries added with `B`, in Kg
+const BINARY_PRIMARY_MASS: f64 = SUN_MASS;
+
+/// Mass of the lighter star of the binaries, relative to the heavier one
+const BINARY_MASS_RATIO: f64 = 0.5;
+
+/// Distance between the stars of the binaries, in meters
+const BINARY_SEPARATION: f64 = AU / 2.;
+
+/// Most memory used by the snapshots of the rewind history, in bytes
+const HISTORY_MAX_BYTES: usize = 256 * 1024 * 1024;
+
+/// Number of integrators cycled through with `K`
+const INTEGRATOR_COUNT: usize = 4;
+
+/// The integrator at `index` in the order they are cycled through
+fn integrator(index: usize) -> Box<dyn Kinematics> {
+    match index % INTEGRATOR_COUNT {
+        0 => Box::new(Leapfrog),
+        1 => Box::new(LeapfrogKDK),
+        2 => Box::new(Euler),
+        _ => Box::new(Tiered::new(
+            Box::new(LeapfrogKDK),
+            "Leapfrog (KDK) / Symplectic Euler",
+        )),
+    }
+}
+
 enum CameraPosition {
     UniverseAbsolute((f64, f64)),
     BodyRelative(BodyId),
 }
 
+/// What the camera follows on its own, for unattended runs. It only moves
+/// when its target changes, the user can look elsewhere in between.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+enum CameraPolicy {
+    /// Left to the user
+    #[default]
+    Stay,
+    /// The most massive body, followed anew whenever another one takes over
+    MostMassive,
+    /// The survivor of the most energetic merge of the last few seconds
+    BigCollision,
+}
+
+/// Wall-clock seconds a merge followed by [CameraPolicy::BigCollision] takes
+/// precedence over less energetic ones
+const BIG_COLLISION_SECONDS: f64 = 10.;
+
+impl CameraPolicy {
+    const ALL: [CameraPolicy; 3] = [
+        CameraPolicy::Stay,
+        CameraPolicy::MostMassive,
+        CameraPolicy::BigCollision,
+    ];
+
+    /// Name of the policy, as given to `--camera-policy`
+    fn name(self) -> &'static str {
+        match self {
+            CameraPolicy::Stay => "stay",
+            CameraPolicy::MostMassive => "most-massive",
+            CameraPolicy::BigCollision => "collision",
+        }
+    }
+
+    fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|&policy| policy == self).unwrap();
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+}
+
+/// Frames drawn per second, each one simulating [SimulationState::dt]
+const TARGET_FPS: u32 = 60;
+
+/// Steps a frame runs at most to catch up with the wall clock, by default
+const MAX_STEPS_PER_FRAME: usize = 4;
+
+/// Frames in a row at the step cap before the HUD shows the simulation is
+/// lagging
+const LAGGING_FRAMES: usize = 10;
+
+/// Bodies of the scene the camera presets can point at, by name
+pub type Landmarks = HashMap<&'static str, BodyId>;
+
 pub struct SimulationState {
     paused: bool,
     compute_collisions: bool,
+    merge_condition: MergeCondition,
+    /// Physics handed to the simulation every frame
+    physics: PhysicsConfig,
     scale: f64,
     camera_position: CameraPosition,
     dt_factor: f64,
     kinematics_index: usize,
+    theme_index: usize,
+    show_accel: bool,
+    /// Clicking spawns a body instead of following one
+    spawn_mode: bool,
+    spawn_draft: Option<SpawnDraft>,
+    trails: TrailToggles,
+    /// Body the trails are drawn relative to, the universe when `None`
+    frame_body: Option<BodyId>,
+    show_virial: bool,
+    doppler: bool,
+    /// Radial speed of the most saturated Doppler tint, in m/s
+    doppler_max_speed: f64,
+    /// Shade the bodies with a radial gradient
+    gradient: bool,
+    /// Only draw some of the asteroids when zoomed out
+    subsample: bool,
+    /// Draw the density of the asteroids instead of the asteroids
+    heatmap: bool,
+    /// Side of the cells of the heatmap, in pixels
+    heatmap_cell: i32,
+    projection: Projection,
+    /// Pin the most massive body in place, instead of letting it recoil
+    /// from the pull of the others
+    fixed_sun: bool,
     speedup: f64,
+    /// Simulated seconds covered by a frame, set directly instead of through
+    /// the speedup
+    step_seconds: Option<f64>,
+    /// Steps a slow frame runs at most to catch up, past which the
+    /// simulation falls behind the wall clock
+    max_steps_per_frame: usize,
+    /// Wall-clock seconds not simulated yet
+    frame_backlog: f64,
+    /// Frames in a row that hit [SimulationState::max_steps_per_frame]
+    saturated_frames: usize,
+    reset_requested: bool,
+    inject_requested: bool,
+    binary_requested: bool,
+    periods_requested: bool,
+    watch_requested: bool,
+    /// Closest approach of a pair of bodies, picked with Ctrl + click
+    approach: Option<ClosestApproach>,
+    camera_policy: CameraPolicy,
+    /// Body the camera policy last followed
+    auto_target: Option<BodyId>,
+    /// Energy of the merge followed by [CameraPolicy::BigCollision], and
+    /// when it happened
+    big_collision: Option<(f64, Instant)>,
+    save_requested: bool,
+    load_requested: bool,
+    export_requested: bool,
+    /// Snapshots to move through the history by, negative to go back
+    scrub_requested: isize,
+    /// Transient message shown on the HUD, with the moment it was posted
+    message: Option<(String, Instant)>,
+    landmarks: Landmarks,
+    /// Day the simulated clock starts at, from 1970-01-01
+    start_day: i64,
+    keybindings: KeyBindings,
 }
 
 impl Default for SimulationState {
@@ -42,32 +215,229 @@ impl Default for SimulationState {
         Self {
             paused: false,
             compute_collisions: true,
+            merge_condition: MergeCondition::default(),
+            physics: PhysicsConfig::default(),
             scale: (1. / (SUN_EARTH_DISTANCE)) * 200.,
             camera_position: CameraPosition::BodyRelative(0),
             dt_factor: 1.0,
             kinematics_index: 0,
+            theme_index: 0,
+            show_accel: false,
+            spawn_mode: false,
+            spawn_draft: None,
+            trails: TrailToggles::default(),
+            frame_body: None,
+            show_virial: false,
+            doppler: false,
+            doppler_max_speed: 30_000.,
+            gradient: false,
+            subsample: true,
+            heatmap: false,
+            heatmap_cell: HEATMAP_CELL_PIXELS,
+            projection: Projection::default(),
+            fixed_sun: false,
             speedup: 1.,
+            step_seconds: None,
+            max_steps_per_frame: MAX_STEPS_PER_FRAME,
+            frame_backlog: 0.,
+            saturated_frames: 0,
+            reset_requested: false,
+            inject_requested: false,
+            binary_requested: false,
+            periods_requested: false,
+            watch_requested: false,
+            approach: None,
+            camera_policy: CameraPolicy::default(),
+            auto_target: None,
+            big_collision: None,
+            save_requested: false,
+            load_requested: false,
+            export_requested: false,
+            scrub_requested: 0,
+            message: None,
+            landmarks: Landmarks::new(),
+            start_day: J2000_DAY,
+            keybindings: KeyBindings::default(),
         }
     }
 }
 
 impl SimulationState {
+    /// Reset the time controls, keeping the camera, integrator and physics
+    /// choices
+    fn reset(&mut self) {
+        let defaults = Self::default();
+        self.paused = defaults.paused;
+        self.dt_factor = defaults.dt_factor;
+        self.speedup = defaults.speedup;
+        self.reset_requested = false;
+    }
+
+    /// Simulated seconds covered by a frame, negative when reversed
+    fn dt(&self) -> f64 {
+        self.dt_factor
+            * self
+                .step_seconds
+                .unwrap_or(self.speedup * self.physics.base_dt)
+    }
+
+    /// Steps of [SimulationState::dt] to run for a frame that took
+    /// `frame_time` wall-clock seconds, one per [TARGET_FPS] frame, so a
+    /// slow frame runs several. What the cap leaves over is dropped rather
+    /// than carried, so that a slow machine never piles up more work than it
+    /// can do.
+    fn steps_for_frame(&mut self, frame_time: f64) -> usize {
+        let frame_period = 1. / TARGET_FPS as f64;
+        self.frame_backlog += frame_time;
+
+        let steps = (self.frame_backlog / frame_period).round() as usize;
+        if steps > self.max_steps_per_frame {
+            self.frame_backlog = 0.;
+            self.saturated_frames += 1;
+            self.max_steps_per_frame
+        } else {
+            self.frame_backlog -= steps as f64 * frame_period;
+            self.saturated_frames = 0;
+            steps
+        }
+    }
+
+    /// Whether the steps hit the cap for several frames in a row: the
+    /// simulated time lags behind the wall clock
+    fn lagging(&self) -> bool {
+        self.saturated_frames >= LAGGING_FRAMES
+    }
+
+    /// Show a message on the HUD for a few seconds
+    fn post_message(&mut self, message: String) {
+        self.message = Some((message, Instant::now()));
+    }
+
+    fn theme(&self) -> &Theme {
+        &THEMES[self.theme_index]
+    }
+
+    /// Center of the view. Following a body that no longer exists, such as
+    /// one merged into another, looks at the origin.
     fn get_universe_center(&self, bodies: &OrbitalBodies) -> (f64, f64) {
         match self.camera_position {
             CameraPosition::UniverseAbsolute(pos) => pos,
-            CameraPosition::BodyRelative(body) => bodies.get_by_id(body).unwrap().pos(),
+            CameraPosition::BodyRelative(body) => {
+                bodies.get_by_id(body).map(Body::pos).unwrap_or((0., 0.))
+            }
+        }
+    }
+
+    /// Where the camera looks, for drawing and picking
+    fn view(&self, bodies: &OrbitalBodies) -> View {
+        View {
+            universe_center: self.get_universe_center(bodies),
+            screen_center: (SPACE_SIZE / 2) as i32,
+            scale: self.scale,
+            projection: self.projection,
+        }
+    }
+
+    /// The camera, as written in a saved scene
+    fn saved_camera(&self) -> SavedCamera {
+        SavedCamera {
+            target: match self.camera_position {
+                CameraPosition::UniverseAbsolute(pos) => SavedTarget::Point(pos),
+                CameraPosition::BodyRelative(id) => SavedTarget::Body(id),
+            },
+            scale: self.scale,
+        }
+    }
+
+    /// Put the camera back where it was when the scene was saved
+    fn restore_camera(&mut self, camera: SavedCamera) {
+        self.camera_position = match camera.target {
+            SavedTarget::Point(pos) => CameraPosition::UniverseAbsolute(pos),
+            SavedTarget::Body(id) => CameraPosition::BodyRelative(id),
+        };
+        self.scale = camera.scale;
+    }
+
+    /// Stop following the body if it no longer exists
+    fn unfollow_missing(&mut self, bodies: &OrbitalBodies) {
+        if let CameraPosition::BodyRelative(id) = self.camera_position
+            && bodies.get_by_id(id).is_none()
+        {
+            self.camera_position = CameraPosition::UniverseAbsolute((0., 0.));
+        }
+    }
+
+    /// Follow the target of the [CameraPolicy] when it changes, given the
+    /// merges of the last step
+    fn apply_camera_policy(&mut self, bodies: &OrbitalBodies, collisions: &[CollisionEvent]) {
+        let target = match self.camera_policy {
+            CameraPolicy::Stay => None,
+            CameraPolicy::MostMassive => bodies.most_massive().map(Body::id),
+            CameraPolicy::BigCollision => {
+                // Forget the followed merge once it is old news
+                let current = self
+                    .big_collision
+                    .filter(|(_, at)| at.elapsed().as_secs_f64() < BIG_COLLISION_SECONDS)
+                    .map_or(0., |(energy, _)| energy);
+
+                match collisions
+                    .iter()
+                    .max_by(|a, b| a.energy.total_cmp(&b.energy))
+                {
+                    Some(event) if event.energy > current => {
+                        self.big_collision = Some((event.energy, Instant::now()));
+                        // The shattered bodies are gone, the largest piece is left
+                        Some(event.fragments.first().copied().unwrap_or(event.survivor))
+                    }
+                    _ => self.auto_target,
+                }
+            }
+        };
+
+        if let Some(target) = target
+            && self.auto_target != Some(target)
+            && bodies.get_by_id(target).is_some()
+        {
+            self.camera_position = CameraPosition::BodyRelative(target);
+            self.auto_target = Some(target);
         }
     }
 }
 
-fn main() {
-    let (mut rl, thread) = init()
-        .size(SPACE_SIZE as i32, SPACE_SIZE as i32)
-        .title("Space")
-        .build();
+/// Draw radius of the asteroids with `--asteroid-radius log`, from a pixel for
+/// the pebbles to 5 for the largest ones
+const LOG_ASTEROID_RADIUS: DrawRadiusPolicy = DrawRadiusPolicy::LogScaled { min: 1., max: 5. };
+
+/// Eccentricities of the asteroids with `--asteroid-eccentricity rayleigh`,
+/// with a mean of about 0.15 as in the main belt
+const RAYLEIGH_ECCENTRICITY: EccentricityDistribution =
+    EccentricityDistribution::Rayleigh { scale: 0.12 };
 
-    rl.set_target_fps(60);
+/// Where the initial bodies come from, kept around to rebuild them on reset
+enum Scene {
+    SolarSystem,
+    Horizons { path: String, epoch: Option<String> },
+}
 
+/// Build the bodies of the scene, with the sun's velocity balancing the
+/// system's momentum, and `belt` around the sun. The same scene and seed
+/// always give the same bodies, with the same ids, so the bodies of an
+/// earlier build must be dropped first, see [reset_ids].
+fn build_bodies(scene: &Scene, seed: u64, belt: BeltParameters) -> (OrbitalBodies, Landmarks) {
+    reset_ids();
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    let (mut bodies, landmarks) = match scene {
+        Scene::SolarSystem => solar_system(belt, &mut rng),
+        Scene::Horizons { path, epoch } => horizons_system(path, epoch.as_deref(), belt, &mut rng),
+    };
+
+    bodies.init_sun(landmarks["sun"]);
+    (bodies, landmarks)
+}
+
+/// The built-in system: the sun, a few planets, a comet and an asteroid belt
+fn solar_system(belt: BeltParameters, rng: &mut impl Rng) -> (OrbitalBodies, Landmarks) {
     let sun = Body::new(
         SUN_MASS,
         (0., 0.),
@@ -76,118 +446,902 @@ fn main() {
         Color::YELLOW,
         (0.0, 0.0),
         (0.0, 0.0),
-    );
+    )
+    .with_label("Sun");
     let sun_id = sun.id();
 
-    let belt = bodies_to_map(create_asteroid_belt(&sun, 10_000, AU));
-
-    let mut bodies = OrbitalBodies {
-        tier0: bodies_to_map(vec![
-            sun,
-            // Mars
-            Body::new(
-                MARS_MASS,
-                (0., 0. + SUN_MARS_DISTANCE),
-                MARS_RADIUS,
-                8.,
-                Color::RED,
-                (MARS_VELOCITY, 0.),
-                (0.0, 0.0),
-            ),
-            // Earth
-            Body::new(
-                EARTH_MASS,
-                (0., 0. + SUN_EARTH_DISTANCE),
-                EARTH_RADIUS,
-                10.,
-                Color::BLUE,
-                (EARTH_SUN_VELOCITY, 0.0),
-                (0.0, 0.0),
-            ),
-            // Moon
-            Body::new(
-                MOON_MASS,
-                (0., 0. + SUN_EARTH_DISTANCE + EARTH_MOON_DISTANCE),
-                MOON_RADIUS,
-                3.0,
-                Color::GRAY,
-                (EARTH_SUN_VELOCITY + MOON_EARTH_VELOCITY, 0.),
-                (0., 0.),
-            ),
-            // Haley's comet
-            Body::new(
-                HALEYS_COMET_MASS,
-                (0. + SUN_HALEY_DISTANCE, 0.),
-                HALEYS_RADIUS,
-                3.0,
-                Color::ORANGERED,
-                (0., HALEYS_COMET_VELOCITY),
-                (0.0, 0.0),
-            ),
-        ]),
-        tier1: belt,
+    let belt = bodies_to_map(create_asteroid_belt(&sun, belt, G, rng));
+
+    let mars = Body::new(
+        MARS_MASS,
+        (0., 0. + SUN_MARS_DISTANCE),
+        MARS_RADIUS,
+        8.,
+        Color::RED,
+        (MARS_VELOCITY, 0.),
+        (0.0, 0.0),
+    )
+    .with_label("Mars");
+    let earth = Body::new(
+        EARTH_MASS,
+        (0., 0. + SUN_EARTH_DISTANCE),
+        EARTH_RADIUS,
+        10.,
+        Color::BLUE,
+        (EARTH_SUN_VELOCITY, 0.0),
+        (0.0, 0.0),
+    )
+    .with_label("Earth");
+    let moon = Body::new(
+        MOON_MASS,
+        (0., 0. + SUN_EARTH_DISTANCE + EARTH_MOON_DISTANCE),
+        MOON_RADIUS,
+        3.0,
+        Color::GRAY,
+        (EARTH_SUN_VELOCITY + MOON_EARTH_VELOCITY, 0.),
+        (0., 0.),
+    )
+    .with_label("Moon");
+    let mut haley = Body::new(
+        HALEYS_COMET_MASS,
+        (0. + SUN_HALEY_DISTANCE, 0.),
+        HALEYS_RADIUS,
+        3.0,
+        Color::ORANGERED,
+        (0., HALEYS_COMET_VELOCITY),
+        (0.0, 0.0),
+    )
+    .with_label("Halley's Comet");
+    haley.mass_loss_rate = HALEYS_MASS_LOSS_RATE;
+
+    let landmarks = Landmarks::from([
+        ("sun", sun_id),
+        ("mars", mars.id()),
+        ("earth", earth.id()),
+        ("moon", moon.id()),
+        ("comet", haley.id()),
+    ]);
+
+    let bodies = OrbitalBodies::new(bodies_to_map(vec![sun, mars, earth, moon, haley]), belt);
+
+    (bodies, landmarks)
+}
+
+/// Inner system loaded from a Horizons export, with an asteroid belt around its most massive body
+fn horizons_system(
+    path: &str,
+    epoch: Option<&str>,
+    belt: BeltParameters,
+    rng: &mut impl Rng,
+) -> (OrbitalBodies, Landmarks) {
+    let options = HorizonsOptions {
+        epoch,
+        ..Default::default()
+    };
+
+    let mut bodies = match load_horizons(path, &options) {
+        Ok(bodies) => bodies,
+        Err(err) => {
+            eprintln!("Could not load {path}: {err}");
+            std::process::exit(1);
+        }
+    };
+
+    let sun = bodies.most_massive().unwrap();
+    let sun_id = sun.id();
+
+    bodies.tier1 = bodies_to_map(create_asteroid_belt(sun, belt, G, rng));
+
+    (bodies, Landmarks::from([("sun", sun_id)]))
+}
+
+fn main() {
+    let args = std::env::args().collect::<Vec<_>>();
+    let arg_value = |flag: &str| {
+        args.iter()
+            .position(|arg| arg == flag)
+            .and_then(|i| args.get(i + 1))
+            .map(String::as_str)
+    };
+
+    let scene = match arg_value("--horizons") {
+        Some(path) => Scene::Horizons {
+            path: path.to_string(),
+            epoch: arg_value("--epoch").map(str::to_string),
+        },
+        None => Scene::SolarSystem,
+    };
+
+    let seed = match arg_value("--seed") {
+        Some(seed) => seed.parse().expect("--seed should be an integer"),
+        None => rand::rng().random(),
+    };
+
+    let mut belt = BeltParameters::default();
+    if let Some(name) = arg_value("--asteroid-radius") {
+        match name {
+            "fixed" => {}
+            "log" => belt.draw_radius_policy = LOG_ASTEROID_RADIUS,
+            _ => eprintln!("Unknown asteroid radius {name}, using the default one"),
+        }
+    }
+
+    if let Some(name) = arg_value("--asteroid-eccentricity") {
+        match name {
+            "uniform" => {}
+            "rayleigh" => belt.eccentricity = RAYLEIGH_ECCENTRICITY,
+            _ => eprintln!("Unknown asteroid eccentricity {name}, using the default one"),
+        }
+    }
+
+    let (bodies, landmarks) = build_bodies(&scene, seed, belt);
+
+    let inject_path = arg_value("--inject");
+    let save_path = arg_value("--save");
+    let export_path = arg_value("--export");
+    let collision_stats_prefix = arg_value("--collision-stats");
+
+    let mut history = arg_value("--history").map(|frames| {
+        SnapshotHistory::new(
+            frames
+                .parse()
+                .expect("--history should be a number of frames"),
+            HISTORY_MAX_BYTES,
+            args.iter().any(|arg| arg == "--history-tier1"),
+        )
+    });
+
+    // Frames are saved by raylib in the working directory
+    let mut recorder = arg_value("--record").map(|prefix| {
+        let seconds_per_frame = match arg_value("--record-interval") {
+            Some(seconds) => seconds
+                .parse()
+                .ok()
+                .filter(|seconds: &f64| *seconds > 0.)
+                .expect("--record-interval should be a positive number of seconds"),
+            None => SECONDS_PER_DAY,
+        };
+
+        FrameRecorder::new(prefix.to_string(), seconds_per_frame)
+    });
+
+    let mut simulation_state = SimulationState {
+        landmarks,
+        ..Default::default()
     };
 
-    let mut simulation_state = SimulationState::default();
+    simulation_state.fixed_sun = args.iter().any(|arg| arg == "--fixed-sun");
+    simulation_state.physics.fragmentation = !args.iter().any(|arg| arg == "--no-fragmentation");
+
+    if let Some(speed) = arg_value("--doppler-max") {
+        simulation_state.doppler_max_speed = speed
+            .parse()
+            .expect("--doppler-max should be a speed in m/s");
+    }
+
+    if let Some(seconds) = arg_value("--dt") {
+        let seconds: f64 = seconds
+            .parse()
+            .ok()
+            .filter(|seconds| *seconds != 0.)
+            .expect("--dt should be a non-zero number of seconds");
+        simulation_state.step_seconds = Some(seconds.abs());
+    }
+
+    if let Some(date) = arg_value("--start-date") {
+        simulation_state.start_day =
+            parse_date(date).expect("--start-date should be a date as YYYY-MM-DD");
+    }
+
+    match KeyBindings::load(KEYBINDINGS_PATH) {
+        Ok(keybindings) => simulation_state.keybindings = keybindings,
+        Err(err) => eprintln!("Could not load {KEYBINDINGS_PATH}: {err}, using the default keys"),
+    }
+
+    if let Some(cap) = arg_value("--max-collisions") {
+        simulation_state.physics.max_collisions_per_step = Some(
+            cap.parse()
+                .expect("--max-collisions should be a number of collisions"),
+        );
+    }
+
+    if let Some(steps) = arg_value("--max-steps-per-frame") {
+        let steps: usize = steps
+            .parse()
+            .expect("--max-steps-per-frame should be a number of steps");
+        simulation_state.max_steps_per_frame = steps.max(1);
+    }
+
+    if let Some(pixels) = arg_value("--heatmap-cell") {
+        let pixels: i32 = pixels
+            .parse()
+            .expect("--heatmap-cell should be a number of pixels");
+        simulation_state.heatmap_cell = pixels.max(1);
+    }
+
+    if let Some(name) = arg_value("--camera-policy") {
+        match CameraPolicy::ALL
+            .iter()
+            .find(|policy| policy.name() == name)
+        {
+            Some(&policy) => simulation_state.camera_policy = policy,
+            None => eprintln!("Unknown camera policy {name}, leaving the camera alone"),
+        }
+    }
+
+    if let Some(name) = arg_value("--theme") {
+        match THEMES.iter().position(|theme| theme.name == name) {
+            Some(index) => simulation_state.theme_index = index,
+            None => eprintln!("Unknown theme {name}, using the default one"),
+        }
+    }
+
+    // The default scale suits the built-in system, other scenes are fitted
+    // in the window
+    match arg_value("--scale") {
+        Some(pixels_per_au) => {
+            let pixels_per_au: f64 = pixels_per_au
+                .parse()
+                .ok()
+                .filter(|pixels_per_au| *pixels_per_au > 0.)
+                .expect("--scale should be a positive number of pixels per AU");
+            simulation_state.scale = (pixels_per_au / AU).clamp(MIN_SCALE, MAX_SCALE);
+        }
+        None if !matches!(scene, Scene::SolarSystem) => {
+            let center = simulation_state.get_universe_center(&bodies);
+            if let Some(scale) = fit_scale(&bodies, center, SPACE_SIZE as i32) {
+                simulation_state.scale = scale;
+            }
+        }
+        None => {}
+    }
+
+    let mut simulation = Simulation::new(bodies, integrator(simulation_state.kinematics_index));
+    simulation.config = simulation_state.physics;
+
+    let (collision_events, collisions) = mpsc::channel();
+    simulation.collision_events = Some(collision_events);
+
+    if let Some(megabytes) = arg_value("--trail-budget") {
+        let megabytes: usize = megabytes
+            .parse()
+            .expect("--trail-budget should be a number of megabytes");
+        simulation.trail_budget = megabytes * 1024 * 1024;
+    }
+
+    if let Some(distance) = arg_value("--trail-distance") {
+        simulation.trail_sampling = TrailSampling::Distance(
+            distance
+                .parse()
+                .expect("--trail-distance should be a distance in meters"),
+        );
+    }
+
+    // Run without a window, to compare integrators or check a scene
+    if let Some(steps) = arg_value("--steps") {
+        let steps: usize = steps.parse().expect("--steps should be a number of steps");
+        let dt = simulation_state
+            .step_seconds
+            .expect("--steps needs the --dt of each step, in seconds");
 
-    let kinematics: [Box<dyn Kinematics>; 3] =
-        [Box::new(Leapfrog), Box::new(LeapfrogKDK), Box::new(Euler)];
+        simulation.merge_condition = simulation_state
+            .compute_collisions
+            .then_some(simulation_state.merge_condition);
+        if let Some(sun) = simulation.bodies.most_massive()
+            && sun.fixed != simulation_state.fixed_sun
+        {
+            let sun = sun.id();
+            simulation.bodies.set_fixed(sun, simulation_state.fixed_sun);
+            simulation.rebaseline();
+        }
+
+        let summary = simulation.run(steps, dt, |_, _, _| ControlFlow::Continue(()));
+        println!("{summary}");
+        save_collision_stats(&simulation, collision_stats_prefix);
+        return;
+    }
+
+    let (mut rl, thread) = init()
+        .size(SPACE_SIZE as i32, SPACE_SIZE as i32)
+        .title("Space")
+        .build();
 
-    bodies.init_sun(sun_id);
-    let mut kin = &kinematics[simulation_state.kinematics_index];
+    rl.set_target_fps(TARGET_FPS);
 
-    let e0 = kin.step(&mut bodies, 0.01);
+    let mut apsis_tracker: Option<ApsisTracker> = None;
+    let mut period_analyzer = PeriodAnalyzer::new();
+    period_analyzer.update(&simulation.bodies, simulation.time(), &simulation.config);
+    let mut binding_watcher: Option<BindingWatcher> = None;
 
     while !rl.window_should_close() {
-        if handle_input(
-            &mut rl,
-            &mut simulation_state,
-            &mut kin,
-            &bodies,
-            &kinematics,
-        ) {
+        if handle_input(&mut rl, &mut simulation_state, &mut simulation) {
             break;
         }
 
+        simulation.config = simulation_state.physics;
+        simulation.merge_condition = simulation_state
+            .compute_collisions
+            .then_some(simulation_state.merge_condition);
+
+        if simulation_state.reset_requested {
+            let center = simulation_state.get_universe_center(&simulation.bodies);
+            simulation.bodies = OrbitalBodies::new(HashMap::new(), HashMap::new());
+            let (bodies, landmarks) = build_bodies(&scene, seed, belt);
+            simulation.reset(bodies);
+            simulation_state.landmarks = landmarks;
+            simulation_state.reset();
+
+            if let Some(history) = history.as_mut() {
+                history.clear();
+            }
+
+            if let Some(recorder) = recorder.as_mut() {
+                recorder.resync(simulation.time());
+            }
+
+            // Stop following a body that did not survive the reset
+            if let CameraPosition::BodyRelative(id) = simulation_state.camera_position
+                && simulation.bodies.get_by_id(id).is_none()
+            {
+                simulation_state.camera_position = CameraPosition::UniverseAbsolute(center);
+            }
+
+            period_analyzer = PeriodAnalyzer::new();
+            period_analyzer.update(&simulation.bodies, simulation.time(), &simulation.config);
+            binding_watcher = None;
+            apsis_tracker = None;
+            simulation_state.approach = None;
+        }
+
+        // Also pins the sun again after a reset
+        if let Some(sun) = simulation.bodies.most_massive()
+            && sun.fixed != simulation_state.fixed_sun
+        {
+            let sun = sun.id();
+            simulation.bodies.set_fixed(sun, simulation_state.fixed_sun);
+            simulation.rebaseline();
+        }
+
+        if simulation_state.inject_requested {
+            simulation_state.inject_requested = false;
+
+            let message = match inject_path.map(load_bodies) {
+                Some(Ok(injected)) => {
+                    let added = simulation.bodies.append(injected);
+                    simulation.rebaseline();
+                    format!("Added {added} bodies")
+                }
+                Some(Err(err)) => format!("Could not add bodies: {err}"),
+                None => "No file to add bodies from, see --inject".to_string(),
+            };
+
+            simulation_state.post_message(message);
+        }
+
+        if simulation_state.save_requested {
+            simulation_state.save_requested = false;
+
+            let camera = simulation_state.saved_camera();
+            let message = match save_path
+                .map(|path| save_scene(path, &simulation.bodies, simulation.time(), &camera))
+            {
+                Some(Ok(())) => "Scene saved".to_string(),
+                Some(Err(err)) => format!("Could not save the scene: {err}"),
+                None => "No file to save the scene to, see --save".to_string(),
+            };
+
+            simulation_state.post_message(message);
+        }
+
+        if simulation_state.export_requested {
+            simulation_state.export_requested = false;
+
+            let message = match export_path.map(|path| save_bodies(path, &simulation.bodies)) {
+                Some(Ok(())) => format!("Exported {0} bodies", simulation.bodies.len()),
+                Some(Err(err)) => format!("Could not export the bodies: {err}"),
+                None => "No file to export the bodies to, see --export".to_string(),
+            };
+
+            simulation_state.post_message(message);
+        }
+
+        if simulation_state.load_requested {
+            simulation_state.load_requested = false;
+
+            let message = match save_path.map(load_scene) {
+                Some(Ok(saved)) => {
+                    simulation.reset(saved.bodies);
+                    simulation.set_time(saved.time);
+                    simulation_state.restore_camera(saved.camera);
+                    simulation_state.unfollow_missing(&simulation.bodies);
+
+                    if let Some(history) = history.as_mut() {
+                        history.clear();
+                    }
+
+                    if let Some(recorder) = recorder.as_mut() {
+                        recorder.resync(simulation.time());
+                    }
+
+                    period_analyzer = PeriodAnalyzer::new();
+                    period_analyzer.update(
+                        &simulation.bodies,
+                        simulation.time(),
+                        &simulation.config,
+                    );
+                    binding_watcher = None;
+                    apsis_tracker = None;
+                    simulation_state.approach = None;
+                    "Scene loaded".to_string()
+                }
+                Some(Err(err)) => format!("Could not load the scene: {err}"),
+                None => "No file to load the scene from, see --save".to_string(),
+            };
+
+            simulation_state.post_message(message);
+        }
+
+        if simulation_state.scrub_requested != 0 {
+            simulation_state.paused = true;
+
+            if let Some(history) = history.as_mut()
+                && let Some(time) =
+                    history.scrub(simulation_state.scrub_requested, &mut simulation.bodies)
+            {
+                simulation.set_time(time);
+
+                if let Some(recorder) = recorder.as_mut() {
+                    recorder.resync(time);
+                }
+            }
+
+            simulation_state.scrub_requested = 0;
+        }
+
+        if simulation_state.binary_requested {
+            simulation_state.binary_requested = false;
+
+            let (primary, secondary) = create_binary(
+                BINARY_PRIMARY_MASS,
+                BINARY_PRIMARY_MASS * BINARY_MASS_RATIO,
+                BINARY_SEPARATION,
+                simulation_state.get_universe_center(&simulation.bodies),
+                simulation.config.g,
+            );
+            simulation.bodies.append(OrbitalBodies::new(
+                bodies_to_map(vec![primary, secondary]),
+                bodies_to_map(vec![]),
+            ));
+
+            simulation.rebaseline();
+            simulation_state.post_message("Added a binary star".to_string());
+        }
+
+        if simulation_state.periods_requested {
+            simulation_state.periods_requested = false;
+
+            for (id, estimate) in period_analyzer.report(&simulation.bodies, simulation.config.g) {
+                match estimate {
+                    PeriodEstimate::Measured(period) => {
+                        println!(
+                            "Body #{id}: period of {0:.2} days",
+                            period / SECONDS_PER_DAY
+                        )
+                    }
+                    PeriodEstimate::Unknown(fraction) => println!(
+                        "Body #{id}: period unknown ({0:.0}% of an orbit)",
+                        fraction * 100.
+                    ),
+                    PeriodEstimate::NotOrbiting => println!("Body #{id}: not orbiting"),
+                }
+            }
+        }
+
+        if simulation_state.watch_requested {
+            simulation_state.watch_requested = false;
+
+            binding_watcher = match simulation_state.camera_position {
+                CameraPosition::BodyRelative(id) => {
+                    BindingWatcher::around_attractor(&simulation.bodies, id, &simulation.config)
+                }
+                CameraPosition::UniverseAbsolute(_) => None,
+            };
+
+            let message = match binding_watcher.as_mut() {
+                Some(watcher) => {
+                    watcher.update(&simulation.bodies, simulation.config.g);
+                    format!(
+                        "Watching body #{0} around #{1}, {2}",
+                        watcher.body,
+                        watcher.attractor,
+                        match watcher.bound() {
+                            Some(true) => "bound",
+                            _ => "unbound",
+                        }
+                    )
+                }
+                None => "Follow a body orbiting another to watch it".to_string(),
+            };
+            simulation_state.post_message(message);
+        }
+
         // Simulate
         let before_step = Instant::now();
+        let frame_time = rl.get_frame_time() as f64;
+
+        if !simulation_state.paused {
+            for _ in 0..simulation_state.steps_for_frame(frame_time) {
+                simulation.advance(simulation_state.dt());
+
+                // Nothing meaningful comes out of further steps
+                if let Some(Divergence {
+                    body,
+                    quantity,
+                    value,
+                }) = simulation.divergence()
+                {
+                    simulation_state.paused = true;
+                    println!(
+                        "Day {0:.1}: diverged, body #{body} has a {quantity} of {value:?}",
+                        simulation.time() / SECONDS_PER_DAY
+                    );
+                    break;
+                }
+            }
+            simulation_state.unfollow_missing(&simulation.bodies);
+
+            let events = collisions.try_iter().collect::<Vec<_>>();
+            for CollisionEvent {
+                survivor_name,
+                destroyed_name,
+                fragments,
+                ..
+            } in &events
+            {
+                let day = simulation.time() / SECONDS_PER_DAY;
+                match fragments.len() {
+                    0 => println!("Day {day:.1}: {destroyed_name} absorbed by {survivor_name}"),
+                    count => println!(
+                        "Day {day:.1}: {survivor_name} and {destroyed_name} shattered into \
+                         {count} fragments"
+                    ),
+                }
+            }
+            simulation_state.apply_camera_policy(&simulation.bodies, &events);
 
-        let energy_delta = if !simulation_state.paused {
-            let step_kinematics = kin.step(
-                &mut bodies,
-                simulation_state.dt_factor * simulation_state.speedup * 1800. * 24.,
-            );
-            let delta_energy_rel = (step_kinematics - e0) / e0.total();
             #[cfg(debug_assertions)]
             {
+                let delta_energy_rel = simulation.diagnostics().energy_drift;
                 println!("Energy delta: ${delta_energy_rel:.3}");
             }
 
-            if simulation_state.compute_collisions {
-                handle_collisions(&mut bodies);
+            if let Some(history) = history.as_mut() {
+                history.push(&simulation.bodies, simulation.time());
             }
-            delta_energy_rel
-        } else {
-            0.
-        };
+
+            period_analyzer.update(&simulation.bodies, simulation.time(), &simulation.config);
+
+            if let Some(watcher) = binding_watcher.as_mut()
+                && let Some(transition) = watcher.update(&simulation.bodies, simulation.config.g)
+            {
+                let (body, attractor) = (watcher.body, watcher.attractor);
+                let day = simulation.time() / SECONDS_PER_DAY;
+
+                match transition {
+                    BindingTransition::Captured => {
+                        println!("Day {day:.1}: body #{body} captured by #{attractor}")
+                    }
+                    BindingTransition::Escaped => {
+                        println!("Day {day:.1}: body #{body} escaped #{attractor}")
+                    }
+                    BindingTransition::Lost => {
+                        println!("Day {day:.1}: body #{body} or #{attractor} is gone");
+                        binding_watcher = None;
+                    }
+                }
+            }
+
+            if let Some(approach) = simulation_state.approach.as_mut()
+                && !approach.update(&simulation.bodies, simulation.time())
+            {
+                let (first, second) = approach.pair;
+                simulation_state.approach = None;
+                simulation_state
+                    .post_message(format!("Body #{first} or #{second} is gone, approach lost"));
+            }
+
+            // Track the apsides of the followed body
+            apsis_tracker = match simulation_state.camera_position {
+                CameraPosition::BodyRelative(id) => Some(
+                    apsis_tracker
+                        .filter(|tracker| tracker.body() == id)
+                        .unwrap_or_else(|| ApsisTracker::new(id)),
+                ),
+                CameraPosition::UniverseAbsolute(_) => None,
+            };
+
+            if let Some(tracker) = apsis_tracker.as_mut()
+                && let Some(apsis) =
+                    tracker.update(&simulation.bodies, simulation.time(), &simulation.config)
+            {
+                println!(
+                    "Body #{0} passed {1:?} at {2:.4} AU, day {3:.1}",
+                    tracker.body(),
+                    apsis.kind,
+                    apsis.distance / AU,
+                    apsis.time / SECONDS_PER_DAY
+                );
+            }
+        }
+
+        for body in simulation
+            .bodies
+            .iter_mut()
+            .filter(|body| body.temperature > 0.)
+        {
+            body.cool(frame_time);
+        }
 
         let after_step = Instant::now();
+        let diagnostics = simulation.diagnostics();
         let hud_text = HudParams {
             compute_time: after_step - before_step,
-            energy_delta,
+            energy_delta: diagnostics.energy_drift,
+            corrected_energy_delta: diagnostics.corrected_energy_drift,
+            dissipated_energy: simulation.dissipated_energy(),
+            momentum_drift: diagnostics.momentum_drift,
+            virial_ratio: diagnostics.energy.virial_ratio(),
         };
 
         // Draw
-        let mut draw_handle = rl.begin_drawing(&thread);
-        draw_handle.clear_background(Color::BLACK);
-
-        draw_universe_relative(
-            &mut draw_handle,
-            &bodies,
-            simulation_state.get_universe_center(&bodies),
-            simulation_state.scale,
-        );
+        {
+            let bodies = &simulation.bodies;
+            let mut draw_handle = rl.begin_drawing(&thread);
+            draw_handle.clear_background(simulation_state.theme().background);
+
+            let doppler = simulation_state.doppler.then(|| Doppler {
+                observer_velocity: match simulation_state.camera_position {
+                    CameraPosition::BodyRelative(id) => {
+                        bodies.get_by_id(id).map_or((0., 0.), |body| body.velocity)
+                    }
+                    CameraPosition::UniverseAbsolute(_) => (0., 0.),
+                },
+                max_radial_speed: simulation_state.doppler_max_speed,
+            });
+
+            let view = simulation_state.view(bodies);
+            let always_drawn = match simulation_state.camera_position {
+                CameraPosition::BodyRelative(id) => vec![id],
+                CameraPosition::UniverseAbsolute(_) => vec![],
+            };
+
+            draw_universe_relative(
+                &mut draw_handle,
+                bodies,
+                view,
+                simulation_state.theme(),
+                BodyView {
+                    doppler: doppler.as_ref(),
+                    gradient: simulation_state.gradient,
+                    subsample: simulation_state.subsample,
+                    heatmap: simulation_state
+                        .heatmap
+                        .then_some(simulation_state.heatmap_cell),
+                    always_drawn: &always_drawn,
+                },
+                TrailView {
+                    toggles: simulation_state.trails,
+                    frame: simulation_state
+                        .frame_body
+                        .and_then(|id| bodies.get_by_id(id)),
+                },
+            );
+
+            if let CameraPosition::BodyRelative(id) = simulation_state.camera_position
+                && let Some(body) = bodies.get_by_id(id)
+                && let Some(attractor) = dominant_attractor(bodies, id, &simulation.config)
+                    .and_then(|attractor| bodies.get_by_id(attractor))
+            {
+                draw_analytic_orbit(&mut draw_handle, body, attractor, simulation.config.g, view);
+            }
+
+            if let CameraPosition::BodyRelative(id) = simulation_state.camera_position
+                && let Some(body) = bodies.get_by_id(id)
+            {
+                draw_follow_marker(&mut draw_handle, body, view, simulation_state.theme());
+            }
+
+            if let Some(draft) = &simulation_state.spawn_draft {
+                draw_spawn_preview(
+                    &mut draw_handle,
+                    draft.position,
+                    draft.reference(bodies, simulation_state.scale),
+                    draft.eccentricity,
+                    view,
+                    simulation_state.theme(),
+                );
+            }
+
+            if simulation_state.show_accel {
+                draw_accel_vectors(&mut draw_handle, bodies, view);
+            }
+
+            draw_hud(&mut draw_handle, &simulation_state, &simulation, hud_text);
+
+            if let CameraPosition::BodyRelative(id) = simulation_state.camera_position
+                && let Some(body) = bodies.get_by_id(id)
+            {
+                // Relative to the heaviest planet of the attractor, Jupiter
+                // would be in the real solar system
+                let config = &simulation.config;
+                let tisserand = dominant_attractor(bodies, id, config)
+                    .and_then(|attractor| bodies.get_by_id(attractor))
+                    .and_then(|attractor| {
+                        let planet = bodies
+                            .tier0
+                            .values()
+                            .filter(|planet| {
+                                planet.id() != id
+                                    && dominant_attractor(bodies, planet.id(), config)
+                                        == Some(attractor.id())
+                            })
+                            .max_by(|a, b| a.mass.total_cmp(&b.mass))?;
+                        Some((
+                            planet,
+                            tisserand(body, planet, attractor, simulation.config.g),
+                        ))
+                    });
+
+                draw_body_info(
+                    &mut draw_handle,
+                    body,
+                    apsis_tracker.as_ref(),
+                    tisserand,
+                    simulation.collision_stats.accreted(id),
+                    simulation_state.theme(),
+                );
+            }
+        }
+
+        if let Some(recorder) = recorder.as_mut() {
+            for name in recorder.frames_due(simulation.time()) {
+                rl.take_screenshot(&thread, &name);
+            }
+        }
+    }
+    if let Some(recorder) = recorder {
+        println!("Recorded {0} frames", recorder.frames());
+    }
+    save_collision_stats(&simulation, collision_stats_prefix);
+}
+
+/// Save the merges of the run, when given a `--collision-stats` prefix, see
+/// [crate::physics::collision_stats::CollisionStats::save_csv]
+fn save_collision_stats(simulation: &Simulation, prefix: Option<&str>) {
+    if let Some(prefix) = prefix
+        && let Err(err) = simulation.collision_stats.save_csv(prefix)
+    {
+        eprintln!("Could not save the collision stats: {err}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::body::{Body, BodyId, OrbitalBodies, bodies_to_map};
+    use crate::physics::collisions::CollisionEvent;
+    use crate::{CameraPolicy, CameraPosition, LAGGING_FRAMES, SimulationState, TARGET_FPS};
+    use raylib::color::Color;
+
+    #[test]
+    fn test_camera_without_bodies() {
+        let bodies = OrbitalBodies::new(bodies_to_map(vec![]), bodies_to_map(vec![]));
+
+        let mut simulation_state = SimulationState {
+            camera_position: CameraPosition::BodyRelative(42),
+            ..Default::default()
+        };
+
+        assert_eq!(simulation_state.get_universe_center(&bodies), (0., 0.));
+
+        simulation_state.unfollow_missing(&bodies);
+        assert!(matches!(
+            simulation_state.camera_position,
+            CameraPosition::UniverseAbsolute((0., 0.))
+        ));
+    }
+
+    #[test]
+    fn test_camera_policy() {
+        let body = |mass: f64| Body::new(mass, (0., 0.), 1., 1., Color::WHITE, (0., 0.), (0., 0.));
+        let (light, heavy) = (body(1.), body(2.));
+        let (light_id, heavy_id) = (light.id(), heavy.id());
+        let bodies = OrbitalBodies::new(bodies_to_map(vec![light, heavy]), bodies_to_map(vec![]));
+        let followed = |simulation_state: &SimulationState| match simulation_state.camera_position {
+            CameraPosition::BodyRelative(id) => Some(id),
+            CameraPosition::UniverseAbsolute(_) => None,
+        };
+
+        // Stay leaves the camera alone
+        let mut simulation_state = SimulationState {
+            camera_position: CameraPosition::UniverseAbsolute((0., 0.)),
+            ..Default::default()
+        };
+        simulation_state.apply_camera_policy(&bodies, &[]);
+        assert_eq!(followed(&simulation_state), None);
+
+        simulation_state.camera_policy = CameraPolicy::MostMassive;
+        simulation_state.apply_camera_policy(&bodies, &[]);
+        assert_eq!(followed(&simulation_state), Some(heavy_id));
+
+        // A manual change sticks while the heaviest body stays the same
+        simulation_state.camera_position = CameraPosition::BodyRelative(light_id);
+        simulation_state.apply_camera_policy(&bodies, &[]);
+        assert_eq!(followed(&simulation_state), Some(light_id));
+
+        let merge = |survivor: BodyId, energy: f64| CollisionEvent {
+            survivor,
+            destroyed: 42,
+            survivor_name: String::new(),
+            destroyed_name: String::new(),
+            survivor_mass: 1.,
+            destroyed_mass: 1.,
+            position: (0., 0.),
+            energy,
+            fragments: vec![],
+        };
+
+        simulation_state.camera_policy = CameraPolicy::BigCollision;
+        simulation_state.apply_camera_policy(&bodies, &[merge(heavy_id, 1.), merge(light_id, 5.)]);
+        assert_eq!(followed(&simulation_state), Some(light_id));
+
+        // A weaker merge soon after does not take over
+        simulation_state.apply_camera_policy(&bodies, &[merge(heavy_id, 2.)]);
+        assert_eq!(followed(&simulation_state), Some(light_id));
+
+        simulation_state.apply_camera_policy(&bodies, &[merge(heavy_id, 10.)]);
+        assert_eq!(followed(&simulation_state), Some(heavy_id));
+    }
+
+    #[test]
+    fn test_steps_for_frame() {
+        let frame = 1. / TARGET_FPS as f64;
+        let mut simulation_state = SimulationState {
+            max_steps_per_frame: 4,
+            ..Default::default()
+        };
+
+        // On time, a step per frame, and a slow frame catches up
+        assert_eq!(simulation_state.steps_for_frame(frame), 1);
+        assert_eq!(simulation_state.steps_for_frame(3. * frame), 3);
+        assert!(!simulation_state.lagging());
+
+        // Far too slow, for long enough to show
+        for _ in 0..LAGGING_FRAMES {
+            assert_eq!(simulation_state.steps_for_frame(10. * frame), 4);
+        }
+        assert!(simulation_state.lagging());
+
+        // Still a little too slow right after: the cap, and it keeps showing
+        assert_eq!(simulation_state.steps_for_frame(5. * frame), 4);
+        assert!(simulation_state.lagging());
+
+        // The time past the cap is dropped, not caught up later
+        assert_eq!(simulation_state.steps_for_frame(frame), 1);
+        assert!(!simulation_state.lagging());
+    }
+
+    #[test]
+    fn test_dt_in_seconds() {
+        let mut simulation_state = SimulationState {
+            speedup: 5.,
+            ..Default::default()
+        };
+        assert_eq!(simulation_state.dt(), 5. * simulation_state.physics.base_dt);
+
+        simulation_state.step_seconds = Some(3600.);
+        assert_eq!(simulation_state.dt(), 3600.);
 
-        draw_hud(&mut draw_handle, &simulation_state, &bodies, &kin, hud_text);
+        simulation_state.dt_factor = -1.;
+        assert_eq!(simulation_state.dt(), -3600.);
     }
 }
@@ -0,0 +1,100 @@
+use crate::body::{BodyId, OrbitalBodies};
+use crate::physics::distance_sq;
+
+/// The smallest separation of a pair of bodies seen since the tracking
+/// started, or was last reset, checked once per step
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ClosestApproach {
+    pub pair: (BodyId, BodyId),
+    /// Separation at the last update, in meters
+    current: Option<f64>,
+    /// Smallest separation, in meters, and the simulated time it was seen at
+    closest: Option<(f64, f64)>,
+}
+
+impl ClosestApproach {
+    pub fn new(first: BodyId, second: BodyId) -> Self {
+        Self {
+            pair: (first, second),
+            current: None,
+            closest: None,
+        }
+    }
+
+    /// Separation at the last update, in meters
+    pub fn current(&self) -> Option<f64> {
+        self.current
+    }
+
+    /// Smallest separation so far, in meters, with the simulated time it was
+    /// seen at
+    pub fn closest(&self) -> Option<(f64, f64)> {
+        self.closest
+    }
+
+    /// Forget the smallest separation, to start over from the next update
+    pub fn reset(&mut self) {
+        self.closest = None;
+    }
+
+    /// Measure the separation after a step at `time`. Returns `false` when
+    /// either body no longer exists, there is nothing left to track.
+    pub fn update(&mut self, bodies: &OrbitalBodies, time: f64) -> bool {
+        let (first, second) = self.pair;
+        let Some((first, second)) = bodies.get_by_id(first).zip(bodies.get_by_id(second)) else {
+            return false;
+        };
+
+        let separation = distance_sq(first, second).sqrt();
+        self.current = Some(separation);
+
+        if self.closest.is_none_or(|(closest, _)| separation < closest) {
+            self.closest = Some((separation, time));
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::body::{Body, OrbitalBodies, bodies_to_map};
+    use crate::constants::AU;
+    use crate::physics::approach::ClosestApproach;
+    use raylib::color::Color;
+
+    #[test]
+    fn test_closest_approach() {
+        let body = |x: f64| Body::new(1., (x, 0.), 1., 1., Color::GRAY, (0., 0.), (0., 0.));
+        let (first, second) = (body(0.), body(3. * AU));
+        let (first_id, second_id) = (first.id(), second.id());
+
+        let mut bodies =
+            OrbitalBodies::new(bodies_to_map(vec![first]), bodies_to_map(vec![second]));
+        let mut approach = ClosestApproach::new(first_id, second_id);
+
+        // Flying by: closer, closest, then away again
+        for (time, x) in [(0., 3.), (1., 1.), (2., 0.5), (3., 2.)] {
+            bodies
+                .get_mut_by_id(second_id)
+                .unwrap()
+                .set_pos((x * AU, 0.));
+            assert!(approach.update(&bodies, time));
+        }
+        assert_eq!(approach.current(), Some(2. * AU));
+        assert_eq!(approach.closest(), Some((0.5 * AU, 2.)));
+
+        approach.reset();
+        assert_eq!(approach.closest(), None);
+
+        bodies
+            .get_mut_by_id(second_id)
+            .unwrap()
+            .set_pos((4. * AU, 0.));
+        assert!(approach.update(&bodies, 4.));
+        assert_eq!(approach.closest(), Some((4. * AU, 4.)));
+
+        bodies.remove(first_id);
+        assert!(!approach.update(&bodies, 5.));
+    }
+}
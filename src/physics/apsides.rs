@@ -0,0 +1,152 @@
+use crate::body::{BodyId, OrbitalBodies};
+use crate::physics::{PhysicsConfig, dominant_attractor, specific_orbital_energy};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ApsisKind {
+    /// Closest point to the attractor
+    Perihelion,
+    /// Farthest point from the attractor
+    Aphelion,
+}
+
+/// A passage of a body at one of its apsides
+#[derive(Debug, Copy, Clone)]
+pub struct Apsis {
+    pub kind: ApsisKind,
+    /// Distance to the attractor in meters
+    pub distance: f64,
+    /// Simulated time of the passage in seconds
+    pub time: f64,
+}
+
+/// Follows a body along its orbit to detect its passages at perihelion and
+/// aphelion, as the sign changes of its radial velocity relative to its
+/// attractor, see [dominant_attractor].
+pub struct ApsisTracker {
+    body: BodyId,
+    previous_radial_velocity: Option<f64>,
+    /// Whether the body is bound to its attractor, an unbound body has no
+    /// aphelion
+    pub bound: bool,
+    pub last_perihelion: Option<Apsis>,
+    pub last_aphelion: Option<Apsis>,
+}
+
+impl ApsisTracker {
+    pub fn new(body: BodyId) -> Self {
+        Self {
+            body,
+            previous_radial_velocity: None,
+            bound: true,
+            last_perihelion: None,
+            last_aphelion: None,
+        }
+    }
+
+    pub fn body(&self) -> BodyId {
+        self.body
+    }
+
+    /// Update the tracker after a step, returns the apsis the body just
+    /// passed, if any. The attractor and whether the body is bound to it are
+    /// taken under `config`.
+    pub fn update(
+        &mut self,
+        bodies: &OrbitalBodies,
+        time: f64,
+        config: &PhysicsConfig,
+    ) -> Option<Apsis> {
+        let body = bodies.get_by_id(self.body)?;
+        let attractor = bodies.get_by_id(dominant_attractor(bodies, self.body, config)?)?;
+
+        let (bx, by) = body.pos();
+        let (ax, ay) = attractor.pos();
+        let (rx, ry) = (bx - ax, by - ay);
+        let (vx, vy) = (
+            body.velocity.0 - attractor.velocity.0,
+            body.velocity.1 - attractor.velocity.1,
+        );
+
+        let distance = (rx.powf(2.) + ry.powf(2.)).sqrt();
+        let radial_velocity = (rx * vx + ry * vy) / distance;
+
+        self.bound = specific_orbital_energy(body, attractor, config.g) < 0.;
+
+        let kind = match self.previous_radial_velocity.replace(radial_velocity) {
+            Some(previous) if previous < 0. && radial_velocity >= 0. => ApsisKind::Perihelion,
+            Some(previous) if previous > 0. && radial_velocity <= 0. => ApsisKind::Aphelion,
+            _ => return None,
+        };
+
+        let apsis = Apsis {
+            kind,
+            distance,
+            time,
+        };
+
+        match kind {
+            ApsisKind::Perihelion => self.last_perihelion = Some(apsis),
+            ApsisKind::Aphelion => self.last_aphelion = Some(apsis),
+        }
+
+        Some(apsis)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::body::{Body, OrbitalBodies, bodies_to_map};
+    use crate::constants::{AU, SUN_MASS};
+    use crate::physics::apsides::{ApsisKind, ApsisTracker};
+    use crate::physics::leapfrog::Leapfrog;
+    use crate::physics::{
+        G, Gravity, Kinematics, OrbitParameters, PhysicsConfig, kepler_orbit, update_acceleration,
+    };
+    use raylib::color::Color;
+
+    #[test]
+    fn test_apsides_of_elliptic_orbit() {
+        let sun = Body::new(
+            SUN_MASS,
+            (0., 0.),
+            1.,
+            1.,
+            Color::YELLOW,
+            (0., 0.),
+            (0., 0.),
+        );
+        let mut comet = Body::new(1., (0., 0.), 1., 1., Color::WHITE, (0., 0.), (0., 0.));
+        kepler_orbit(
+            OrbitParameters {
+                a: AU,
+                e: 0.5,
+                theta: std::f64::consts::FRAC_PI_2,
+            },
+            &mut comet,
+            &sun,
+            G,
+        );
+        let comet_id = comet.id();
+
+        let mut bodies = OrbitalBodies::new(bodies_to_map(vec![sun, comet]), bodies_to_map(vec![]));
+        update_acceleration(&mut bodies, &mut 0., &Gravity::Exact.into());
+
+        let config = PhysicsConfig::from(Gravity::Exact);
+        let mut tracker = ApsisTracker::new(comet_id);
+        let mut passages = vec![];
+        let dt = 3600.;
+        for step in 1..12_000 {
+            Leapfrog.step(&mut bodies, dt, &Gravity::Exact.into());
+            passages.extend(tracker.update(&bodies, step as f64 * dt, &config));
+        }
+
+        assert!(tracker.bound);
+        let kinds = passages.iter().map(|apsis| apsis.kind).collect::<Vec<_>>();
+        assert_eq!(kinds, vec![ApsisKind::Aphelion, ApsisKind::Perihelion]);
+
+        let perihelion = tracker.last_perihelion.unwrap().distance;
+        let aphelion = tracker.last_aphelion.unwrap().distance;
+        assert!((perihelion - 0.5 * AU).abs() / AU < 1e-3);
+        assert!((aphelion - 1.5 * AU).abs() / AU < 1e-3);
+    }
+}
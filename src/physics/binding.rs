@@ -0,0 +1,148 @@
+use crate::body::{BodyId, OrbitalBodies};
+use crate::physics::{PhysicsConfig, dominant_attractor, specific_orbital_energy};
+
+/// A change in whether a body is bound to its attractor
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum BindingTransition {
+    /// The body fell into a closed orbit around the attractor
+    Captured,
+    /// The body broke free of the attractor
+    Escaped,
+    /// One of the pair no longer exists, nothing more will be reported
+    Lost,
+}
+
+/// Watches a pair of bodies for the moments the first one becomes bound to
+/// the second, or unbound from it, from their two-body energy.
+pub struct BindingWatcher {
+    pub body: BodyId,
+    pub attractor: BodyId,
+    /// Whether the body was bound at the last update, `None` before the first
+    /// one and once the pair is lost
+    bound: Option<bool>,
+    lost: bool,
+}
+
+impl BindingWatcher {
+    pub fn new(body: BodyId, attractor: BodyId) -> Self {
+        Self {
+            body,
+            attractor,
+            bound: None,
+            lost: false,
+        }
+    }
+
+    /// Watch the body and the one it orbits under `config`, see
+    /// [dominant_attractor]: the Earth for the Moon. `None` when there is no
+    /// such body.
+    pub fn around_attractor(
+        bodies: &OrbitalBodies,
+        body: BodyId,
+        config: &PhysicsConfig,
+    ) -> Option<Self> {
+        dominant_attractor(bodies, body, config).map(|attractor| Self::new(body, attractor))
+    }
+
+    /// Whether the body was bound at the last update
+    pub fn bound(&self) -> Option<bool> {
+        self.bound
+    }
+
+    /// Update the watcher after a step, returns the transition that just
+    /// happened, if any, under the gravity constant `g`. The first update
+    /// only records the initial state.
+    pub fn update(&mut self, bodies: &OrbitalBodies, g: f64) -> Option<BindingTransition> {
+        if self.lost {
+            return None;
+        }
+
+        let pair = bodies
+            .get_by_id(self.body)
+            .zip(bodies.get_by_id(self.attractor));
+
+        let Some((body, attractor)) = pair else {
+            self.lost = true;
+            self.bound = None;
+            return Some(BindingTransition::Lost);
+        };
+
+        let bound = specific_orbital_energy(body, attractor, g) < 0.;
+
+        match self.bound.replace(bound) {
+            Some(false) if bound => Some(BindingTransition::Captured),
+            Some(true) if !bound => Some(BindingTransition::Escaped),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::body::{Body, OrbitalBodies, bodies_to_map};
+    use crate::constants::{
+        EARTH_MASS, EARTH_MOON_DISTANCE, EARTH_SUN_VELOCITY, MOON_EARTH_VELOCITY, MOON_MASS,
+        SUN_EARTH_DISTANCE, SUN_MASS,
+    };
+    use crate::physics::binding::{BindingTransition, BindingWatcher};
+    use crate::physics::{G, PhysicsConfig};
+    use raylib::color::Color;
+
+    #[test]
+    fn test_moon_escape() {
+        let sun = Body::new(
+            SUN_MASS,
+            (0., 0.),
+            1.,
+            1.,
+            Color::YELLOW,
+            (0., 0.),
+            (0., 0.),
+        );
+        let earth = Body::new(
+            EARTH_MASS,
+            (0., SUN_EARTH_DISTANCE),
+            1.,
+            1.,
+            Color::BLUE,
+            (EARTH_SUN_VELOCITY, 0.),
+            (0., 0.),
+        );
+        let moon = Body::new(
+            MOON_MASS,
+            (0., SUN_EARTH_DISTANCE + EARTH_MOON_DISTANCE),
+            1.,
+            1.,
+            Color::GRAY,
+            (EARTH_SUN_VELOCITY + MOON_EARTH_VELOCITY, 0.),
+            (0., 0.),
+        );
+        let (earth_id, moon_id) = (earth.id(), moon.id());
+
+        let mut bodies =
+            OrbitalBodies::new(bodies_to_map(vec![sun, earth, moon]), bodies_to_map(vec![]));
+
+        // The Moon is within the Earth's Hill sphere
+        let mut watcher =
+            BindingWatcher::around_attractor(&bodies, moon_id, &PhysicsConfig::default()).unwrap();
+        assert_eq!(watcher.attractor, earth_id);
+
+        assert_eq!(watcher.update(&bodies, G), None);
+        assert_eq!(watcher.bound(), Some(true));
+
+        // Kicked well past the escape velocity from the Earth
+        bodies.get_mut_by_id(moon_id).unwrap().velocity.0 += 2_000.;
+        assert_eq!(watcher.update(&bodies, G), Some(BindingTransition::Escaped));
+        assert_eq!(watcher.update(&bodies, G), None);
+
+        bodies.get_mut_by_id(moon_id).unwrap().velocity.0 -= 2_000.;
+        assert_eq!(
+            watcher.update(&bodies, G),
+            Some(BindingTransition::Captured)
+        );
+
+        bodies.remove(earth_id);
+        assert_eq!(watcher.update(&bodies, G), Some(BindingTransition::Lost));
+        assert_eq!(watcher.update(&bodies, G), None);
+    }
+}
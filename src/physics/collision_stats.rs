@@ -0,0 +1,94 @@
+use crate::body::BodyId;
+use crate::physics::collisions::CollisionEvent;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::Path;
+
+/// Merges and shatters accumulated over a simulation, kept to study the
+/// accretion rates. Fed by [crate::physics::collisions::handle_collisions].
+#[derive(Debug, Clone, Default)]
+pub struct CollisionStats {
+    /// Number of merges since the start
+    pub merges: usize,
+    /// Number of pairs of bodies shattered since the start
+    pub shatters: usize,
+    /// Mass each tier 0 body gained in the merges it survived, in Kg
+    accreted: HashMap<BodyId, f64>,
+    /// Merges and shatters of the step in progress
+    step_collisions: (usize, usize),
+    /// Simulated time, number of merges and of shatters of each step that
+    /// had any
+    history: Vec<(f64, usize, usize)>,
+}
+
+impl CollisionStats {
+    /// Count a merge, and the mass the survivor gained when it is a tier 0
+    /// body, or a shatter, see [CollisionEvent::fragments]
+    pub fn record(&mut self, event: &CollisionEvent, tier0_survivor: bool) {
+        if !event.fragments.is_empty() {
+            self.shatters += 1;
+            self.step_collisions.1 += 1;
+            return;
+        }
+
+        self.merges += 1;
+        self.step_collisions.0 += 1;
+
+        if tier0_survivor {
+            *self.accreted.entry(event.survivor).or_default() += event.destroyed_mass;
+        }
+    }
+
+    /// Close the step that ended at `time`, in simulated seconds
+    pub fn end_step(&mut self, time: f64) {
+        let (merges, shatters) = std::mem::take(&mut self.step_collisions);
+        if merges + shatters > 0 {
+            self.history.push((time, merges, shatters));
+        }
+    }
+
+    /// Mass the tier 0 body gained in merges, in Kg
+    pub fn accreted(&self, body: BodyId) -> f64 {
+        self.accreted.get(&body).copied().unwrap_or(0.)
+    }
+
+    /// The tier 0 bodies that gained mass, with how much, by id
+    pub fn accretion(&self) -> Vec<(BodyId, f64)> {
+        let mut accretion = self
+            .accreted
+            .iter()
+            .map(|(&id, &mass)| (id, mass))
+            .collect::<Vec<_>>();
+        accretion.sort_by_key(|&(id, _)| id);
+        accretion
+    }
+
+    /// The merges and shatters per step as a CSV table, `time` in seconds
+    pub fn format_history_csv(&self) -> String {
+        let mut csv = "time,merges,shatters\n".to_string();
+        for (time, merges, shatters) in &self.history {
+            let _ = writeln!(csv, "{time},{merges},{shatters}");
+        }
+        csv
+    }
+
+    /// The mass accreted by each tier 0 body as a CSV table, in Kg
+    pub fn format_accretion_csv(&self) -> String {
+        let mut csv = "body,accreted_mass\n".to_string();
+        for (id, mass) in self.accretion() {
+            let _ = writeln!(csv, "{id},{mass:e}");
+        }
+        csv
+    }
+
+    /// Save both tables to disk, as `<prefix>_merges.csv` and
+    /// `<prefix>_accretion.csv`
+    pub fn save_csv(&self, prefix: impl AsRef<Path>) -> std::io::Result<()> {
+        let prefix = prefix.as_ref().display();
+        std::fs::write(format!("{prefix}_merges.csv"), self.format_history_csv())?;
+        std::fs::write(
+            format!("{prefix}_accretion.csv"),
+            self.format_accretion_csv(),
+        )
+    }
+}
@@ -1,87 +1,453 @@
-use crate::body::{Body, BodyId, OrbitalBodies};
-use crate::constants::AU;
-use crate::physics::distance;
+use crate::body::{Body, BodyId, OrbitalBodies, TrailParameter};
+use crate::physics::PhysicsConfig;
+use crate::physics::collision_stats::CollisionStats;
 use kdtree::distance::squared_euclidean;
+use raylib::color::Color;
 use rayon::prelude::*;
 use std::cmp::Ordering;
-use std::collections::HashSet;
-use std::sync::Arc;
-use std::sync::RwLock;
+use std::collections::{HashMap, HashSet};
+use std::f64::consts::PI;
+use std::sync::mpsc::Sender;
 
-const MAX_DISTANCE_DEFAULT: f64 = AU * 10.;
-const BIN_WIDTH_DEFAULT: f64 = AU / 2.;
+/// Most bodies checked pairwise together, denser bins are split in quadrants
+const MAX_BIN_BODIES: usize = 64;
+
+/// Most times a dense bin is split, bodies closer than that stay together
+const MAX_SPLIT_DEPTH: usize = 12;
+
+/// Specific energy holding a body together by the strength of its material
+/// alone, in J/Kg. Large bodies are mostly held together by their gravity,
+/// see [binding_energy].
+const MATERIAL_BINDING_ENERGY: f64 = 1E5;
+
+/// Most fragments a shattered pair of bodies breaks into
+const MAX_FRAGMENTS: usize = 8;
+
+/// The k-th largest fragment has a mass proportional to `k^-exponent`
+const FRAGMENT_MASS_EXPONENT: f64 = 1.5;
 
 struct BinBodiesParam {
     max_distance: f64,
     bin_width: f64,
 }
 
-impl Default for BinBodiesParam {
-    fn default() -> Self {
+impl From<&PhysicsConfig> for BinBodiesParam {
+    fn from(config: &PhysicsConfig) -> Self {
         Self {
-            max_distance: MAX_DISTANCE_DEFAULT,
-            bin_width: BIN_WIDTH_DEFAULT,
+            max_distance: config.collision_max_distance,
+            bin_width: config.collision_bin_width,
         }
     }
 }
 
+impl Default for BinBodiesParam {
+    fn default() -> Self {
+        (&PhysicsConfig::default()).into()
+    }
+}
+
+/// Condition under which two overlapping bodies merge
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum MergeCondition {
+    /// Merge as soon as the bodies overlap
+    #[default]
+    Overlap,
+    /// Merge only if the bodies overlap and are getting closer, which avoids
+    /// spurious merges of bodies separating after a near miss at large dt
+    Approaching,
+}
+
+/// A merge or a shatter of two bodies, reported to the optional sender of
+/// [handle_collisions] so that an embedding application can react to it
+#[derive(Debug, Clone, PartialEq)]
+pub struct CollisionEvent {
+    /// Body that absorbed the other one, the heavier one of a shattered pair
+    pub survivor: BodyId,
+    pub destroyed: BodyId,
+    /// Names of the bodies, see [Body::name]
+    pub survivor_name: String,
+    pub destroyed_name: String,
+    /// Mass of the survivor before the merge, in Kg
+    pub survivor_mass: f64,
+    pub destroyed_mass: f64,
+    /// Center of mass of the pair at the impact
+    pub position: (f64, f64),
+    /// Kinetic energy turned into heat by the impact, in J
+    pub energy: f64,
+    /// Bodies the pair shattered into, the largest first, empty for a merge
+    pub fragments: Vec<BodyId>,
+}
+
+/// What the collisions of a step did, returned by [handle_collisions]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CollisionOutcome {
+    /// Kinetic energy lost in the merges and shatters, in J
+    pub dissipated_energy: f64,
+    /// The body each body destroyed in a merge now lives on as, to follow
+    /// it through the accretion, the largest fragment for a shattered one. A
+    /// body absorbed by one absorbed in turn in the same step maps to the
+    /// last survivor.
+    pub merged_into: HashMap<BodyId, BodyId>,
+    /// Number of pairs of bodies shattered into fragments
+    pub shattered: usize,
+}
+
+/// The body left by a merge, see [compute_merger]
+struct Merger {
+    new_mass: f64,
+    new_velocity: (f64, f64),
+    /// Kinetic energy lost in the inelastic merge, in J
+    dissipated_energy: f64,
+    event: CollisionEvent,
+}
+
 enum CollisionResult {
-    Merge {
-        body_id: BodyId,
-        new_mass: f64,
-        new_velocity: (f64, f64),
-    },
-    Destroyed {
-        body_id: BodyId,
+    Merge(Merger),
+    /// Both bodies shatter into fragments, all in tier 1
+    Fragment {
+        body_ids: (BodyId, BodyId),
+        fragments: Vec<Fragment>,
+        color: Color,
+        /// See [Body::theme_color]
+        theme_color: bool,
+        /// Kinetic energy of the impact lost to breaking the bodies apart, in
+        /// J, turned into heat evenly across the fragments
+        dissipated_energy: f64,
     },
 }
 
-fn collides(body1: &Body, body2: &Body) -> bool {
-    let (_, dist) = distance(body1, body2);
-    dist <= (body1.physical_radius + body2.physical_radius)
+/// A piece of a shattered body, the largest ones first
+struct Fragment {
+    mass: f64,
+    pos: (f64, f64),
+    velocity: (f64, f64),
+    physical_radius: f64,
+}
+
+impl CollisionResult {
+    fn body_id(&self) -> BodyId {
+        match self {
+            CollisionResult::Merge(merger) => merger.event.survivor,
+            CollisionResult::Fragment { body_ids, .. } => body_ids.0.min(body_ids.1),
+        }
+    }
+
+    /// Rank of the kind of result, in the order they are applied to a body
+    fn kind_rank(&self) -> u8 {
+        match self {
+            CollisionResult::Merge(_) => 0,
+            CollisionResult::Fragment { .. } => 1,
+        }
+    }
+
+    /// Total order of the results, by body and then merges and
+    /// fragmentations. Merges into the same body are ordered by their
+    /// resulting mass, so that they add up the same way whatever order they
+    /// were found in.
+    fn order(&self, other: &Self) -> Ordering {
+        let by_kind = match (self, other) {
+            (CollisionResult::Merge(m1), CollisionResult::Merge(m2)) => m1
+                .new_mass
+                .total_cmp(&m2.new_mass)
+                .then(m1.new_velocity.0.total_cmp(&m2.new_velocity.0))
+                .then(m1.new_velocity.1.total_cmp(&m2.new_velocity.1))
+                .then(m1.dissipated_energy.total_cmp(&m2.dissipated_energy)),
+            (
+                CollisionResult::Fragment { body_ids: ids1, .. },
+                CollisionResult::Fragment { body_ids: ids2, .. },
+            ) => ids1.0.max(ids1.1).cmp(&ids2.0.max(ids2.1)),
+            _ => self.kind_rank().cmp(&other.kind_rank()),
+        };
+
+        self.body_id().cmp(&other.body_id()).then(by_kind)
+    }
+}
+
+/// How close two bodies must have come during the last step to collide
+#[derive(Debug, Copy, Clone)]
+struct Contact {
+    /// See [PhysicsConfig::accretion_factor]
+    accretion_factor: f64,
+    /// Duration of the last step, in seconds
+    dt: f64,
+    /// See [PhysicsConfig::collision_substeps]
+    substeps: usize,
+    /// See [PhysicsConfig::g], for the binding energy of the bodies
+    g: f64,
+    /// See [PhysicsConfig::fragmentation]
+    fragmentation: bool,
+}
+
+impl Contact {
+    fn new(config: &PhysicsConfig, dt: f64) -> Self {
+        Self {
+            accretion_factor: config.accretion_factor,
+            dt,
+            substeps: config.collision_substeps,
+            g: config.g,
+            fragmentation: config.fragmentation,
+        }
+    }
+}
+
+impl Default for Contact {
+    fn default() -> Self {
+        Self::new(&PhysicsConfig::default(), 0.)
+    }
+}
+
+/// Separation of the bodies, from the second one to the first one, at the
+/// earliest point they are within their combined radii, scaled by the
+/// accretion factor, of each other. The points are spread evenly along the
+/// last step, from its end back, assuming the bodies moved in a straight
+/// line. `None` when they are apart at all of them, or in different groups,
+/// see [Body::group_id].
+fn contact_separation(body1: &Body, body2: &Body, contact: Contact) -> Option<(f64, f64)> {
+    if body1.group_id != body2.group_id {
+        return None;
+    }
+
+    let reach = (body1.physical_radius + body2.physical_radius) * contact.accretion_factor;
+    let ((x1, y1), (x2, y2)) = (body1.pos(), body2.pos());
+    let (vx, vy) = (
+        body1.velocity.0 - body2.velocity.0,
+        body1.velocity.1 - body2.velocity.1,
+    );
+    let substeps = contact.substeps.max(1);
+
+    (0..substeps).rev().find_map(|k| {
+        let back = contact.dt * k as f64 / substeps as f64;
+        let (dx, dy) = (x1 - x2 - vx * back, y1 - y2 - vy * back);
+
+        (dx.powf(2.) + dy.powf(2.) <= reach.powf(2.)).then_some((dx, dy))
+    })
+}
+
+/// Whether the bodies are getting closer when `separation` apart, that is
+/// their relative velocity points against their separation
+fn approaching(body1: &Body, body2: &Body, separation: (f64, f64)) -> bool {
+    let (dx, dy) = separation;
+    let (vx1, vy1) = body1.velocity;
+    let (vx2, vy2) = body2.velocity;
+
+    (vx1 - vx2) * dx + (vy1 - vy2) * dy < 0.
 }
 
-fn compute_merger(winner: &Body, destroyed: &Body) -> CollisionResult {
+/// The survivor and the absorbed body of a merge: the heaviest body wins,
+/// the lowest id on a tie, so that the outcome does not depend on the order
+/// the pair is checked in
+fn merge_roles<'a>(body1: &'a Body, body2: &'a Body) -> (&'a Body, &'a Body) {
+    let body1_wins = match body1.mass.total_cmp(&body2.mass) {
+        Ordering::Greater => true,
+        Ordering::Less => false,
+        Ordering::Equal => body1.id() < body2.id(),
+    };
+
+    match body1_wins {
+        true => (body1, body2),
+        false => (body2, body1),
+    }
+}
+
+fn compute_merger(winner: &Body, destroyed: &Body) -> Merger {
     let new_mass = winner.mass + destroyed.mass;
 
-    let (vx1, vy1) = winner.velocity;
-    let (vx2, vy2) = destroyed.velocity;
+    let (px1, py1) = winner.momentum();
+    let (px2, py2) = destroyed.momentum();
+
+    let vx = (px1 + px2) / new_mass;
+    let vy = (py1 + py2) / new_mass;
+
+    let kinetic_energy_after = 0.5 * new_mass * (vx.powf(2.) + vy.powf(2.));
+    let dissipated_energy =
+        winner.kinetic_energy() + destroyed.kinetic_energy() - kinetic_energy_after;
 
-    let vx = ((vx1 * winner.mass) + (vx2 * destroyed.mass)) / new_mass;
-    let vy = ((vy1 * winner.mass) + (vy2 * destroyed.mass)) / new_mass;
+    let ((x1, y1), (x2, y2)) = (winner.pos(), destroyed.pos());
+    let position = (
+        (winner.mass * x1 + destroyed.mass * x2) / new_mass,
+        (winner.mass * y1 + destroyed.mass * y2) / new_mass,
+    );
 
-    CollisionResult::Merge {
-        body_id: winner.id(),
+    Merger {
         new_mass,
         new_velocity: (vx, vy),
+        dissipated_energy,
+        event: CollisionEvent {
+            survivor: winner.id(),
+            destroyed: destroyed.id(),
+            survivor_name: winner.name(),
+            destroyed_name: destroyed.name(),
+            survivor_mass: winner.mass,
+            destroyed_mass: destroyed.mass,
+            position,
+            energy: dissipated_energy,
+            fragments: vec![],
+        },
     }
 }
 
-fn append_collision(body1: &Body, body2: &Body, collisions: &mut Vec<CollisionResult>) {
-    if collides(body1, body2) {
-        let (largest, smallest) = if body1.mass > body2.mass {
-            (body1, body2)
-        } else {
-            (body2, body1)
-        };
+/// Specific energy needed to shatter the bodies, in J/Kg: the strength of
+/// their material plus the gravitational binding energy of their union
+fn binding_energy(body1: &Body, body2: &Body, g: f64) -> f64 {
+    let mass = body1.mass + body2.mass;
+    let radius = (body1.physical_radius.powf(3.) + body2.physical_radius.powf(3.)).cbrt();
 
-        collisions.push(CollisionResult::Destroyed {
-            body_id: smallest.id(),
-        });
+    MATERIAL_BINDING_ENERGY + 3. / 5. * g * mass / radius
+}
+
+/// Shatter the bodies when the specific energy of their impact exceeds their
+/// binding energy under the gravity constant `g`, `None` when they merge
+/// instead.
+///
+/// The fragments follow a power law in mass, and are spread evenly around
+/// the center of mass, moving away from it. The impact energy left over
+/// after breaking the bodies apart is split between the fragments, on top of
+/// the velocity of the center of mass. The total mass and momentum are
+/// conserved, the kinetic energy lost is dissipated.
+fn compute_fragmentation(body1: &Body, body2: &Body, g: f64) -> Option<CollisionResult> {
+    let mass = body1.mass + body2.mass;
+    let reduced_mass = body1.mass * body2.mass / mass;
+
+    let relative_velocity = (
+        body1.velocity.0 - body2.velocity.0,
+        body1.velocity.1 - body2.velocity.1,
+    );
+    let impact_energy =
+        0.5 * reduced_mass * (relative_velocity.0.powf(2.) + relative_velocity.1.powf(2.)) / mass;
+
+    let binding_energy = binding_energy(body1, body2, g);
+    if impact_energy <= binding_energy {
+        return None;
+    }
+
+    let count = ((impact_energy / binding_energy) as usize).clamp(2, MAX_FRAGMENTS);
+
+    let weights = (1..=count)
+        .map(|k| (k as f64).powf(-FRAGMENT_MASS_EXPONENT))
+        .collect::<Vec<_>>();
+    let total_weight = weights.iter().sum::<f64>();
+
+    let center_of_mass = |of: fn(&Body) -> (f64, f64)| {
+        let ((x1, y1), (x2, y2)) = (of(body1), of(body2));
+        (
+            (x1 * body1.mass + x2 * body2.mass) / mass,
+            (y1 * body1.mass + y2 * body2.mass) / mass,
+        )
+    };
+    let center = center_of_mass(Body::pos);
+    let velocity = center_of_mass(|body| body.velocity);
+
+    let radius = (body1.physical_radius.powf(3.) + body2.physical_radius.powf(3.)).cbrt();
+    let ejection_speed = (2. * (impact_energy - binding_energy)).sqrt();
+    let impact_angle = relative_velocity.1.atan2(relative_velocity.0);
+
+    let mut fragments = weights
+        .iter()
+        .enumerate()
+        .map(|(k, weight)| {
+            let fragment_mass = mass * weight / total_weight;
+            let angle = impact_angle + 2. * PI * k as f64 / count as f64;
+            let (cos, sin) = (angle.cos(), angle.sin());
+
+            Fragment {
+                mass: fragment_mass,
+                pos: (2. * radius * cos, 2. * radius * sin),
+                velocity: (ejection_speed * cos, ejection_speed * sin),
+                physical_radius: radius * (weight / total_weight).cbrt(),
+            }
+        })
+        .collect::<Vec<_>>();
 
-        collisions.push(compute_merger(largest, smallest));
+    // The fragments are unequal, move them around so that their center of
+    // mass and momentum are those of the bodies
+    let mean = |of: fn(&Fragment) -> (f64, f64)| {
+        fragments.iter().fold((0., 0.), |(x, y), fragment| {
+            let (fx, fy) = of(fragment);
+            (x + fx * fragment.mass / mass, y + fy * fragment.mass / mass)
+        })
+    };
+    let mean_pos = mean(|fragment| fragment.pos);
+    let mean_velocity = mean(|fragment| fragment.velocity);
+
+    for fragment in fragments.iter_mut() {
+        fragment.pos = (
+            center.0 + fragment.pos.0 - mean_pos.0,
+            center.1 + fragment.pos.1 - mean_pos.1,
+        );
+        fragment.velocity = (
+            velocity.0 + fragment.velocity.0 - mean_velocity.0,
+            velocity.1 + fragment.velocity.1 - mean_velocity.1,
+        );
     }
+
+    // The binding energy, and what moving the fragments back to the momentum
+    // of the bodies took away
+    let kinetic_energy_after = fragments
+        .iter()
+        .map(|fragment| {
+            let (vx, vy) = fragment.velocity;
+            0.5 * fragment.mass * (vx.powf(2.) + vy.powf(2.))
+        })
+        .sum::<f64>();
+    let dissipated_energy = body1.kinetic_energy() + body2.kinetic_energy() - kinetic_energy_after;
+
+    let largest = if body1.mass >= body2.mass {
+        body1
+    } else {
+        body2
+    };
+
+    Some(CollisionResult::Fragment {
+        body_ids: (body1.id(), body2.id()),
+        fragments,
+        color: largest.color,
+        theme_color: largest.theme_color,
+        dissipated_energy,
+    })
 }
 
-fn compute_pairwise_collision_slice(bodies: &[&Body]) -> Vec<CollisionResult> {
+fn append_collision(
+    body1: &Body,
+    body2: &Body,
+    merge_condition: MergeCondition,
+    contact: Contact,
+    collisions: &mut Vec<CollisionResult>,
+) {
+    let separation = contact_separation(body1, body2, contact);
+    let merges = match (merge_condition, separation) {
+        (_, None) => false,
+        (MergeCondition::Overlap, Some(_)) => true,
+        (MergeCondition::Approaching, Some(separation)) => approaching(body1, body2, separation),
+    };
+
+    if merges {
+        if contact.fragmentation
+            && let Some(fragmentation) = compute_fragmentation(body1, body2, contact.g)
+        {
+            collisions.push(fragmentation);
+            return;
+        }
+
+        let (largest, smallest) = merge_roles(body1, body2);
+        collisions.push(CollisionResult::Merge(compute_merger(largest, smallest)));
+    }
+}
+
+fn compute_pairwise_collision_slice(
+    bodies: &[&Body],
+    merge_condition: MergeCondition,
+    contact: Contact,
+) -> Vec<CollisionResult> {
     let mut collisions = vec![];
     for i in 0..bodies.len() {
-        for j in 0..bodies.len() {
-            if i == j {
-                continue;
-            }
-
-            append_collision(bodies[i], bodies[j], &mut collisions);
+        for j in i + 1..bodies.len() {
+            append_collision(
+                bodies[i],
+                bodies[j],
+                merge_condition,
+                contact,
+                &mut collisions,
+            );
         }
     }
 
@@ -90,9 +456,13 @@ fn compute_pairwise_collision_slice(bodies: &[&Body]) -> Vec<CollisionResult> {
 
 /// Check all pairs of bodies and returns the list of results
 #[allow(unused)]
-fn compute_pairwise_collisions(orbital_bodies: &OrbitalBodies) -> Vec<CollisionResult> {
+fn compute_pairwise_collisions(
+    orbital_bodies: &OrbitalBodies,
+    merge_condition: MergeCondition,
+    contact: Contact,
+) -> Vec<CollisionResult> {
     let bodies = orbital_bodies.iter().collect::<Vec<_>>();
-    compute_pairwise_collision_slice(bodies.as_slice())
+    compute_pairwise_collision_slice(bodies.as_slice(), merge_condition, contact)
 }
 
 /// Bins of fixed width
@@ -107,7 +477,7 @@ fn bin_bodies(orbital_bodies: &OrbitalBodies, params: BinBodiesParam) -> Vec<Has
         (x.powf(2.) + y.powf(2.)).sqrt() < max_distance
     };
 
-    let width = orbital_bodies
+    let farthest = orbital_bodies
         .iter()
         .filter(|b| body_in_range(b))
         .map(|body| body.pos())
@@ -122,9 +492,14 @@ fn bin_bodies(orbital_bodies: &OrbitalBodies, params: BinBodiesParam) -> Vec<Has
             } else {
                 Ordering::Less
             }
-        })
-        .unwrap()
-        * 2.0;
+        });
+
+    // Nothing in range, nothing to bin
+    let Some(farthest) = farthest else {
+        return vec![];
+    };
+
+    let width = farthest * 2.0;
 
     let bin_count = (width / bin_width) as usize;
 
@@ -168,52 +543,146 @@ fn bin_bodies(orbital_bodies: &OrbitalBodies, params: BinBodiesParam) -> Vec<Has
     bins
 }
 
+/// Collisions of the bodies near each other in a kd-tree. The bodies with a
+/// non-finite position, after a diverging step, cannot be placed in the tree
+/// and are skipped. Returns the collisions, and how many bodies were skipped
+/// for the caller to report.
 #[allow(unused)]
-fn compute_kdtree_collisions(orbital_bodies: &OrbitalBodies) -> Vec<CollisionResult> {
-    let collisions = Arc::new(RwLock::new(vec![]));
-    let kd = Arc::new(RwLock::new(kdtree::KdTree::new(2)));
+fn compute_kdtree_collisions(
+    orbital_bodies: &OrbitalBodies,
+    merge_condition: MergeCondition,
+    contact: Contact,
+) -> (Vec<CollisionResult>, usize) {
+    let bodies = orbital_bodies
+        .iter()
+        .filter(|body| body.pos_arr().iter().all(|coord| coord.is_finite()))
+        .collect::<Vec<_>>();
+    let skipped = orbital_bodies.len() - bodies.len();
 
-    {
-        let mut kd = kd.write().unwrap();
-        for body in orbital_bodies.iter() {
-            kd.add(body.pos_arr(), body).unwrap();
+    let mut kd = kdtree::KdTree::new(2);
+    for body in &bodies {
+        kd.add(body.pos_arr(), *body).unwrap();
+    }
+
+    let neighbours = bodies
+        .par_iter()
+        .flat_map_iter(|body| {
+            kd.nearest(&body.pos_arr(), 100, &squared_euclidean)
+                .unwrap()
+                .into_iter()
+                .filter(|(_, other)| other.id() != body.id())
+                .map(|(_, other)| (*body, *other))
+        })
+        .collect::<Vec<_>>();
+
+    // The pairs checked along with the collisions, as a pair can be among
+    // the nearest neighbours of only one of its bodies
+    let mut checked = HashSet::new();
+    let mut collisions = vec![];
+    for (body, other) in neighbours {
+        let (id1, id2) = (body.id(), other.id());
+        if checked.insert((id1.min(id2), id1.max(id2))) {
+            append_collision(body, other, merge_condition, contact, &mut collisions);
         }
     }
 
-    let bodies = orbital_bodies.iter().collect::<Vec<_>>();
+    (collisions, skipped)
+}
+
+/// Split the bodies of a bin in quadrants, and again, until each group is
+/// small enough to check pairwise. A body overlapping a split line goes in
+/// each quadrant it touches, so no collision is missed. Bodies packed too
+/// tightly to be separated are left together.
+fn split_dense_bin(bodies: Vec<&Body>, depth: usize) -> Vec<Vec<&Body>> {
+    if bodies.len() <= MAX_BIN_BODIES || depth >= MAX_SPLIT_DEPTH {
+        return vec![bodies];
+    }
 
-    bodies.par_iter().for_each({
-        let kd = kd.clone();
-        let collisions = collisions.clone();
+    let (min, max) = bodies.iter().map(|body| body.pos()).fold(
+        (
+            (f64::INFINITY, f64::INFINITY),
+            (f64::NEG_INFINITY, f64::NEG_INFINITY),
+        ),
+        |((min_x, min_y), (max_x, max_y)), (x, y)| {
+            ((min_x.min(x), min_y.min(y)), (max_x.max(x), max_y.max(y)))
+        },
+    );
+    let (mid_x, mid_y) = ((min.0 + max.0) / 2., (min.1 + max.1) / 2.);
 
-        move |body| {
-            let kd = kd.read().unwrap();
+    let mut quadrants: [Vec<&Body>; 4] = Default::default();
+    for body in &bodies {
+        let (x, y) = body.pos();
+        let r = body.physical_radius;
 
-            let neigh = kd
-                .nearest(&body.pos_arr(), 100, &squared_euclidean)
-                .unwrap();
+        for (quadrant, (left, top)) in [(true, true), (false, true), (true, false), (false, false)]
+            .into_iter()
+            .enumerate()
+        {
+            let in_x = if left { x - r <= mid_x } else { x + r > mid_x };
+            let in_y = if top { y - r <= mid_y } else { y + r > mid_y };
 
-            neigh.par_iter().for_each({
-                let collisions = collisions.clone();
-                move |(_, other)| {
-                    if body.id() == other.id() {
-                        return;
-                    }
+            if in_x && in_y {
+                quadrants[quadrant].push(body);
+            }
+        }
+    }
+
+    // Nothing was separated, splitting further would not help
+    if quadrants
+        .iter()
+        .any(|quadrant| quadrant.len() == bodies.len())
+    {
+        return vec![bodies];
+    }
 
-                    let mut collisions = collisions.write().unwrap();
-                    append_collision(body, other, &mut collisions);
+    quadrants
+        .into_iter()
+        .filter(|quadrant| quadrant.len() > 1)
+        .flat_map(|quadrant| split_dense_bin(quadrant, depth + 1))
+        .collect()
+}
+
+/// Collisions of the bodies of each group, see [split_dense_bin]. A pair
+/// found in several groups, both bodies overlapping a split line, is only
+/// checked once, else its merge would be applied once per group.
+fn compute_group_collisions(
+    groups: &[Vec<&Body>],
+    merge_condition: MergeCondition,
+    contact: Contact,
+) -> Vec<CollisionResult> {
+    let mut checked = HashSet::new();
+    let mut collisions = vec![];
+
+    for group in groups {
+        for i in 0..group.len() {
+            for j in i + 1..group.len() {
+                let (id1, id2) = (group[i].id(), group[j].id());
+                if checked.insert((id1.min(id2), id1.max(id2))) {
+                    append_collision(
+                        group[i],
+                        group[j],
+                        merge_condition,
+                        contact,
+                        &mut collisions,
+                    );
                 }
-            });
+            }
         }
-    });
+    }
 
-    vec![]
+    collisions
 }
 
 /// Compute collisions using spatial hashing
 #[allow(unused)]
-fn compute_collisions_spatial_hash(orbital_bodies: &OrbitalBodies) -> Vec<CollisionResult> {
-    let bins = bin_bodies(orbital_bodies, BinBodiesParam::default());
+fn compute_collisions_spatial_hash(
+    orbital_bodies: &OrbitalBodies,
+    merge_condition: MergeCondition,
+    config: &PhysicsConfig,
+    dt: f64,
+) -> Vec<CollisionResult> {
+    let bins = bin_bodies(orbital_bodies, config.into());
+    let contact = Contact::new(config, dt);
 
     bins.par_iter()
         .map(|bin| {
@@ -222,21 +691,34 @@ fn compute_collisions_spatial_hash(orbital_bodies: &OrbitalBodies) -> Vec<Collis
                 .map(|id| orbital_bodies.get_by_id(*id).unwrap())
                 .collect::<Vec<_>>();
 
-            compute_pairwise_collision_slice(bodies.as_slice())
+            compute_group_collisions(&split_dense_bin(bodies, 0), merge_condition, contact)
         })
         .flatten()
         .collect::<Vec<_>>()
 }
 
-/// Handle the collisions for the orbital system
-pub fn handle_collisions(orbital_bodies: &mut OrbitalBodies) {
+/// Handle the collisions for the orbital system, after a step of `dt`
+/// seconds, sending each merge to `events` when given.
+///
+/// Only the bodies sorted in the same bin at the end of the step are checked
+/// against each other, however many [PhysicsConfig::collision_substeps].
+///
+/// Returns the energy lost in the merges and which body absorbed which.
+pub fn handle_collisions(
+    orbital_bodies: &mut OrbitalBodies,
+    merge_condition: MergeCondition,
+    config: &PhysicsConfig,
+    dt: f64,
+    events: Option<&Sender<CollisionEvent>>,
+    stats: Option<&mut CollisionStats>,
+) -> CollisionOutcome {
     #[cfg(debug_assertions)]
     use std::time::Instant;
     #[cfg(debug_assertions)]
     let start = Instant::now();
-    
-    let collisions = compute_collisions_spatial_hash(orbital_bodies);
-    
+
+    let collisions = compute_collisions_spatial_hash(orbital_bodies, merge_condition, config, dt);
+
     #[cfg(debug_assertions)]
     {
         let end = Instant::now();
@@ -244,30 +726,221 @@ pub fn handle_collisions(orbital_bodies: &mut OrbitalBodies) {
         println!("Collision time: {0}ms", delta.as_millis());
     }
 
+    let collisions = match config.max_collisions_per_step {
+        Some(cap) => cap_collisions(collisions, cap),
+        None => collisions,
+    };
+
+    apply_collisions(orbital_bodies, collisions, config.g, events, stats)
+}
+
+/// Keep the first `cap` merges and fragmentations, in the order they are
+/// applied. The bodies spared still touch, they collide again in a later
+/// step.
+fn cap_collisions(mut collisions: Vec<CollisionResult>, cap: usize) -> Vec<CollisionResult> {
+    collisions.sort_by(CollisionResult::order);
+    collisions.truncate(cap);
+    collisions
+}
+
+/// The body `id` lives on as after the merges in `merged_into`, itself when
+/// it was not absorbed
+fn merged_survivor(merged_into: &HashMap<BodyId, BodyId>, mut id: BodyId) -> BodyId {
+    while let Some(&next) = merged_into.get(&id) {
+        id = next;
+    }
+    id
+}
+
+/// Apply the collision results to the bodies. The results are gathered in
+/// parallel, in no particular order, so they are sorted first for a body
+/// involved in several collisions to always end up the same.
+///
+/// A merge is redone with what the earlier merges of the step left of its
+/// bodies, so that a body touching several others adds all of their masses,
+/// and one absorbed in a chain reaches the last survivor.
+///
+/// A body that absorbed another before shattering is shattered again under
+/// the gravity constant `g`, see [compute_fragmentation]. The fragments of
+/// the step do not merge with anything before the next one.
+///
+/// Returns the energy lost in the merges and shatters, and which body
+/// absorbed which.
+fn apply_collisions(
+    orbital_bodies: &mut OrbitalBodies,
+    mut collisions: Vec<CollisionResult>,
+    g: f64,
+    events: Option<&Sender<CollisionEvent>>,
+    mut stats: Option<&mut CollisionStats>,
+) -> CollisionOutcome {
+    collisions.sort_by(CollisionResult::order);
+
+    let mut outcome = CollisionOutcome::default();
+    let mut fragment_ids = HashSet::new();
+
     for collision in collisions {
         match collision {
-            CollisionResult::Merge {
-                body_id,
-                new_mass,
-                new_velocity,
-            } => {
-                if let Some(body) = orbital_bodies.get_mut_by_id(body_id) {
+            CollisionResult::Merge(Merger { event, .. }) => {
+                // Either body may have been absorbed since, or shattered
+                let (id1, id2) = (
+                    merged_survivor(&outcome.merged_into, event.survivor),
+                    merged_survivor(&outcome.merged_into, event.destroyed),
+                );
+                let Some((body1, body2)) = orbital_bodies
+                    .get_by_id(id1)
+                    .zip(orbital_bodies.get_by_id(id2))
+                    .filter(|_| id1 != id2)
+                    .filter(|_| !fragment_ids.contains(&id1) && !fragment_ids.contains(&id2))
+                else {
+                    continue;
+                };
+
+                let (winner, destroyed) = merge_roles(body1, body2);
+                let Merger {
+                    new_mass,
+                    new_velocity,
+                    dissipated_energy,
+                    event,
+                } = compute_merger(winner, destroyed);
+
+                let tier0_survivor = orbital_bodies.tier0.contains_key(&event.survivor);
+                if let Some(body) = orbital_bodies.get_mut_by_id(event.survivor) {
                     body.mass = new_mass;
                     body.velocity = new_velocity;
+                    body.heat(dissipated_energy);
+                }
+                orbital_bodies.remove(event.destroyed);
+                outcome.dissipated_energy += dissipated_energy;
+                outcome.merged_into.insert(event.destroyed, event.survivor);
+
+                if let Some(stats) = stats.as_deref_mut() {
+                    stats.record(&event, tier0_survivor);
+                }
+
+                // Nobody listening is not an error
+                if let Some(events) = events {
+                    let _ = events.send(event);
                 }
             }
-            CollisionResult::Destroyed { body_id } => {
-                orbital_bodies.remove(body_id);
+            CollisionResult::Fragment {
+                body_ids: (id1, id2),
+                mut fragments,
+                mut color,
+                mut theme_color,
+                mut dissipated_energy,
+            } => {
+                // Either body may already be gone, merged or shattered by
+                // another collision of the frame
+                let Some((body1, body2)) = orbital_bodies
+                    .get_by_id(id1)
+                    .zip(orbital_bodies.get_by_id(id2))
+                else {
+                    continue;
+                };
+
+                // A body that absorbed another since shatters with its new
+                // mass, or merges in a later step when no longer shattering
+                if outcome
+                    .merged_into
+                    .values()
+                    .any(|&id| id == id1 || id == id2)
+                {
+                    match compute_fragmentation(body1, body2, g) {
+                        Some(CollisionResult::Fragment {
+                            fragments: current,
+                            color: current_color,
+                            theme_color: current_theme_color,
+                            dissipated_energy: current_dissipated_energy,
+                            ..
+                        }) => {
+                            (fragments, color, theme_color, dissipated_energy) = (
+                                current,
+                                current_color,
+                                current_theme_color,
+                                current_dissipated_energy,
+                            )
+                        }
+                        _ => continue,
+                    }
+                }
+
+                let (heavier, lighter) = merge_roles(body1, body2);
+                let mut event = CollisionEvent {
+                    energy: dissipated_energy,
+                    ..compute_merger(heavier, lighter).event
+                };
+                let group_id = body1.group_id;
+                let mass = body1.mass + body2.mass;
+
+                orbital_bodies.remove(id1);
+                orbital_bodies.remove(id2);
+
+                for fragment in fragments {
+                    let mut body = Body::new(
+                        fragment.mass,
+                        fragment.pos,
+                        fragment.physical_radius,
+                        1.,
+                        color,
+                        fragment.velocity,
+                        (0., 0.),
+                    );
+                    body.trail_parameter = TrailParameter::NoTrail;
+                    body.theme_color = theme_color;
+                    body.group_id = group_id;
+                    body.heat(dissipated_energy * fragment.mass / mass);
+                    event.fragments.push(body.id());
+                    orbital_bodies.tier1.insert(body.id(), body);
+                }
+
+                if let Some(&largest) = event.fragments.first() {
+                    outcome.merged_into.insert(id1, largest);
+                    outcome.merged_into.insert(id2, largest);
+                }
+                fragment_ids.extend(event.fragments.iter().copied());
+                outcome.dissipated_energy += dissipated_energy;
+                outcome.shattered += 1;
+
+                if let Some(stats) = stats.as_deref_mut() {
+                    // Neither body survives a shatter
+                    stats.record(&event, false);
+                }
+
+                // Nobody listening is not an error
+                if let Some(events) = events {
+                    let _ = events.send(event);
+                }
             }
         }
     }
+
+    // Follow the chains of merges to the body that is left
+    let merged_into = outcome.merged_into.clone();
+    for survivor in outcome.merged_into.values_mut() {
+        *survivor = merged_survivor(&merged_into, *survivor);
+    }
+
+    outcome
 }
 
 #[cfg(test)]
 mod tests {
     use crate::body::{Body, OrbitalBodies, bodies_to_map};
-    use crate::physics::collisions::{BinBodiesParam, bin_bodies};
+    use crate::constants::AU;
+    use crate::physics::collision_stats::CollisionStats;
+    use crate::physics::collisions::{
+        BinBodiesParam, CollisionEvent, CollisionResult, Contact, MAX_BIN_BODIES, MAX_FRAGMENTS,
+        MergeCondition, Merger, append_collision, apply_collisions, bin_bodies,
+        compute_kdtree_collisions, compute_merger, compute_pairwise_collision_slice,
+        compute_pairwise_collisions, contact_separation, handle_collisions, split_dense_bin,
+    };
+    use crate::physics::{G, PhysicsConfig, distance};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
     use raylib::color::Color;
+    use std::collections::{HashMap, HashSet};
+    use std::f64::consts::PI;
+    use std::sync::mpsc;
 
     #[test]
     fn test_bin_bodies() {
@@ -292,10 +965,10 @@ mod tests {
         let b6 = body(1, 1);
         let b6_id = *&b6.id();
 
-        let bodies = OrbitalBodies {
-            tier0: bodies_to_map(vec![b0, b1, b2, b3, b4, b5, b6]),
-            tier1: bodies_to_map(vec![]),
-        };
+        let bodies = OrbitalBodies::new(
+            bodies_to_map(vec![b0, b1, b2, b3, b4, b5, b6]),
+            bodies_to_map(vec![]),
+        );
 
         let binned = bin_bodies(
             &bodies,
@@ -310,4 +983,723 @@ mod tests {
             "There should not be more than a body per bin"
         );
     }
+
+    #[test]
+    fn test_merge_condition() {
+        fn pair(velocity: f64) -> OrbitalBodies {
+            let left = Body::new(
+                1.,
+                (-1., 0.),
+                2.,
+                1.,
+                Color::WHITE,
+                (velocity, 0.),
+                (0., 0.),
+            );
+            let right = Body::new(
+                2.,
+                (1., 0.),
+                2.,
+                1.,
+                Color::WHITE,
+                (-velocity, 0.),
+                (0., 0.),
+            );
+
+            OrbitalBodies::new(bodies_to_map(vec![left, right]), bodies_to_map(vec![]))
+        }
+
+        let approaching = pair(1.);
+        let separating = pair(-1.);
+
+        assert!(
+            !compute_pairwise_collisions(&approaching, MergeCondition::Overlap, Contact::default())
+                .is_empty()
+        );
+        assert!(
+            !compute_pairwise_collisions(&separating, MergeCondition::Overlap, Contact::default())
+                .is_empty()
+        );
+
+        assert!(
+            !compute_pairwise_collisions(
+                &approaching,
+                MergeCondition::Approaching,
+                Contact::default()
+            )
+            .is_empty()
+        );
+        assert!(
+            compute_pairwise_collisions(
+                &separating,
+                MergeCondition::Approaching,
+                Contact::default()
+            )
+            .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_merger_dissipated_energy() {
+        let left = Body::new(2., (0., 0.), 1., 1., Color::WHITE, (3., 0.), (0., 0.));
+        let right = Body::new(2., (0., 0.), 1., 1., Color::WHITE, (-3., 0.), (0., 0.));
+
+        // Head-on between equal masses, all the kinetic energy is lost
+        let merger = compute_merger(&left, &right);
+        assert_eq!(merger.new_velocity, (0., 0.));
+        assert_eq!(merger.dissipated_energy, 18.);
+    }
+
+    #[test]
+    fn test_collision_order() {
+        fn cluster() -> OrbitalBodies {
+            let body =
+                |mass: f64| Body::new(mass, (0., 0.), 1., 1., Color::WHITE, (mass, 0.), (0., 0.));
+
+            OrbitalBodies::new(
+                bodies_to_map(vec![body(1.), body(2.), body(4.)]),
+                bodies_to_map(vec![]),
+            )
+        }
+
+        // Whatever order the parallel bins hand the results in, the same
+        // body survives with the same mass and velocity
+        for rotation in 0..12 {
+            let mut bodies = cluster();
+            let mut collisions =
+                compute_pairwise_collisions(&bodies, MergeCondition::Overlap, Contact::default());
+            let count = collisions.len();
+            collisions.rotate_left(rotation % count);
+            if rotation % 2 == 1 {
+                collisions.reverse();
+            }
+
+            apply_collisions(&mut bodies, collisions, G, None, None);
+
+            // All of the masses and momenta add up, 1 + 2 + 4 and 1 + 4 + 16
+            let survivors = bodies.iter().collect::<Vec<_>>();
+            assert_eq!(survivors.len(), 1);
+            assert_eq!(survivors[0].mass, 7.);
+            assert_eq!(survivors[0].velocity, (3., 0.));
+        }
+    }
+
+    #[test]
+    fn test_collides_matches_distance() {
+        let body = |x: f64, radius: f64| {
+            Body::new(
+                1.,
+                (x, 0.5 * x),
+                radius,
+                1.,
+                Color::WHITE,
+                (0., 0.),
+                (0., 0.),
+            )
+        };
+
+        for (x, radius) in [
+            (0., 1.),
+            (3., 1.),
+            (4., 2.),
+            (4.4, 2.),
+            (5., 2.),
+            (1e11, 5e10),
+        ] {
+            let (left, right) = (body(0., radius), body(x, radius));
+            let (_, dist) = distance(&left, &right);
+
+            assert_eq!(
+                contact_separation(&left, &right, Contact::default()).is_some(),
+                dist <= 2. * radius
+            );
+        }
+    }
+
+    #[test]
+    fn test_equal_mass_tie() {
+        let left = Body::new(3., (0., 0.), 1., 1., Color::WHITE, (1., 0.), (0., 0.));
+        let right = Body::new(3., (0.5, 0.), 1., 1., Color::WHITE, (-1., 0.), (0., 0.));
+        let winner = left.id().min(right.id());
+
+        // Whichever way round the pair is checked, the lowest id survives
+        for (body1, body2) in [(&left, &right), (&right, &left)] {
+            let mut collisions = vec![];
+            append_collision(
+                body1,
+                body2,
+                MergeCondition::Overlap,
+                Contact::default(),
+                &mut collisions,
+            );
+            collisions.sort_by(CollisionResult::order);
+
+            match collisions.as_slice() {
+                [CollisionResult::Merge(merger)] => {
+                    assert_eq!(merger.event.survivor, winner);
+                    assert_eq!(merger.new_mass, 6.);
+                    assert_ne!(merger.event.destroyed, winner);
+                }
+                _ => panic!("expected a merge"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_fragmentation_conserves_mass_and_momentum() {
+        let momentum = |bodies: &OrbitalBodies| {
+            bodies
+                .iter()
+                .map(Body::momentum)
+                .fold((0., 0.), |(px, py), (bx, by)| (px + bx, py + by))
+        };
+
+        // From barely above the binding energy to far beyond it
+        for speed in [500., 5_000.] {
+            let left = Body::new(
+                1E10,
+                (0., 0.),
+                100.,
+                1.,
+                Color::WHITE,
+                (speed, 300.),
+                (0., 0.),
+            );
+            let right = Body::new(5E9, (150., 0.), 80., 1., Color::RED, (-speed, 0.), (0., 0.));
+
+            let (left_id, right_id) = (left.id(), right.id());
+            let mut bodies =
+                OrbitalBodies::new(bodies_to_map(vec![left, right]), bodies_to_map(vec![]));
+            let (mass_before, momentum_before) = (bodies.total_mass(), momentum(&bodies));
+            let energy_before = bodies.total_kinetic_energy();
+
+            let (sender, receiver) = mpsc::channel();
+            let mut stats = CollisionStats::default();
+            let collisions =
+                compute_pairwise_collisions(&bodies, MergeCondition::Overlap, Contact::default());
+            let outcome =
+                apply_collisions(&mut bodies, collisions, G, Some(&sender), Some(&mut stats));
+
+            // The fragments of tier 0 bodies go to tier 1
+            assert_eq!(outcome.shattered, 1);
+            assert!(bodies.tier0.is_empty());
+            assert!((2..=MAX_FRAGMENTS).contains(&bodies.tier1.len()));
+
+            // All of the kinetic energy lost is accounted for
+            let lost = energy_before - bodies.total_kinetic_energy();
+            assert!(outcome.dissipated_energy > 0.);
+            assert!((outcome.dissipated_energy - lost).abs() < 1e-9 * energy_before);
+
+            let events = receiver.try_iter().collect::<Vec<_>>();
+            assert_eq!(events.len(), 1);
+            assert_eq!(events[0].survivor, left_id);
+            assert_eq!(events[0].fragments.len(), bodies.len());
+            assert_eq!(outcome.merged_into[&right_id], events[0].fragments[0]);
+            assert_eq!((stats.merges, stats.shatters), (0, 1));
+
+            let momentum_after = momentum(&bodies);
+            assert!((bodies.total_mass() - mass_before).abs() / mass_before < 1e-12);
+            assert!((momentum_after.0 - momentum_before.0).abs() < 1e-12 * mass_before * speed);
+            assert!((momentum_after.1 - momentum_before.1).abs() < 1e-12 * mass_before * speed);
+        }
+    }
+
+    #[test]
+    fn test_dense_bin_split() {
+        let mut rng = StdRng::seed_from_u64(3);
+
+        // A tight cluster in the belt, all in a single bin
+        let bodies = OrbitalBodies::new(
+            bodies_to_map(vec![]),
+            bodies_to_map(
+                (0..5_000)
+                    .map(|_| {
+                        let pos = (
+                            2.5 * AU + rng.random_range(0. ..1E6),
+                            rng.random_range(0. ..1E6),
+                        );
+                        Body::new(1., pos, 2_000., 1., Color::WHITE, (0., 0.), (0., 0.))
+                    })
+                    .collect(),
+            ),
+        );
+
+        let bins = bin_bodies(&bodies, BinBodiesParam::default());
+        assert!(bins.iter().any(|bin| bin.len() == 5_000));
+
+        let groups = split_dense_bin(bodies.iter().collect(), 0);
+        let comparisons = groups
+            .iter()
+            .map(|group| group.len() * (group.len() - 1) / 2)
+            .sum::<usize>();
+        assert!(comparisons < 5_000 * MAX_BIN_BODIES);
+
+        // The same bodies collide as when checking every pair
+        let destroyed = |collisions: Vec<CollisionResult>| {
+            collisions
+                .into_iter()
+                .filter_map(|collision| match collision {
+                    CollisionResult::Merge(Merger { event, .. }) => Some(event.destroyed),
+                    _ => None,
+                })
+                .collect::<HashSet<_>>()
+        };
+        let split = groups
+            .iter()
+            .flat_map(|group| {
+                compute_pairwise_collision_slice(group, MergeCondition::Overlap, Contact::default())
+            })
+            .collect();
+
+        assert_eq!(
+            destroyed(split),
+            destroyed(compute_pairwise_collisions(
+                &bodies,
+                MergeCondition::Overlap,
+                Contact::default()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_pair_on_split_line() {
+        // A grid dense enough to be split, around a point off the bins
+        let center = (2.5 * AU + 5E5, 5E5);
+        let grid = (0..10).flat_map(|i| {
+            (0..10).map(move |j| {
+                let pos = (
+                    center.0 + (i as f64 - 4.5) * 1000.,
+                    center.1 + (j as f64 - 4.5) * 1000.,
+                );
+                Body::new(1., pos, 1., 1., Color::WHITE, (0., 0.), (0., 0.))
+            })
+        });
+
+        // A head-on pair across both split lines, in each of the quadrants:
+        // 2 * 1/2 * 2 * 3^2 is lost in the merge
+        let pair = [
+            Body::new(
+                2.,
+                (center.0 - 0.5, center.1),
+                1.,
+                1.,
+                Color::WHITE,
+                (3., 0.),
+                (0., 0.),
+            ),
+            Body::new(
+                2.,
+                (center.0 + 0.5, center.1),
+                1.,
+                1.,
+                Color::WHITE,
+                (-3., 0.),
+                (0., 0.),
+            ),
+        ];
+
+        let mut bodies = OrbitalBodies::new(
+            bodies_to_map(vec![]),
+            bodies_to_map(grid.chain(pair).collect()),
+        );
+        let groups = split_dense_bin(bodies.iter().collect(), 0);
+        assert_eq!(groups.len(), 4);
+
+        let (sender, receiver) = mpsc::channel();
+        let outcome = handle_collisions(
+            &mut bodies,
+            MergeCondition::Overlap,
+            &PhysicsConfig::default(),
+            3600.,
+            Some(&sender),
+            None,
+        );
+
+        assert_eq!(receiver.try_iter().count(), 1);
+        assert_eq!(outcome.dissipated_energy, 18.);
+        assert_eq!(bodies.len(), 101);
+        assert_eq!(bodies.total_mass(), 104.);
+    }
+
+    #[test]
+    fn test_collisions_without_bodies() {
+        let mut bodies = OrbitalBodies::new(bodies_to_map(vec![]), bodies_to_map(vec![]));
+
+        assert!(bin_bodies(&bodies, BinBodiesParam::default()).is_empty());
+        handle_collisions(
+            &mut bodies,
+            MergeCondition::default(),
+            &PhysicsConfig::default(),
+            3600.,
+            None,
+            None,
+        );
+        assert_eq!(bodies.len(), 0);
+    }
+
+    #[test]
+    fn test_accretion_factor() {
+        // Touching at a distance of 2, placed at 1.5 times that
+        let bodies = OrbitalBodies::new(
+            bodies_to_map(vec![
+                Body::new(1., (0., 0.), 1., 1., Color::WHITE, (0., 0.), (0., 0.)),
+                Body::new(1., (3., 0.), 1., 1., Color::WHITE, (0., 0.), (0., 0.)),
+            ]),
+            bodies_to_map(vec![]),
+        );
+
+        for (accretion_factor, merges) in [(0.5, false), (1., false), (1.5, true), (2., true)] {
+            let contact = Contact {
+                accretion_factor,
+                ..Default::default()
+            };
+            let collisions = compute_pairwise_collisions(&bodies, MergeCondition::Overlap, contact);
+            assert_eq!(!collisions.is_empty(), merges);
+        }
+    }
+
+    #[test]
+    fn test_collision_events() {
+        // Pairs far apart, each at rest and touching, merging without
+        // shattering
+        let pairs = (0..3)
+            .map(|i| {
+                let x = i as f64 * AU;
+                (
+                    Body::new(3., (x, 0.), 1., 1., Color::WHITE, (0., 0.), (0., 0.))
+                        .with_label(&format!("Planet {i}")),
+                    Body::new(1., (x + 1., 0.), 1., 1., Color::WHITE, (0., 0.), (0., 0.)),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let expected = pairs
+            .iter()
+            .map(|(heavy, light)| CollisionEvent {
+                survivor: heavy.id(),
+                destroyed: light.id(),
+                survivor_name: heavy.name(),
+                destroyed_name: light.name(),
+                survivor_mass: 3.,
+                destroyed_mass: 1.,
+                position: (heavy.pos().0 + 0.25, 0.),
+                energy: 0.,
+                fragments: vec![],
+            })
+            .collect::<Vec<_>>();
+
+        let mut bodies = OrbitalBodies::new(
+            bodies_to_map(pairs.into_iter().flat_map(|(a, b)| [a, b]).collect()),
+            bodies_to_map(vec![]),
+        );
+
+        let (sender, receiver) = mpsc::channel();
+        let collisions =
+            compute_pairwise_collisions(&bodies, MergeCondition::Overlap, Contact::default());
+        apply_collisions(&mut bodies, collisions, G, Some(&sender), None);
+
+        let mut events = receiver.try_iter().collect::<Vec<_>>();
+        events.sort_by_key(|event| event.survivor);
+        assert_eq!(events, expected);
+        assert_eq!(events[1].survivor_name, "Planet 1");
+        assert_eq!(
+            events[1].destroyed_name,
+            format!("#{0}", events[1].destroyed)
+        );
+        assert_eq!(bodies.len(), 3);
+    }
+
+    #[test]
+    fn test_max_collisions_per_step() {
+        // Pairs far apart, each at rest and touching
+        let pairs = (0..3).flat_map(|i| {
+            let x = i as f64 * AU;
+            [
+                Body::new(3., (x, 0.), 1., 1., Color::WHITE, (0., 0.), (0., 0.)),
+                Body::new(1., (x + 1., 0.), 1., 1., Color::WHITE, (0., 0.), (0., 0.)),
+            ]
+        });
+        let mut bodies = OrbitalBodies::new(bodies_to_map(pairs.collect()), bodies_to_map(vec![]));
+        let config = PhysicsConfig {
+            max_collisions_per_step: Some(1),
+            ..Default::default()
+        };
+
+        // A single merge per call, until none is left
+        for expected in [5, 4, 3, 3] {
+            handle_collisions(
+                &mut bodies,
+                MergeCondition::Overlap,
+                &config,
+                3600.,
+                None,
+                None,
+            );
+            assert_eq!(bodies.len(), expected);
+        }
+        assert_eq!(bodies.iter().map(|body| body.mass).sum::<f64>(), 12.);
+    }
+
+    #[test]
+    fn test_collisions_within_group() {
+        let body = |x: f64| Body::new(1., (x, 0.), 1., 1., Color::WHITE, (0., 0.), (0., 0.));
+        let (left, mut right) = (body(0.), body(1.));
+        assert!(contact_separation(&left, &right, Contact::default()).is_some());
+
+        right.group_id = 1;
+        assert!(contact_separation(&left, &right, Contact::default()).is_none());
+    }
+
+    #[test]
+    fn test_collision_substeps() {
+        // Crossed the target in the last step, from 10 m on its left to 10 m
+        // on its right
+        let target = Body::new(1., (0., 0.), 1.5, 1., Color::WHITE, (0., 0.), (0., 0.));
+        let bullet = Body::new(1., (10., 0.), 1.5, 1., Color::WHITE, (100., 0.), (0., 0.));
+        let contact = |substeps| Contact {
+            dt: 0.2,
+            substeps,
+            ..Default::default()
+        };
+
+        assert!(contact_separation(&bullet, &target, contact(1)).is_none());
+        assert!(contact_separation(&bullet, &target, contact(3)).is_none());
+        assert!(contact_separation(&bullet, &target, contact(10)).is_some());
+
+        // Merging only approaching bodies, they were approaching at contact
+        let mut collisions = vec![];
+        append_collision(
+            &bullet,
+            &target,
+            MergeCondition::Approaching,
+            contact(10),
+            &mut collisions,
+        );
+        assert!(!collisions.is_empty());
+    }
+
+    #[test]
+    fn test_kdtree_collisions_skip_non_finite() {
+        let body = |pos: (f64, f64)| Body::new(1., pos, 1., 1., Color::WHITE, (0., 0.), (0., 0.));
+
+        let (b1, b2) = (body((0., 0.)), body((1., 0.)));
+        let ids = HashSet::from([b1.id(), b2.id()]);
+        let bodies = OrbitalBodies::new(
+            bodies_to_map(vec![b1, b2, body((f64::NAN, 0.))]),
+            bodies_to_map(vec![body((0.5, f64::INFINITY)), body((10., 10.))]),
+        );
+
+        let (collisions, skipped) =
+            compute_kdtree_collisions(&bodies, MergeCondition::Overlap, Contact::default());
+        assert_eq!(skipped, 2);
+
+        // Only the touching pair merges, once
+        match collisions.as_slice() {
+            [CollisionResult::Merge(Merger { event, .. })] => {
+                assert_eq!(HashSet::from([event.survivor, event.destroyed]), ids);
+            }
+            _ => panic!("expected a single merge"),
+        }
+    }
+
+    #[test]
+    fn test_kdtree_collisions_count_skipped() {
+        let body = |pos: (f64, f64)| Body::new(1., pos, 1., 1., Color::WHITE, (0., 0.), (0., 0.));
+
+        let nan = body((f64::NAN, 0.));
+        let nan_id = nan.id();
+        let bodies = OrbitalBodies::new(
+            bodies_to_map(vec![body((0., 0.)), nan]),
+            bodies_to_map(vec![body((1., 0.))]),
+        );
+
+        let (collisions, skipped) =
+            compute_kdtree_collisions(&bodies, MergeCondition::Overlap, Contact::default());
+
+        assert_eq!(skipped, 1);
+        assert_eq!(collisions.len(), 1);
+        assert!(collisions.iter().all(|collision| match collision {
+            CollisionResult::Merge(Merger { event, .. }) => {
+                event.survivor != nan_id && event.destroyed != nan_id
+            }
+            CollisionResult::Fragment { body_ids, .. } => {
+                body_ids.0 != nan_id && body_ids.1 != nan_id
+            }
+        }));
+    }
+
+    #[test]
+    fn test_kdtree_collisions_one_sided_neighbours() {
+        let body = |pos: (f64, f64), radius: f64| {
+            Body::new(1., pos, radius, 1., Color::WHITE, (0., 0.), (0., 0.))
+        };
+
+        // The nearest neighbours of the first body are all on the arc, the
+        // second body it touches is only found from the other side
+        let (b1, b2) = (body((0., 0.), 10.), body((15., 0.), 10.));
+        let ids = HashSet::from([b1.id(), b2.id()]);
+        let arc = (0..150).map(|i| {
+            let angle = PI / 2. + i as f64 / 150. * PI;
+            body((12. * angle.cos(), 12. * angle.sin()), 1e-3)
+        });
+        let bodies = OrbitalBodies::new(bodies_to_map(vec![b1, b2]), bodies_to_map(arc.collect()));
+
+        let (collisions, _) =
+            compute_kdtree_collisions(&bodies, MergeCondition::Overlap, Contact::default());
+
+        match collisions.as_slice() {
+            [CollisionResult::Merge(Merger { event, .. })] => {
+                assert_eq!(HashSet::from([event.survivor, event.destroyed]), ids);
+            }
+            _ => panic!("expected a single merge"),
+        }
+    }
+
+    #[test]
+    fn test_apply_collisions_dissipated_energy() {
+        // Head-on between equal masses at rest together after: all of their
+        // kinetic energy, 2 * 1/2 * 2 * 3^2, is lost
+        let bodies = || {
+            OrbitalBodies::new(
+                bodies_to_map(vec![
+                    Body::new(2., (0., 0.), 1., 1., Color::WHITE, (3., 0.), (0., 0.)),
+                    Body::new(2., (1., 0.), 1., 1., Color::WHITE, (-3., 0.), (0., 0.)),
+                ]),
+                bodies_to_map(vec![]),
+            )
+        };
+
+        let mut merging = bodies();
+        let collisions =
+            compute_pairwise_collisions(&merging, MergeCondition::Overlap, Contact::default());
+        assert_eq!(
+            apply_collisions(&mut merging, collisions, G, None, None).dissipated_energy,
+            18.
+        );
+        assert_eq!(merging.len(), 1);
+
+        // Nothing lost without a collision
+        let mut apart = bodies();
+        apart
+            .iter_mut()
+            .for_each(|body| body.set_pos((body.pos().0 * 10., 0.)));
+        let collisions =
+            compute_pairwise_collisions(&apart, MergeCondition::Overlap, Contact::default());
+        assert_eq!(
+            apply_collisions(&mut apart, collisions, G, None, None).dissipated_energy,
+            0.
+        );
+    }
+
+    #[test]
+    fn test_fragment_after_merge() {
+        let body = |mass: f64, pos: (f64, f64), velocity: (f64, f64)| {
+            Body::new(mass, pos, 1., 1., Color::WHITE, velocity, (0., 0.))
+        };
+        // Absorbs the pebble at rest against it, and is shattered by the
+        // impactor in the same step
+        let target = body(1., (0., 0.), (0., 0.));
+        let pebble = body(0.5, (1.5, 0.), (0., 0.));
+        let impactor = body(1., (0., -1.5), (0., 1000.));
+        let ids = [target.id(), pebble.id(), impactor.id()];
+
+        let mut bodies = OrbitalBodies::new(
+            bodies_to_map(vec![target, pebble, impactor]),
+            bodies_to_map(vec![]),
+        );
+
+        let collisions =
+            compute_pairwise_collisions(&bodies, MergeCondition::Overlap, Contact::default());
+        assert!(
+            collisions
+                .iter()
+                .any(|collision| matches!(collision, CollisionResult::Fragment { .. }))
+        );
+        apply_collisions(&mut bodies, collisions, G, None, None);
+
+        assert!(ids.iter().all(|&id| bodies.get_by_id(id).is_none()));
+        assert!((bodies.total_mass() - 2.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_fragmentation_disabled() {
+        let bodies = OrbitalBodies::new(
+            bodies_to_map(vec![
+                Body::new(1., (0., 0.), 1., 1., Color::WHITE, (0., 0.), (0., 0.)),
+                Body::new(1., (1.5, 0.), 1., 1., Color::WHITE, (-1000., 0.), (0., 0.)),
+            ]),
+            bodies_to_map(vec![]),
+        );
+
+        for (fragmentation, shatters) in [(true, true), (false, false)] {
+            let contact = Contact {
+                fragmentation,
+                ..Default::default()
+            };
+            let collisions = compute_pairwise_collisions(&bodies, MergeCondition::Overlap, contact);
+
+            assert_eq!(collisions.len(), 1);
+            assert_eq!(
+                matches!(collisions[0], CollisionResult::Fragment { .. }),
+                shatters
+            );
+        }
+    }
+
+    #[test]
+    fn test_merged_into() {
+        let body =
+            |mass: f64, x: f64| Body::new(mass, (x, 0.), 1., 1., Color::WHITE, (0., 0.), (0., 0.));
+        let (planet, moon, far) = (body(10., 0.), body(1., 1.), body(1., AU));
+        let (planet_id, moon_id) = (planet.id(), moon.id());
+
+        let mut bodies = OrbitalBodies::new(
+            bodies_to_map(vec![planet, moon, far]),
+            bodies_to_map(vec![]),
+        );
+
+        let outcome = handle_collisions(
+            &mut bodies,
+            MergeCondition::Overlap,
+            &PhysicsConfig::default(),
+            3600.,
+            None,
+            None,
+        );
+        assert_eq!(outcome.merged_into, HashMap::from([(moon_id, planet_id)]));
+        assert_eq!(bodies.len(), 2);
+    }
+
+    #[test]
+    fn test_accreted_masses() {
+        let body =
+            |mass: f64, x: f64| Body::new(mass, (x, 0.), 1., 1., Color::WHITE, (0., 0.), (0., 0.));
+        let planet = body(10., 0.);
+        let planet_id = planet.id();
+
+        let mut bodies = OrbitalBodies::new(bodies_to_map(vec![planet]), bodies_to_map(vec![]));
+        let mut stats = CollisionStats::default();
+
+        // One asteroid touching the planet per step, then a step without any
+        for (step, mass) in [2., 3., 0.5].into_iter().enumerate() {
+            let asteroid = body(mass, 1.);
+            bodies.tier1.insert(asteroid.id(), asteroid);
+
+            let collisions =
+                compute_pairwise_collisions(&bodies, MergeCondition::Overlap, Contact::default());
+            apply_collisions(&mut bodies, collisions, G, None, Some(&mut stats));
+            stats.end_step(step as f64);
+        }
+        stats.end_step(3.);
+
+        assert_eq!(stats.merges, 3);
+        assert_eq!(stats.accreted(planet_id), 5.5);
+        assert_eq!(stats.accretion(), vec![(planet_id, 5.5)]);
+        assert_eq!(bodies.len(), 1);
+
+        assert_eq!(
+            stats.format_history_csv(),
+            "time,merges,shatters\n0,1,0\n1,1,0\n2,1,0\n"
+        );
+    }
 }
@@ -0,0 +1,125 @@
+use crate::body::{Body, BodyId, OrbitalBodies};
+use crate::physics::distance_sq;
+use std::collections::HashMap;
+
+/// A Hookean spring tying two tier 0 bodies, pulling them back to its rest
+/// length, such as the two halves of a dumbbell
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Constraint {
+    pub bodies: (BodyId, BodyId),
+    /// Force per meter of stretch, in N/m
+    pub stiffness: f64,
+    /// Separation at which the spring pulls nothing, in meters
+    pub rest_length: f64,
+}
+
+impl Constraint {
+    /// Force of the spring on the first body, towards the second one when
+    /// stretched. The second body gets the opposite.
+    fn force(&self, first: &Body, second: &Body) -> (f64, f64) {
+        let ((x1, y1), (x2, y2)) = (first.pos(), second.pos());
+        let (dx, dy) = (x2 - x1, y2 - y1);
+
+        let distance = (dx.powf(2.) + dy.powf(2.)).sqrt();
+        if distance == 0. {
+            return (0., 0.);
+        }
+
+        let magnitude = self.stiffness * (distance - self.rest_length);
+        (magnitude * dx / distance, magnitude * dy / distance)
+    }
+
+    /// Energy stored in the spring, `k (d - L)^2 / 2`, in J
+    fn potential_energy(&self, first: &Body, second: &Body) -> f64 {
+        let stretch = distance_sq(first, second).sqrt() - self.rest_length;
+        self.stiffness * stretch.powf(2.) / 2.
+    }
+}
+
+/// Add the pull of the springs to the acceleration of the bodies they tie.
+/// The springs missing either body, merged away or not tier 0, are ignored.
+///
+/// Returns the energy stored in the springs, in J.
+pub fn apply_constraints(
+    bodies: &mut OrbitalBodies,
+    accelerations: &mut HashMap<BodyId, (f64, f64)>,
+) -> f64 {
+    let mut potential_energy = 0.;
+
+    for constraint in &bodies.constraints {
+        let (first_id, second_id) = constraint.bodies;
+        let (Some(first), Some(second)) =
+            (bodies.tier0.get(&first_id), bodies.tier0.get(&second_id))
+        else {
+            continue;
+        };
+        if first_id == second_id {
+            continue;
+        }
+
+        let (fx, fy) = constraint.force(first, second);
+        potential_energy += constraint.potential_energy(first, second);
+
+        for (id, (fx, fy)) in [(first_id, (fx, fy)), (second_id, (-fx, -fy))] {
+            let body = bodies.tier0.get_mut(&id).unwrap();
+            body.accel.0 += fx / body.mass;
+            body.accel.1 += fy / body.mass;
+            accelerations.insert(id, body.accel);
+        }
+    }
+
+    potential_energy
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::body::{Body, OrbitalBodies, bodies_to_map};
+    use crate::physics::constraint::Constraint;
+    use crate::physics::leapfrog::Leapfrog;
+    use crate::physics::{Gravity, Kinematics, PhysicsConfig, distance_sq, update_acceleration};
+    use raylib::color::Color;
+
+    #[test]
+    fn test_stretched_spring() {
+        let body =
+            |mass: f64, x: f64| Body::new(mass, (x, 0.), 1., 1., Color::WHITE, (0., 0.), (0., 0.));
+        let (first, second) = (body(1., 0.), body(3., 3.));
+        let (first_id, second_id) = (first.id(), second.id());
+
+        let mut bodies = OrbitalBodies {
+            tier0: bodies_to_map(vec![first, second]),
+            tier1: bodies_to_map(vec![]),
+            constraints: vec![Constraint {
+                bodies: (first_id, second_id),
+                stiffness: 1.,
+                rest_length: 1.,
+            }],
+        };
+        let config: PhysicsConfig = Gravity::Exact.into();
+
+        // Stretched by 2 m: 2 N on each, towards the other, the gravity of
+        // such small masses is negligible
+        let mut potential_energy = 0.;
+        let accelerations = update_acceleration(&mut bodies, &mut potential_energy, &config);
+        let (a1, a2) = (accelerations[&first_id], accelerations[&second_id]);
+        assert!((a1.0 - 2.).abs() < 1e-9);
+        assert!((a2.0 + 2. / 3.).abs() < 1e-9);
+        assert!((1. * a1.0 + 3. * a2.0).abs() < 1e-12);
+        assert!((potential_energy - 2.).abs() < 1e-9);
+
+        let separation = |bodies: &OrbitalBodies| {
+            distance_sq(&bodies.tier0[&first_id], &bodies.tier0[&second_id]).sqrt()
+        };
+        let mut previous = separation(&bodies);
+        for _ in 0..10 {
+            Leapfrog.step(&mut bodies, 0.05, &config);
+            let current = separation(&bodies);
+            assert!(current < previous);
+            previous = current;
+        }
+
+        // Pulled together without any net momentum
+        let momentum = bodies.iter().map(|body| body.momentum().0).sum::<f64>();
+        assert!(momentum.abs() < 1e-12);
+    }
+}
@@ -1,35 +1,41 @@
 use crate::body::OrbitalBodies;
-use crate::physics::{Kinematics, KinematicsDiagnostic, update_acceleration};
+use crate::physics::{Kinematics, KinematicsDiagnostic, PhysicsConfig, update_acceleration};
+use rayon::prelude::*;
 
 pub struct Euler;
 
 impl Kinematics for Euler {
-    fn step(&self, bodies: &mut OrbitalBodies, dt: f64) -> KinematicsDiagnostic {
+    fn step(
+        &self,
+        bodies: &mut OrbitalBodies,
+        dt: f64,
+        config: &PhysicsConfig,
+    ) -> KinematicsDiagnostic {
         // Rn+1 = Rn + Vn*dt
         // Vn+1 = Vn + An*dt
 
         let mut potential_energy = 0.;
-        let mut kinetic_energy = 0.;
 
-        update_acceleration(bodies, &mut potential_energy);
+        update_acceleration(bodies, &mut potential_energy, config);
 
-        for body in bodies.iter_mut() {
-            let (rx, ry) = body.pos();
+        bodies
+            .par_iter_mut()
+            .filter(|body| !body.fixed)
+            .for_each(|body| {
+                let (rx, ry) = body.pos();
 
-            body.velocity.0 += body.accel.0 * dt;
-            body.velocity.1 += body.accel.1 * dt;
+                body.velocity.0 += body.accel.0 * dt;
+                body.velocity.1 += body.accel.1 * dt;
 
-            let rx_1 = rx + body.velocity.0 * dt;
-            let ry_1 = ry + body.velocity.1 * dt;
+                let rx_1 = rx + body.velocity.0 * dt;
+                let ry_1 = ry + body.velocity.1 * dt;
 
-            body.set_pos((rx_1, ry_1));
-
-            kinetic_energy += body.kinetic_energy();
-        }
+                body.set_pos((rx_1, ry_1));
+            });
 
         KinematicsDiagnostic {
             potential_energy,
-            kinetic_energy,
+            kinetic_energy: bodies.total_kinetic_energy(),
         }
     }
 
@@ -1,46 +1,56 @@
 use crate::body::OrbitalBodies;
-use crate::physics::{Kinematics, KinematicsDiagnostic, update_acceleration};
+use crate::physics::{Kinematics, KinematicsDiagnostic, PhysicsConfig, update_acceleration};
+use rayon::prelude::*;
 use std::collections::HashMap;
 
 pub struct Leapfrog;
 
 impl Kinematics for Leapfrog {
-    fn step(&self, bodies: &mut OrbitalBodies, dt: f64) -> KinematicsDiagnostic {
+    fn step(
+        &self,
+        bodies: &mut OrbitalBodies,
+        dt: f64,
+        config: &PhysicsConfig,
+    ) -> KinematicsDiagnostic {
         let acceleration = bodies
             .iter()
             .map(|body| (body.id(), body.accel))
             .collect::<HashMap<_, _>>();
 
         let mut potential_energy = 0.0;
-        let mut kinetic_energy = 0.0;
 
-        for body in bodies.iter_mut() {
-            let (ax, ay) = acceleration.get(&body.id()).unwrap();
-            let (rx, ry) = body.pos();
-            let (vx, vy) = body.velocity;
+        bodies
+            .par_iter_mut()
+            .filter(|body| !body.fixed)
+            .for_each(|body| {
+                let (ax, ay) = acceleration.get(&body.id()).unwrap();
+                let (rx, ry) = body.pos();
+                let (vx, vy) = body.velocity;
 
-            let rx1 = rx + vx * dt + (1. / 2.) * ax * dt.powf(2.0);
-            let ry1 = ry + vy * dt + (1. / 2.) * ay * dt.powf(2.0);
+                let rx1 = rx + vx * dt + (1. / 2.) * ax * dt.powf(2.0);
+                let ry1 = ry + vy * dt + (1. / 2.) * ay * dt.powf(2.0);
 
-            body.set_pos((rx1, ry1));
-        }
+                body.set_pos((rx1, ry1));
+            });
 
-        let acceleration_updated = update_acceleration(bodies, &mut potential_energy);
+        let acceleration_updated = update_acceleration(bodies, &mut potential_energy, config);
 
-        for body in bodies.iter_mut() {
-            let (ax, ay) = acceleration.get(&body.id()).unwrap();
-            let (ax1, ay1) = acceleration_updated.get(&body.id()).unwrap();
-            let (vx, vy) = body.velocity;
+        bodies
+            .par_iter_mut()
+            .filter(|body| !body.fixed)
+            .for_each(|body| {
+                let (ax, ay) = acceleration.get(&body.id()).unwrap();
+                let (ax1, ay1) = acceleration_updated.get(&body.id()).unwrap();
+                let (vx, vy) = body.velocity;
 
-            let vx1 = vx + (1. / 2.) * (ax + ax1) * dt;
-            let vy1 = vy + (1. / 2.) * (ay + ay1) * dt;
+                let vx1 = vx + (1. / 2.) * (ax + ax1) * dt;
+                let vy1 = vy + (1. / 2.) * (ay + ay1) * dt;
 
-            body.velocity = (vx1, vy1);
-            kinetic_energy += body.kinetic_energy();
-        }
+                body.velocity = (vx1, vy1);
+            });
 
         KinematicsDiagnostic {
-            kinetic_energy,
+            kinetic_energy: bodies.total_kinetic_energy(),
             potential_energy,
         }
     }
@@ -53,45 +63,51 @@ impl Kinematics for Leapfrog {
 pub struct LeapfrogKDK;
 
 impl Kinematics for LeapfrogKDK {
-    fn step(&self, bodies: &mut OrbitalBodies, dt: f64) -> KinematicsDiagnostic {
+    fn step(
+        &self,
+        bodies: &mut OrbitalBodies,
+        dt: f64,
+        config: &PhysicsConfig,
+    ) -> KinematicsDiagnostic {
         // kick-drift-kick format
-        let acceleration = bodies.iter().map(|body| body.accel).collect::<Vec<_>>();
-
         let mut potential_energy = 0.0;
-        let mut kinetic_energy = 0.0;
 
-        let mut velocities_half = Vec::with_capacity(bodies.tier0.len() + bodies.tier1.len());
+        let velocities_half = bodies
+            .par_iter_mut()
+            .filter(|body| !body.fixed)
+            .map(|body| {
+                let (ax, ay) = body.accel;
+                let (vx, vy) = body.velocity;
+                let (rx, ry) = body.pos();
 
-        for (i, body) in bodies.iter_mut().enumerate() {
-            let (ax, ay) = acceleration[i];
-            let (vx, vy) = body.velocity;
-            let (rx, ry) = body.pos();
+                let vx_i_half = vx + (1. / 2.) * ax * dt;
+                let vy_i_half = vy + (1. / 2.) * ay * dt;
 
-            let vx_i_half = vx + (1. / 2.) * ax * dt;
-            let vy_i_half = vy + (1. / 2.) * ay * dt;
+                let rx_i_1 = rx + vx_i_half * dt;
+                let ry_i_1 = ry + vy_i_half * dt;
 
-            let rx_i_1 = rx + vx_i_half * dt;
-            let ry_i_1 = ry + vy_i_half * dt;
-
-            body.set_pos((rx_i_1, ry_i_1));
-            velocities_half.push((vx_i_half, vy_i_half));
-        }
+                body.set_pos((rx_i_1, ry_i_1));
+                (body.id(), (vx_i_half, vy_i_half))
+            })
+            .collect::<HashMap<_, _>>();
 
-        let acceleration_updated = update_acceleration(bodies, &mut potential_energy);
+        let acceleration_updated = update_acceleration(bodies, &mut potential_energy, config);
 
-        for (i, body) in bodies.iter_mut().enumerate() {
-            let (ax_1, ay_1) = acceleration_updated[&body.id()];
-            let (vx_i_half, vy_i_half) = velocities_half[i];
+        bodies
+            .par_iter_mut()
+            .filter(|body| !body.fixed)
+            .for_each(|body| {
+                let (ax_1, ay_1) = acceleration_updated[&body.id()];
+                let (vx_i_half, vy_i_half) = velocities_half[&body.id()];
 
-            let vx_i_1 = vx_i_half + (1. / 2.) * ax_1 * dt;
-            let vy_i_1 = vy_i_half + (1. / 2.) * ay_1 * dt;
+                let vx_i_1 = vx_i_half + (1. / 2.) * ax_1 * dt;
+                let vy_i_1 = vy_i_half + (1. / 2.) * ay_1 * dt;
 
-            body.velocity = (vx_i_1, vy_i_1);
-            kinetic_energy += body.kinetic_energy();
-        }
+                body.velocity = (vx_i_1, vy_i_1);
+            });
 
         KinematicsDiagnostic {
-            kinetic_energy,
+            kinetic_energy: bodies.total_kinetic_energy(),
             potential_energy,
         }
     }
@@ -100,3 +116,49 @@ impl Kinematics for LeapfrogKDK {
         "Leapfrog (KDK)"
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::body::{Body, OrbitalBodies, bodies_to_map};
+    use crate::constants::{EARTH_MASS, EARTH_SUN_VELOCITY, SUN_EARTH_DISTANCE, SUN_MASS};
+    use crate::physics::leapfrog::LeapfrogKDK;
+    use crate::physics::{Kinematics, PhysicsConfig};
+    use raylib::color::Color;
+
+    #[test]
+    fn test_kdk_fixed_body() {
+        let mut sun = Body::new(
+            SUN_MASS,
+            (0., 0.),
+            1.,
+            1.,
+            Color::YELLOW,
+            (0., 0.),
+            (0., 0.),
+        );
+        sun.fixed = true;
+        let sun_id = sun.id();
+
+        let earth = Body::new(
+            EARTH_MASS,
+            (0., SUN_EARTH_DISTANCE),
+            1.,
+            1.,
+            Color::BLUE,
+            (EARTH_SUN_VELOCITY, 0.),
+            (0., 0.),
+        );
+
+        let mut bodies = OrbitalBodies::new(bodies_to_map(vec![sun, earth]), bodies_to_map(vec![]));
+
+        // The second step starts with the pull of the earth stored on the sun
+        for _ in 0..2 {
+            LeapfrogKDK.step(&mut bodies, 3600., &PhysicsConfig::default());
+        }
+
+        let sun = bodies.get_by_id(sun_id).unwrap();
+        assert_ne!(sun.accel, (0., 0.));
+        assert_eq!(sun.velocity, (0., 0.));
+        assert_eq!(sun.pos(), (0., 0.));
+    }
+}
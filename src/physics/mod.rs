@@ -1,14 +1,126 @@
+pub mod approach;
+pub mod apsides;
+pub mod binding;
+pub mod collision_stats;
 pub mod collisions;
+pub mod constraint;
 pub mod euler;
 pub mod leapfrog;
+pub mod orbit;
+pub mod periods;
+pub mod sublimation;
+pub mod tiered;
 
 use crate::body::{Body, BodyId, OrbitalBodies};
+use crate::constants::AU;
+use crate::physics::constraint::apply_constraints;
 use std::collections::HashMap;
 use std::ops::Sub;
 
 /// Gravity constant
 pub const G: f64 = 6.6674 * 1E-11;
 
+/// Most steps between two computations of the acceleration of a throttled
+/// tier 1 body
+pub const MAX_THROTTLED_STEPS: usize = 8;
+
+/// How the gravitational pull between two bodies is computed
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum Gravity {
+    /// Softened on close encounters, to avoid slingshots of bodies
+    #[default]
+    Softened,
+    /// Plain Newtonian gravity, to validate against analytic solutions
+    Exact,
+}
+
+/// The tunables of the physics engine, handed to the integrators and the
+/// collision handler
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PhysicsConfig {
+    /// Gravity constant, [G] by default
+    pub g: f64,
+    pub gravity: Gravity,
+    /// Softening length of a pair of bodies, as a fraction of the sum of
+    /// their radii, when the gravity is [Gravity::Softened]
+    pub softening_factor: f64,
+    /// Exponent `n` of the pull, `F ∝ 1/r^n`. Newtonian gravity is 2, the
+    /// only one giving closed elliptic orbits.
+    pub exponent: f64,
+    /// Width of the square bins bodies are sorted in to find collisions, in
+    /// meters
+    pub collision_bin_width: f64,
+    /// Distance from the origin beyond which collisions are not looked for,
+    /// in meters
+    pub collision_max_distance: f64,
+    /// Scale of the sum of the radii under which two bodies collide, above 1
+    /// they merge before touching, below 1 only once they overlap
+    pub accretion_factor: f64,
+    /// Points along the last step of each pair of bodies at which they are
+    /// checked for a collision, to catch fast bodies going through each
+    /// other. 1 only checks where they ended up.
+    pub collision_substeps: usize,
+    /// Most collisions resolved in a step, the others wait for the next ones,
+    /// so that turning the collisions back on does not merge a whole belt at
+    /// once. `None` resolves them all.
+    pub max_collisions_per_step: Option<usize>,
+    /// Shatter the bodies hitting each other hard enough to overcome their
+    /// binding energy, instead of always merging them
+    pub fragmentation: bool,
+    /// Simulated seconds covered by a frame at a speedup of 1
+    pub base_dt: f64,
+    /// Distance to the nearest tier 0 body, in meters, beyond which the
+    /// acceleration of a tier 1 body is only recomputed every `distance /
+    /// tier1_throttle_distance` steps, up to [MAX_THROTTLED_STEPS]. In
+    /// between, it drifts without any acceleration, and its potential energy
+    /// is the one of the last computation. `None` recomputes both every
+    /// step, exactly.
+    pub tier1_throttle_distance: Option<f64>,
+    /// Hold the tier 1 bodies in place, neither moved nor pulled, to only
+    /// advance the tier 0 ones, see [crate::simulation::Simulation::advance]
+    pub freeze_tier1: bool,
+}
+
+impl Default for PhysicsConfig {
+    fn default() -> Self {
+        Self {
+            g: G,
+            gravity: Gravity::default(),
+            softening_factor: 0.7,
+            exponent: 2.,
+            collision_bin_width: AU / 2.,
+            collision_max_distance: AU * 10.,
+            accretion_factor: 1.,
+            collision_substeps: 1,
+            max_collisions_per_step: None,
+            fragmentation: true,
+            base_dt: 1800. * 24.,
+            tier1_throttle_distance: None,
+            freeze_tier1: false,
+        }
+    }
+}
+
+/// The default physics, with softened or exact gravity
+impl From<Gravity> for PhysicsConfig {
+    fn from(gravity: Gravity) -> Self {
+        Self {
+            gravity,
+            ..Default::default()
+        }
+    }
+}
+
+/// How the drag on a body grows with its speed
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum DragLaw {
+    /// `a = -k * v`, as in a viscous medium
+    Linear,
+    /// `a = -k * v * |v|`, as in a thin gas or an atmosphere
+    #[default]
+    Quadratic,
+}
+
 #[derive(Copy, Clone)]
 pub struct KinematicsDiagnostic {
     kinetic_energy: f64,
@@ -19,6 +131,17 @@ impl KinematicsDiagnostic {
     pub fn total(&self) -> f64 {
         self.kinetic_energy + self.potential_energy
     }
+
+    /// The virial ratio `2T/|U|`: about 1 for a system in equilibrium, below
+    /// when it collapses and above when it flies apart. `None` without any
+    /// interaction, when there is no potential energy.
+    pub fn virial_ratio(&self) -> Option<f64> {
+        if self.potential_energy == 0. {
+            return None;
+        }
+
+        Some(2. * self.kinetic_energy / self.potential_energy.abs())
+    }
 }
 
 impl Sub for KinematicsDiagnostic {
@@ -33,43 +156,192 @@ impl Sub for KinematicsDiagnostic {
 /// bodies.
 pub trait Kinematics {
     /// Compute a time step
-    fn step(&self, bodies: &mut OrbitalBodies, dt: f64) -> KinematicsDiagnostic;
+    fn step(
+        &self,
+        bodies: &mut OrbitalBodies,
+        dt: f64,
+        config: &PhysicsConfig,
+    ) -> KinematicsDiagnostic;
 
     fn name(&self) -> &'static str;
 }
 
-/// Compute the euclidian distance between two bodies. Returns
-/// two values, (d^2, d) where d is the euclidian distance.
+/// Compute the squared euclidian distance between two bodies. Cheaper than
+/// [distance] when only comparing distances.
 #[inline]
-pub fn distance(body1: &Body, body2: &Body) -> (f64, f64) {
+pub fn distance_sq(body1: &Body, body2: &Body) -> f64 {
     let pos_1 = body1.pos();
     let pos_2 = body2.pos();
 
     let dx2 = (pos_1.0 - pos_2.0).powf(2.0);
     let dy2 = (pos_1.1 - pos_2.1).powf(2.0);
-    let sum = dx2 + dy2;
 
+    dx2 + dy2
+}
+
+/// Compute the euclidian distance between two bodies. Returns
+/// two values, (d^2, d) where d is the euclidian distance.
+#[inline]
+pub fn distance(body1: &Body, body2: &Body) -> (f64, f64) {
+    let sum = distance_sq(body1, body2);
     (sum, sum.sqrt())
 }
 
-fn pairwise_acceleration(pullee: &Body, pulling: &Body) -> (f64, f64) {
+/// Specific orbital energy of `body` relative to `attractor`, as if they were
+/// alone, in J/Kg, under the gravity constant `g`. Negative when the body is
+/// bound to the attractor.
+pub fn specific_orbital_energy(body: &Body, attractor: &Body, g: f64) -> f64 {
+    let (_, distance) = distance(body, attractor);
+    let (vx, vy) = (
+        body.velocity.0 - attractor.velocity.0,
+        body.velocity.1 - attractor.velocity.1,
+    );
+
+    let mu = g * (body.mass + attractor.mass);
+    (vx.powf(2.) + vy.powf(2.)) / 2. - mu / distance
+}
+
+/// The tier 0 body `body` primarily orbits. Only the tier 0 bodies of its
+/// group heavier than it are considered, the heaviest body orbits nothing.
+/// Starting from the one pulling the hardest under `config`, `g m / r^n`, it
+/// goes down to any lighter one whose Hill sphere the body is inside: the Sun
+/// pulls the Moon twice as hard as the Earth does, yet the Moon orbits the
+/// Earth. Ties go to the heavier body, then the lower id.
+///
+/// `None` when the body does not exist or nothing heavier pulls it.
+pub fn dominant_attractor(
+    bodies: &OrbitalBodies,
+    body: BodyId,
+    config: &PhysicsConfig,
+) -> Option<BodyId> {
+    let orbiting = bodies.get_by_id(body)?;
+
+    // Each candidate with its distance to the body and its pull on it
+    let candidates = bodies
+        .tier0
+        .values()
+        .filter(|other| {
+            other.id() != body && other.group_id == orbiting.group_id && other.mass > orbiting.mass
+        })
+        .map(|other| (other, distance(orbiting, other).1))
+        .filter(|(_, separation)| *separation > 0.)
+        .map(|(other, separation)| {
+            let pull = config.g * other.mass / separation.powf(config.exponent);
+            (other, separation, pull)
+        })
+        .collect::<Vec<_>>();
+
+    let by_pull = |(b1, _, pull1): &&(&Body, f64, f64), (b2, _, pull2): &&(&Body, f64, f64)| {
+        pull1
+            .total_cmp(pull2)
+            .then(b1.mass.total_cmp(&b2.mass))
+            .then(b2.id().cmp(&b1.id()))
+    };
+
+    let mut attractor = candidates.iter().max_by(by_pull)?.0;
+    while let Some(&(inner, ..)) = candidates
+        .iter()
+        .filter(|(other, separation, _)| {
+            let hill_radius =
+                distance(other, attractor).1 * (other.mass / (3. * attractor.mass)).powf(1. / 3.);
+            other.mass < attractor.mass && *separation < hill_radius
+        })
+        .max_by(by_pull)
+    {
+        attractor = inner;
+    }
+
+    Some(attractor.id())
+}
+
+/// Potential energy of a pair of bodies `distance` meters apart, in J. Its
+/// gradient is the pull of the configured law.
+fn pair_potential_energy(m1: f64, m2: f64, distance: f64, config: &PhysicsConfig) -> f64 {
+    let n = config.exponent;
+
+    if n == 1. {
+        config.g * m1 * m2 * distance.ln()
+    } else {
+        -config.g * m1 * m2 / ((n - 1.) * distance.powf(n - 1.))
+    }
+}
+
+/// Length added in quadrature to the distance between the bodies, to avoid
+/// slingshots on close encounters. Zero for [Gravity::Exact].
+fn softening(bi: &Body, bj: &Body, config: &PhysicsConfig) -> f64 {
+    let (mi, mj) = (bi.mass, bj.mass);
+
+    match config.gravity {
+        Gravity::Softened => {
+            (config.softening_factor * (mi.min(mj) / mi.max(mj)).sqrt()).min(1.)
+                * (bi.physical_radius + bj.physical_radius)
+        }
+        Gravity::Exact => 0.,
+    }
+}
+
+/// Distance between the bodies the pull is computed at, see [softening]
+fn softened_distance(bi: &Body, bj: &Body, config: &PhysicsConfig) -> f64 {
+    (distance_sq(bi, bj) + softening(bi, bj, config).powf(2.)).sqrt()
+}
+
+/// Potential energy of a pair of bodies, at the softened distance the pull is
+/// computed with. Bodies at the very same place are skipped, as their energy
+/// is infinite without softening, see [coincident_pair].
+fn bodies_potential_energy(bi: &Body, bj: &Body, config: &PhysicsConfig) -> f64 {
+    if bi.group_id != bj.group_id {
+        return 0.;
+    }
+
+    match softened_distance(bi, bj, config) {
+        0. => 0.,
+        distance => pair_potential_energy(bi.mass, bj.mass, distance, config),
+    }
+}
+
+/// The first pair of bodies pulling each other from the very same place,
+/// whose potential energy is left out, see [bodies_potential_energy]
+pub fn coincident_pair(bodies: &OrbitalBodies, config: &PhysicsConfig) -> Option<(BodyId, BodyId)> {
+    let tier0 = bodies.tier0.values().collect::<Vec<_>>();
+
+    tier0
+        .iter()
+        .enumerate()
+        .flat_map(|(i, bi)| {
+            tier0[i + 1..]
+                .iter()
+                .copied()
+                .chain(bodies.tier1.values())
+                .map(move |bj| (*bi, bj))
+        })
+        .find(|(bi, bj)| bi.group_id == bj.group_id && softened_distance(bi, bj, config) == 0.)
+        .map(|(bi, bj)| (bi.id(), bj.id()))
+}
+
+fn pairwise_acceleration(pullee: &Body, pulling: &Body, config: &PhysicsConfig) -> (f64, f64) {
+    // Separate systems, see [Body::group_id]
+    if pullee.group_id != pulling.group_id {
+        return (0., 0.);
+    }
+
     let bi = pullee;
     let bj = pulling;
 
     let pos_i = bi.pos();
     let pos_j = bj.pos();
 
-    let (d2, _) = distance(bi, bj);
+    let d2 = distance_sq(bi, bj);
 
-    let mi = bi.mass;
-    let mj = bj.mass;
-
-    let body_grav_constant = -G * mj;
+    let body_grav_constant = -config.g * bj.mass;
 
     // Use softening to avoid slingshot of bodies
-    let softening = (0.7 * (mi.min(mj) / mi.max(mj)).sqrt()).min(1.)
-        * (pullee.physical_radius + pulling.physical_radius);
-    let softened_distance = (d2 + softening.powf(2.)).powf(1.5);
+    let softening = softening(bi, bj, config);
+    let softened_distance = (d2 + softening.powf(2.)).powf((config.exponent + 1.) / 2.);
+
+    // On top of each other, there is no direction to pull in
+    if softened_distance == 0. {
+        return (0., 0.);
+    }
 
     let x_acc = (body_grav_constant * (pos_i.0 - pos_j.0)) / softened_distance;
     let y_acc = (body_grav_constant * (pos_i.1 - pos_j.1)) / softened_distance;
@@ -77,6 +349,25 @@ fn pairwise_acceleration(pullee: &Body, pulling: &Body) -> (f64, f64) {
     (x_acc, y_acc)
 }
 
+/// Deceleration of a body by the medium it moves through, relative to the
+/// origin. Zero unless the body has a drag coefficient.
+///
+/// Drag takes energy out of the system: with drag, the energy delta shown in
+/// the HUD no longer measures the accuracy of the integrator.
+fn drag_acceleration(body: &Body) -> (f64, f64) {
+    if body.drag_coefficient == 0. {
+        return (0., 0.);
+    }
+
+    let (vx, vy) = body.velocity;
+    let factor = match body.drag_law {
+        DragLaw::Linear => body.drag_coefficient,
+        DragLaw::Quadratic => body.drag_coefficient * body.actual_velocity(),
+    };
+
+    (-factor * vx, -factor * vy)
+}
+
 /// Update the acceleration of each bodies relative to one another.
 /// This is an expensive operation, because the acceleration of a body
 /// depends on **all the other bodies**. This means the performance is
@@ -86,6 +377,7 @@ fn pairwise_acceleration(pullee: &Body, pulling: &Body) -> (f64, f64) {
 pub fn update_acceleration(
     bodies: &mut OrbitalBodies,
     potential_energy: &mut f64,
+    config: &PhysicsConfig,
 ) -> HashMap<BodyId, (f64, f64)> {
     let mut potential_energy_acc = 0.;
 
@@ -107,59 +399,175 @@ pub fn update_acceleration(
 
             let pulling = bodies.tier0.get(&pulling_id).unwrap();
 
-            let (x, y) = pairwise_acceleration(pullee, pulling);
+            let (x, y) = pairwise_acceleration(pullee, pulling, config);
             x_acc += x;
             y_acc += y;
         }
 
+        let (x_drag, y_drag) = drag_acceleration(pullee);
+        x_acc += x_drag;
+        y_acc += y_drag;
+
         // Re-borrow for mutability
         let pullee = &mut bodies.tier0.get_mut(&pullee_id).unwrap();
         pullee.accel = (x_acc, y_acc);
         accelerations.insert(pullee.id(), (x_acc, y_acc));
     }
 
-    // Pullee are tier1, only pulled by tier0
+    potential_energy_acc += apply_constraints(bodies, &mut accelerations);
+
+    let tier1_potential_energy = update_tier1_acceleration(bodies, config, &mut accelerations);
+
+    // Count tier0 gravity
+    let n = body_ids.len();
+    for i in 0..n {
+        for j in i + 1..n {
+            let bi = bodies.get_by_id(body_ids[i]).unwrap();
+            let bj = bodies.get_by_id(body_ids[j]).unwrap();
+
+            potential_energy_acc += bodies_potential_energy(bi, bj, config);
+        }
+    }
+
+    potential_energy_acc += tier1_potential_energy;
+
+    *potential_energy = potential_energy_acc;
+
+    accelerations
+}
+
+/// Update the acceleration of the tier 1 bodies, which are only pulled by
+/// the tier 0 bodies at their current positions. This is `O(n * m)` for `n`
+/// tier 0 bodies and `m` tier 1 bodies. Returns their potential energy in
+/// the field of the tier 0 bodies, see [tier1_potential_energy].
+///
+/// With [PhysicsConfig::tier1_throttle_distance], the bodies far from all
+/// the tier 0 ones skip some updates, drifting without acceleration and
+/// counting the potential energy of their last update.
+pub fn update_tier1_acceleration(
+    bodies: &mut OrbitalBodies,
+    config: &PhysicsConfig,
+    accelerations: &mut HashMap<BodyId, (f64, f64)>,
+) -> f64 {
+    let mut potential_energy = 0.;
+
     for (pullee_id, pullee) in bodies.tier1.iter_mut() {
+        if config.tier1_throttle_distance.is_some() && pullee.accel_countdown > 0 {
+            pullee.accel_countdown -= 1;
+            pullee.accel = (0., 0.);
+            accelerations.insert(*pullee_id, pullee.accel);
+            potential_energy += pullee.tier1_potential_energy;
+            continue;
+        }
+
         let mut x_acc = 0.0;
         let mut y_acc = 0.0;
+        let mut nearest_sq = f64::INFINITY;
+        pullee.tier1_potential_energy = 0.;
 
         for (_, pulling) in bodies.tier0.iter() {
-            let (x, y) = pairwise_acceleration(pullee, pulling);
+            let (x, y) = pairwise_acceleration(pullee, pulling, config);
             x_acc += x;
             y_acc += y;
+            nearest_sq = nearest_sq.min(distance_sq(pullee, pulling));
+            pullee.tier1_potential_energy += bodies_potential_energy(pulling, pullee, config);
         }
+        potential_energy += pullee.tier1_potential_energy;
+
+        pullee.accel_countdown = match config.tier1_throttle_distance {
+            Some(throttle_distance) => {
+                let steps = (nearest_sq.sqrt() / throttle_distance) as usize;
+                steps.clamp(1, MAX_THROTTLED_STEPS) - 1
+            }
+            None => 0,
+        };
+
+        let (x_drag, y_drag) = drag_acceleration(pullee);
+        x_acc += x_drag;
+        y_acc += y_drag;
 
         pullee.accel = (x_acc, y_acc);
         accelerations.insert(*pullee_id, (x_acc, y_acc));
     }
 
-    // Count tier0 gravity
-    let n = body_ids.len();
-    for i in 0..n {
-        for j in i + 1..n {
-            let bi = bodies.get_by_id(body_ids[i]).unwrap();
-            let bj = bodies.get_by_id(body_ids[j]).unwrap();
+    potential_energy
+}
 
-            let (_, d) = distance(bi, bj);
-            let g = -G * bi.mass * bj.mass;
-            potential_energy_acc += g / d;
-        }
+/// Step the tier 0 bodies alone, the tier 1 ones held in place with their
+/// velocity and acceleration kept as they were, see
+/// [PhysicsConfig::freeze_tier1]. The energy of the frozen bodies is still
+/// counted, so that freezing them does not show as a drift.
+pub fn step_frozen_tier1(
+    kinematics: &dyn Kinematics,
+    bodies: &mut OrbitalBodies,
+    dt: f64,
+    config: &PhysicsConfig,
+) -> KinematicsDiagnostic {
+    let tier1 = std::mem::take(&mut bodies.tier1);
+    let tier0_diagnostic = kinematics.step(bodies, dt, config);
+    bodies.tier1 = tier1;
+
+    KinematicsDiagnostic {
+        kinetic_energy: tier0_diagnostic.kinetic_energy
+            + bodies
+                .tier1
+                .values()
+                .filter(|body| !body.fixed)
+                .map(Body::kinetic_energy)
+                .sum::<f64>(),
+        potential_energy: tier0_diagnostic.potential_energy
+            + tier1_potential_energy(bodies, config),
     }
+}
 
-    // Count tier1 gravity, no chance of doubling up here
-    for i in 0..n {
-        for bj in bodies.tier1.values() {
-            let bi = bodies.get_by_id(body_ids[i]).unwrap();
+/// Recompute the acceleration of the tier 1 bodies from scratch, as it went
+/// stale while they were frozen and the tier 0 bodies moved on
+pub fn thaw_tier1(bodies: &mut OrbitalBodies, config: &PhysicsConfig) {
+    for body in bodies.tier1.values_mut() {
+        body.accel_countdown = 0;
+    }
+
+    update_tier1_acceleration(bodies, config, &mut HashMap::new());
+}
+
+/// Potential energy of the tier 1 bodies in the field of the tier 0 bodies
+pub fn tier1_potential_energy(bodies: &OrbitalBodies, config: &PhysicsConfig) -> f64 {
+    let mut potential_energy = 0.;
 
-            let (_, d) = distance(bi, bj);
-            let g = -G * bi.mass * bj.mass;
-            potential_energy_acc += g / d;
+    // No chance of doubling up here, tier 1 bodies do not pull each other
+    for bi in bodies.tier0.values() {
+        for bj in bodies.tier1.values() {
+            potential_energy += bodies_potential_energy(bi, bj, config);
         }
     }
 
-    *potential_energy = potential_energy_acc;
+    potential_energy
+}
 
-    accelerations
+/// Every contribution to the potential energy, one per pair of bodies that
+/// pull each other, the tier 0 pairs then the tier 0 and tier 1 ones. They
+/// add up to the potential energy of [update_acceleration], to find out
+/// which pairs dominate it.
+#[cfg(debug_assertions)]
+#[allow(unused)]
+pub fn pairwise_potential_energies(
+    bodies: &OrbitalBodies,
+    config: &PhysicsConfig,
+) -> Vec<(BodyId, BodyId, f64)> {
+    let tier0 = bodies.tier0.values().collect::<Vec<_>>();
+
+    let tier0_pairs = tier0
+        .iter()
+        .enumerate()
+        .flat_map(|(i, bi)| tier0[i + 1..].iter().map(move |bj| (*bi, *bj)));
+    let tier1_pairs = tier0
+        .iter()
+        .flat_map(|bi| bodies.tier1.values().map(move |bj| (*bi, bj)));
+
+    tier0_pairs
+        .chain(tier1_pairs)
+        .map(|(bi, bj)| (bi.id(), bj.id(), bodies_potential_energy(bi, bj, config)))
+        .collect()
 }
 
 /// Given an angle in radians, a radius (a distance in meters), computes the position of the second
@@ -172,6 +580,26 @@ pub fn orient(theta: f64, radius: f64, pos: (f64, f64)) -> (f64, f64) {
     (x + dx, y + dy)
 }
 
+/// Speed of a circular orbit `radius` meters from a body of `central_mass`
+/// Kg, `sqrt(g M / r)`, in m/s
+pub fn circular_velocity(central_mass: f64, radius: f64, g: f64) -> f64 {
+    (g * central_mass / radius).sqrt()
+}
+
+/// Place the body on a circular orbit `radius` meters around `central`, at
+/// the angle `theta` from the x axis, counterclockwise. Like [kepler_orbit]
+/// with no eccentricity, the orbit is relative to the central body, moving
+/// along with it.
+#[allow(unused)]
+pub fn place_circular(body: &mut Body, central: &Body, radius: f64, theta: f64, g: f64) {
+    // Both masses count, for the orbit of the pair
+    let speed = circular_velocity(central.mass + body.mass, radius, g);
+    let ((cx, cy), (cvx, cvy)) = (central.pos(), central.velocity);
+
+    body.set_pos((cx + theta.cos() * radius, cy + theta.sin() * radius));
+    body.velocity = (cvx - theta.sin() * speed, cvy + theta.cos() * speed);
+}
+
 /// Parameters to create an orbit
 pub struct OrbitParameters {
     /// Semi-major axis, meters
@@ -182,14 +610,27 @@ pub struct OrbitParameters {
     pub theta: f64,
 }
 
-/// Configure the orbit of a body around another one.
+/// Configure the orbit of a body around another one, under the gravity
+/// constant `g`.
+///
+/// The orbit is relative to the point of reference, wherever it is and
+/// however it moves. The point of reference is left untouched, so for bodies
+/// of comparable masses their barycenter drifts, see [kepler_orbit_barycentric].
 ///
 /// Warning:
+/// The orbit is Keplerian, only valid for Newtonian gravity: with any other
+/// [PhysicsConfig::exponent], the body does not follow it.
+///
 /// Overwrites the position of the orbiting body, as well as the velocity.
-/// This means you should set the mass of the bodies and place them both
-/// anywhere, then configure the orbit.
-pub fn kepler_orbit(orb: OrbitParameters, orbiting_body: &mut Body, point_of_reference: &Body) {
-    let mu = G * (orbiting_body.mass + point_of_reference.mass);
+/// This means you should set the mass of the bodies and place the point of
+/// reference first, then configure the orbit.
+pub fn kepler_orbit(
+    orb: OrbitParameters,
+    orbiting_body: &mut Body,
+    point_of_reference: &Body,
+    g: f64,
+) {
+    let mu = g * (orbiting_body.mass + point_of_reference.mass);
 
     let p = orb.a * (1. - orb.e.powf(2.0));
 
@@ -204,9 +645,736 @@ pub fn kepler_orbit(orb: OrbitParameters, orbiting_body: &mut Body, point_of_ref
         (orb.theta.cos() + orb.e) * factor,
     );
 
-    let total_mass = orbiting_body.mass + point_of_reference.mass;
-    let m2_mt = (point_of_reference.mass) / total_mass;
+    let (px, py) = point_of_reference.pos();
+    let (pvx, pvy) = point_of_reference.velocity;
+
+    orbiting_body.set_pos((px + rx, py + ry));
+    orbiting_body.velocity = (pvx + vx, pvy + vy);
+}
+
+/// Configure the orbit of two bodies around their common barycenter.
+///
+/// Like [kepler_orbit], with the primary taken as the point of reference,
+/// but the separation and the relative velocity are then split between both
+/// bodies by their masses: their barycenter sits at the former position of
+/// the primary, moving at its former velocity, so their net momentum in that
+/// frame is zero.
+pub fn kepler_orbit_barycentric(
+    orb: OrbitParameters,
+    orbiting_body: &mut Body,
+    primary: &mut Body,
+    g: f64,
+) {
+    kepler_orbit(orb, orbiting_body, primary, g);
+
+    let total_mass = orbiting_body.mass + primary.mass;
+    let (px, py) = primary.pos();
+    let (pvx, pvy) = primary.velocity;
+    let (rx, ry) = (orbiting_body.pos().0 - px, orbiting_body.pos().1 - py);
+    let (vx, vy) = (
+        orbiting_body.velocity.0 - pvx,
+        orbiting_body.velocity.1 - pvy,
+    );
+
+    let shares = [-orbiting_body.mass / total_mass, primary.mass / total_mass];
+    for (body, share) in [primary, orbiting_body].into_iter().zip(shares) {
+        body.set_pos((px + share * rx, py + share * ry));
+        body.velocity = (pvx + share * vx, pvy + share * vy);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::body::{Body, OrbitalBodies, bodies_to_map};
+    use crate::constants::{
+        AU, EARTH_MASS, EARTH_MOON_DISTANCE, EARTH_RADIUS, EARTH_SUN_VELOCITY, MOON_MASS,
+        SUN_EARTH_DISTANCE, SUN_MASS, SUN_RADIUS,
+    };
+    use crate::physics::euler::Euler;
+    use crate::physics::leapfrog::{Leapfrog, LeapfrogKDK};
+    #[cfg(debug_assertions)]
+    use crate::physics::pairwise_potential_energies;
+    use crate::physics::tiered::Tiered;
+    use crate::physics::{
+        G, Gravity, Kinematics, KinematicsDiagnostic, OrbitParameters, PhysicsConfig,
+        bodies_potential_energy, circular_velocity, distance_sq, dominant_attractor, kepler_orbit,
+        kepler_orbit_barycentric, pair_potential_energy, pairwise_acceleration, place_circular,
+        tier1_potential_energy, update_acceleration,
+    };
+    use raylib::color::Color;
+    use rayon::ThreadPoolBuilder;
+
+    #[test]
+    fn test_exact_gravity_circular_period() {
+        let (m1, m2) = (SUN_MASS, EARTH_MASS);
+        let r = SUN_EARTH_DISTANCE;
+        let mu = G * (m1 + m2);
+        let v = (mu / r).sqrt();
+        let period = 2. * std::f64::consts::PI * (r.powf(3.) / mu).sqrt();
+
+        // Both bodies circle the barycenter, at the origin
+        let sun = Body::new(
+            m1,
+            (-m2 / (m1 + m2) * r, 0.),
+            SUN_RADIUS,
+            1.,
+            Color::YELLOW,
+            (0., -m2 / (m1 + m2) * v),
+            (0., 0.),
+        );
+        let earth = Body::new(
+            m2,
+            (m1 / (m1 + m2) * r, 0.),
+            EARTH_RADIUS,
+            1.,
+            Color::BLUE,
+            (0., m1 / (m1 + m2) * v),
+            (0., 0.),
+        );
+        let (sun_id, earth_id) = (sun.id(), earth.id());
+
+        let mut bodies = OrbitalBodies::new(bodies_to_map(vec![sun, earth]), bodies_to_map(vec![]));
+
+        let steps = 20_000;
+        let dt = period / steps as f64;
+        update_acceleration(&mut bodies, &mut 0., &Gravity::Exact.into());
+
+        let relative_angle = |bodies: &OrbitalBodies| {
+            let (x1, y1) = bodies.get_by_id(sun_id).unwrap().pos();
+            let (x2, y2) = bodies.get_by_id(earth_id).unwrap().pos();
+            (y2 - y1).atan2(x2 - x1)
+        };
+
+        // Find the step at which the orbit closes, from below the x axis to above
+        let mut previous = relative_angle(&bodies);
+        let mut crossing = None;
+        for step in 1..=steps + steps / 100 {
+            Leapfrog.step(&mut bodies, dt, &Gravity::Exact.into());
+            let angle = relative_angle(&bodies);
+
+            if step > steps / 2 && previous < 0. && angle >= 0. {
+                // Interpolate the exact crossing time within the step
+                let fraction = -previous / (angle - previous);
+                crossing = Some((step as f64 - 1. + fraction) * dt);
+                break;
+            }
+            previous = angle;
+        }
+
+        let measured = crossing.expect("the orbit should close");
+        assert!(
+            ((measured - period) / period).abs() < 1e-6,
+            "measured {measured}, expected {period}"
+        );
+    }
+
+    #[test]
+    fn test_virial_ratio() {
+        let circular = KinematicsDiagnostic {
+            kinetic_energy: 1.,
+            potential_energy: -2.,
+        };
+        assert_eq!(circular.virial_ratio(), Some(1.));
+
+        let free = KinematicsDiagnostic {
+            kinetic_energy: 1.,
+            potential_energy: 0.,
+        };
+        assert_eq!(free.virial_ratio(), None);
+    }
+
+    #[test]
+    fn test_kepler_orbit_moving_reference() {
+        let reference = Body::new(
+            SUN_MASS,
+            (5. * AU, -3. * AU),
+            1.,
+            1.,
+            Color::YELLOW,
+            (10_000., 2_000.),
+            (0., 0.),
+        );
+        let mut orbiting = Body::new(
+            EARTH_MASS,
+            (0., 0.),
+            1.,
+            1.,
+            Color::BLUE,
+            (0., 0.),
+            (0., 0.),
+        );
+
+        let (a, e, theta) = (AU, 0.2, 0.7);
+        kepler_orbit(
+            OrbitParameters { a, e, theta },
+            &mut orbiting,
+            &reference,
+            G,
+        );
+
+        let (x, y) = orbiting.pos();
+        let (rx, ry) = (x - reference.pos().0, y - reference.pos().1);
+        let r = (rx.powf(2.) + ry.powf(2.)).sqrt();
+        let (vx, vy) = (
+            orbiting.velocity.0 - reference.velocity.0,
+            orbiting.velocity.1 - reference.velocity.1,
+        );
+        let v2 = vx.powf(2.) + vy.powf(2.);
+
+        // Distance from the focus, and speed from the vis-viva equation
+        let mu = G * (SUN_MASS + EARTH_MASS);
+        let expected_r = a * (1. - e.powf(2.)) / (1. + e * theta.cos());
+        assert!((r - expected_r).abs() / expected_r < 1e-12);
+        assert!((ry.atan2(rx) - theta).abs() < 1e-12);
+
+        let expected_v2 = mu * (2. / r - 1. / a);
+        assert!((v2 - expected_v2).abs() / expected_v2 < 1e-12);
+    }
+
+    #[test]
+    fn test_drag_slows_down() {
+        let mut body = Body::new(1., (0., 0.), 1., 1., Color::WHITE, (30., 40.), (0., 0.));
+        body.drag_coefficient = 0.01;
+        let id = body.id();
+
+        let mut bodies = OrbitalBodies::new(bodies_to_map(vec![body]), bodies_to_map(vec![]));
+
+        // Quadratic drag alone gives v(t) = v0 / (1 + k * v0 * t)
+        let dt = 0.01;
+        let mut previous = 50.;
+        for _ in 0..10_000 {
+            Euler.step(&mut bodies, dt, &PhysicsConfig::default());
+
+            let speed = bodies.get_by_id(id).unwrap().actual_velocity();
+            assert!(speed < previous);
+            previous = speed;
+        }
+
+        let expected = 50. / (1. + 0.01 * 50. * 100.);
+        assert!((previous - expected).abs() / expected < 0.01);
+
+        // The direction is kept
+        let (vx, vy) = bodies.get_by_id(id).unwrap().velocity;
+        assert!((vy / vx - 4. / 3.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_integrators_agree_on_energy() {
+        // A pinned sun left with a velocity, which carries no kinetic energy
+        let system = || {
+            let mut sun = Body::new(
+                SUN_MASS,
+                (0., 0.),
+                SUN_RADIUS,
+                1.,
+                Color::YELLOW,
+                (1_000., 0.),
+                (0., 0.),
+            );
+            sun.fixed = true;
+
+            let mut earth = Body::new(
+                EARTH_MASS,
+                (0., 0.),
+                EARTH_RADIUS,
+                1.,
+                Color::BLUE,
+                (0., 0.),
+                (0., 0.),
+            );
+            kepler_orbit(
+                OrbitParameters {
+                    a: AU,
+                    e: 0.1,
+                    theta: 1.,
+                },
+                &mut earth,
+                &sun,
+                G,
+            );
+
+            let mut bodies =
+                OrbitalBodies::new(bodies_to_map(vec![sun, earth]), bodies_to_map(vec![]));
+            update_acceleration(&mut bodies, &mut 0., &PhysicsConfig::default());
+            bodies
+        };
+
+        let integrators: [Box<dyn Kinematics>; 3] =
+            [Box::new(Euler), Box::new(Leapfrog), Box::new(LeapfrogKDK)];
+        let energies = integrators
+            .iter()
+            .map(|integrator| {
+                let mut bodies = system();
+                let expected_kinetic = bodies.total_kinetic_energy();
+                let diagnostic = integrator.step(&mut bodies, 1e-3, &PhysicsConfig::default());
+
+                assert!(
+                    (diagnostic.kinetic_energy - expected_kinetic).abs() / expected_kinetic < 1e-9
+                );
+                diagnostic.total()
+            })
+            .collect::<Vec<_>>();
+
+        for energy in &energies[1..] {
+            assert!((energy - energies[0]).abs() / energies[0].abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_gravity_law_exponent() {
+        let sun = Body::new(
+            SUN_MASS,
+            (0., 0.),
+            SUN_RADIUS,
+            1.,
+            Color::YELLOW,
+            (0., 0.),
+            (0., 0.),
+        );
+        let earth = Body::new(
+            EARTH_MASS,
+            (AU, 0.),
+            EARTH_RADIUS,
+            1.,
+            Color::BLUE,
+            (0., 0.),
+            (0., 0.),
+        );
+
+        for exponent in [1., 1.5, 2., 3.] {
+            let config = PhysicsConfig {
+                gravity: Gravity::Exact,
+                exponent,
+                ..Default::default()
+            };
+
+            let (ax, ay) = pairwise_acceleration(&earth, &sun, &config);
+            let expected = -G * SUN_MASS / AU.powf(exponent);
+            assert!((ax - expected).abs() / expected.abs() < 1e-9);
+            assert_eq!(ay, 0.);
+
+            // The force is the derivative of the potential energy
+            let h = 1e-4 * AU;
+            let force = (pair_potential_energy(SUN_MASS, EARTH_MASS, AU + h, &config)
+                - pair_potential_energy(SUN_MASS, EARTH_MASS, AU - h, &config))
+                / (2. * h);
+            assert!((force / EARTH_MASS + expected).abs() / expected.abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_coincident_bodies() {
+        let body = || {
+            Body::new(
+                EARTH_MASS,
+                (AU, 0.),
+                1.,
+                1.,
+                Color::BLUE,
+                (0., 0.),
+                (0., 0.),
+            )
+        };
+
+        let mut bodies = OrbitalBodies::new(
+            bodies_to_map(vec![body(), body()]),
+            bodies_to_map(vec![body()]),
+        );
+
+        for gravity in [Gravity::Softened, Gravity::Exact] {
+            let diagnostic = Leapfrog.step(&mut bodies, 3600., &gravity.into());
+
+            assert!(diagnostic.total().is_finite());
+            assert!(bodies.iter().all(|body| body.pos().0.is_finite()));
+        }
+    }
+
+    #[test]
+    fn test_parallel_step_matches_serial() {
+        // No body has more than two pullers, the tier 0 ones pull each other
+        // and both pull the tier 1 ones. Adding two terms gives the same
+        // float in either order, so the accelerations do not depend on the
+        // order the map is walked in.
+        let mut bodies = OrbitalBodies::new(
+            bodies_to_map(vec![
+                Body::new(
+                    SUN_MASS,
+                    (0., 0.),
+                    1.,
+                    1.,
+                    Color::YELLOW,
+                    (0., 0.),
+                    (0., 0.),
+                ),
+                Body::new(
+                    EARTH_MASS,
+                    (0., SUN_EARTH_DISTANCE),
+                    1.,
+                    1.,
+                    Color::BLUE,
+                    (29_780., 0.),
+                    (0., 0.),
+                ),
+            ]),
+            bodies_to_map(
+                (1..500)
+                    .map(|i| {
+                        let theta = i as f64;
+                        let r = AU * (2. + i as f64 / 500.);
+                        let v = (G * SUN_MASS / r).sqrt();
+                        Body::new(
+                            1.,
+                            (r * theta.cos(), r * theta.sin()),
+                            1.,
+                            1.,
+                            Color::GRAY,
+                            (-v * theta.sin(), v * theta.cos()),
+                            (0., 0.),
+                        )
+                    })
+                    .collect(),
+            ),
+        );
+
+        let state = |bodies: &OrbitalBodies| {
+            let mut state = bodies
+                .iter()
+                .map(|body| (body.id(), body.pos(), body.velocity))
+                .collect::<Vec<_>>();
+            state.sort_by_key(|(id, ..)| *id);
+            state
+        };
+
+        // Integrators are not Sync, each pool builds its own
+        let integrator = |i: usize| -> Box<dyn Kinematics> {
+            match i {
+                0 => Box::new(Euler),
+                1 => Box::new(Leapfrog),
+                2 => Box::new(LeapfrogKDK),
+                _ => Box::new(Tiered::new(Box::new(LeapfrogKDK), "Tiered")),
+            }
+        };
+        let serial = ThreadPoolBuilder::new().num_threads(1).build().unwrap();
+        let parallel = ThreadPoolBuilder::new().num_threads(4).build().unwrap();
+
+        for i in 0..4 {
+            let step = |pool: &rayon::ThreadPool, bodies: &mut OrbitalBodies| {
+                pool.install(|| integrator(i).step(bodies, 3600., &PhysicsConfig::default()));
+                state(bodies)
+            };
+
+            update_acceleration(&mut bodies, &mut 0., &PhysicsConfig::default());
+            let before = state(&bodies);
+            let serial_state = step(&serial, &mut bodies);
+
+            // Rewind to step again from the same state
+            for (id, pos, velocity) in &before {
+                let body = bodies.get_mut_by_id(*id).unwrap();
+                body.set_pos(*pos);
+                body.velocity = *velocity;
+            }
+            update_acceleration(&mut bodies, &mut 0., &PhysicsConfig::default());
+
+            assert_eq!(step(&parallel, &mut bodies), serial_state);
+        }
+    }
+
+    #[test]
+    fn test_tier1_throttle() {
+        let sun = Body::new(
+            SUN_MASS,
+            (0., 0.),
+            1.,
+            1.,
+            Color::YELLOW,
+            (0., 0.),
+            (0., 0.),
+        );
+        let sun_id = sun.id();
+        let near = Body::new(1., (AU / 2., 0.), 1., 1., Color::GRAY, (0., 0.), (0., 0.));
+        let near_id = near.id();
+        let far = Body::new(1., (5. * AU, 0.), 1., 1., Color::GRAY, (0., 0.), (0., 0.));
+        let far_id = far.id();
+
+        let mut bodies =
+            OrbitalBodies::new(bodies_to_map(vec![sun]), bodies_to_map(vec![near, far]));
+        let fresh = |bodies: &OrbitalBodies, id| {
+            let sun = bodies.get_by_id(sun_id).unwrap();
+            pairwise_acceleration(
+                bodies.get_by_id(id).unwrap(),
+                sun,
+                &PhysicsConfig::default(),
+            )
+        };
+        let move_out = |bodies: &mut OrbitalBodies| {
+            for id in [near_id, far_id] {
+                let body = bodies.get_mut_by_id(id).unwrap();
+                let (x, y) = body.pos();
+                body.set_pos((x * 1.1, y));
+            }
+        };
+
+        let throttled = PhysicsConfig {
+            tier1_throttle_distance: Some(AU),
+            ..Default::default()
+        };
+        let mut potential_energy = 0.;
+        update_acceleration(&mut bodies, &mut potential_energy, &throttled);
+        let far_energy = bodies.get_by_id(far_id).unwrap().tier1_potential_energy;
+        move_out(&mut bodies);
+        let accelerations = update_acceleration(&mut bodies, &mut potential_energy, &throttled);
+
+        // The far body drifts, and none of its work is done again: 5 AU
+        // out, it is only updated every 5 steps
+        let far = bodies.get_by_id(far_id).unwrap();
+        assert_eq!(accelerations[&near_id], fresh(&bodies, near_id));
+        assert_eq!(accelerations[&far_id], (0., 0.));
+        assert_eq!(far.accel, (0., 0.));
+        assert_eq!(far.accel_countdown, 3);
+        assert_eq!(far.tier1_potential_energy, far_energy);
+
+        // Its potential energy is the one from before moving
+        let near_energy = bodies_potential_energy(
+            bodies.get_by_id(sun_id).unwrap(),
+            bodies.get_by_id(near_id).unwrap(),
+            &throttled,
+        );
+        assert_eq!(potential_energy, near_energy + far_energy);
+        assert_ne!(
+            potential_energy,
+            tier1_potential_energy(&bodies, &throttled)
+        );
+
+        // Without throttling, every body is exact again right away
+        move_out(&mut bodies);
+        let accelerations = update_acceleration(&mut bodies, &mut 0., &PhysicsConfig::default());
+        for id in [near_id, far_id] {
+            assert_eq!(accelerations[&id], fresh(&bodies, id));
+            assert_eq!(bodies.get_by_id(id).unwrap().accel, fresh(&bodies, id));
+        }
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn test_pairwise_potential_energies() {
+        let body = |mass: f64, pos: (f64, f64)| {
+            Body::new(mass, pos, 1., 1., Color::GRAY, (0., 0.), (0., 0.))
+        };
+
+        let sun = body(SUN_MASS, (0., 0.));
+        let earth = body(EARTH_MASS, (AU, 0.));
+        let pair = (sun.id(), earth.id());
+
+        let mut bodies = OrbitalBodies::new(
+            bodies_to_map(vec![sun, earth, body(EARTH_MASS, (0., -2. * AU))]),
+            bodies_to_map(vec![body(1e15, (3. * AU, 0.)), body(1e15, (0., 4. * AU))]),
+        );
+
+        for gravity in [Gravity::Softened, Gravity::Exact] {
+            let config = gravity.into();
+
+            let mut potential_energy = 0.;
+            update_acceleration(&mut bodies, &mut potential_energy, &config);
 
-    orbiting_body.set_pos((-m2_mt * rx, -m2_mt * ry));
-    orbiting_body.velocity = (-m2_mt * vx, -m2_mt * vy);
+            let contributions = pairwise_potential_energies(&bodies, &config);
+            // 3 tier 0 pairs, and each tier 1 body with each tier 0 one
+            assert_eq!(contributions.len(), 3 + 2 * 3);
+
+            let sum = contributions
+                .iter()
+                .map(|(_, _, energy)| energy)
+                .sum::<f64>();
+            assert!((sum - potential_energy).abs() / potential_energy.abs() < 1e-12);
+
+            // The sun and the closest planet dominate
+            let (i, j, _) = contributions
+                .iter()
+                .min_by(|a, b| a.2.total_cmp(&b.2))
+                .unwrap();
+            assert!(pair == (*i, *j) || pair == (*j, *i));
+        }
+    }
+
+    #[test]
+    fn test_place_circular() {
+        let sun = Body::new(
+            SUN_MASS,
+            (AU, AU),
+            1.,
+            1.,
+            Color::YELLOW,
+            (0., 0.),
+            (0., 0.),
+        );
+        let mut earth = Body::new(
+            EARTH_MASS,
+            (0., 0.),
+            1.,
+            1.,
+            Color::BLUE,
+            (0., 0.),
+            (0., 0.),
+        );
+        place_circular(&mut earth, &sun, AU, 2., G);
+        assert!(
+            (circular_velocity(SUN_MASS, AU, G) - EARTH_SUN_VELOCITY).abs() / EARTH_SUN_VELOCITY
+                < 1e-3
+        );
+
+        let (sun_id, earth_id) = (sun.id(), earth.id());
+        let mut bodies = OrbitalBodies::new(bodies_to_map(vec![sun, earth]), bodies_to_map(vec![]));
+        let config = Gravity::Exact.into();
+        update_acceleration(&mut bodies, &mut 0., &config);
+
+        let radius = |bodies: &OrbitalBodies| {
+            distance_sq(&bodies.tier0[&sun_id], &bodies.tier0[&earth_id]).sqrt()
+        };
+
+        // A year, about one orbit, without the radius wandering off
+        for _ in 0..365 * 24 {
+            Leapfrog.step(&mut bodies, 3600., &config);
+            assert!((radius(&bodies) - AU).abs() / AU < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_groups_do_not_attract() {
+        let body = |x: f64, group_id: usize| {
+            let mut body = Body::new(SUN_MASS, (x, 0.), 1., 1., Color::GRAY, (0., 0.), (0., 0.));
+            body.group_id = group_id;
+            body
+        };
+        let (star, other, companion, asteroid) =
+            (body(0., 0), body(AU, 1), body(2. * AU, 1), body(-AU, 1));
+        let (star_id, other_id, asteroid_id) = (star.id(), other.id(), asteroid.id());
+
+        let mut bodies = OrbitalBodies::new(
+            bodies_to_map(vec![star, other, companion]),
+            bodies_to_map(vec![asteroid]),
+        );
+
+        let mut potential_energy = 0.;
+        let accelerations =
+            update_acceleration(&mut bodies, &mut potential_energy, &Gravity::Exact.into());
+
+        // Alone in its group, the star is not pulled, nor does it pull
+        assert_eq!(accelerations[&star_id], (0., 0.));
+        assert!(accelerations[&other_id].0 > 0.);
+        assert!(accelerations[&asteroid_id].0 > 0.);
+
+        // Only the pairs within group 1 count: the planets AU apart, and the
+        // asteroid 2 and 3 AU from them
+        let unit = G * SUN_MASS * SUN_MASS / AU;
+        let expected = -unit * (1. + 1. / 2. + 1. / 3.);
+        assert!((potential_energy - expected).abs() / unit < 1e-9);
+    }
+
+    #[test]
+    fn test_dominant_attractor() {
+        let body =
+            |mass: f64, x: f64| Body::new(mass, (x, 0.), 1., 1., Color::GRAY, (0., 0.), (0., 0.));
+        let (sun, earth, moon) = (
+            body(SUN_MASS, 0.),
+            body(EARTH_MASS, AU),
+            body(MOON_MASS, AU + EARTH_MOON_DISTANCE),
+        );
+        let (sun_id, earth_id, moon_id) = (sun.id(), earth.id(), moon.id());
+        let asteroid = body(1., 2. * AU);
+        let asteroid_id = asteroid.id();
+
+        let mut bodies = OrbitalBodies::new(
+            bodies_to_map(vec![sun, earth, moon]),
+            bodies_to_map(vec![asteroid]),
+        );
+        let config = PhysicsConfig::default();
+
+        // The Sun pulls the Moon harder, but the Moon is inside the Earth's
+        // Hill sphere
+        assert_eq!(
+            dominant_attractor(&bodies, moon_id, &config),
+            Some(earth_id)
+        );
+        assert_eq!(dominant_attractor(&bodies, earth_id, &config), Some(sun_id));
+        assert_eq!(
+            dominant_attractor(&bodies, asteroid_id, &config),
+            Some(sun_id)
+        );
+        assert_eq!(dominant_attractor(&bodies, sun_id, &config), None);
+        assert_eq!(dominant_attractor(&bodies, 999_999, &config), None);
+
+        // Two equal stars as far, the lower id wins
+        let (left, right) = (body(SUN_MASS, -AU), body(SUN_MASS, AU));
+        let (left_id, right_id) = (left.id(), right.id());
+        let middle = body(1., 0.);
+        let middle_id = middle.id();
+        bodies.tier0 = bodies_to_map(vec![right, left, middle]);
+        assert_eq!(
+            dominant_attractor(&bodies, middle_id, &config),
+            Some(left_id.min(right_id))
+        );
+
+        // The near star pulls harder under 1/r^2, the heavy far one under 1/r
+        let (far, near) = (body(4. * SUN_MASS, 2. * AU), body(1.5 * SUN_MASS, AU));
+        let probe = body(1., 0.);
+        let (far_id, near_id, probe_id) = (far.id(), near.id(), probe.id());
+        bodies.tier0 = bodies_to_map(vec![far, near, probe]);
+        for (exponent, expected) in [(2., near_id), (1., far_id)] {
+            let config = PhysicsConfig {
+                exponent,
+                ..Default::default()
+            };
+            assert_eq!(
+                dominant_attractor(&bodies, probe_id, &config),
+                Some(expected)
+            );
+        }
+    }
+
+    #[test]
+    fn test_kepler_orbit_barycentric() {
+        let (center, drift) = ((AU, -2. * AU), (3000., -1000.));
+        let mut primary = Body::new(SUN_MASS, center, 1., 1., Color::YELLOW, drift, (0., 0.));
+        let mut secondary = Body::new(
+            0.5 * SUN_MASS,
+            (0., 0.),
+            1.,
+            1.,
+            Color::ORANGE,
+            (0., 0.),
+            (0., 0.),
+        );
+
+        let (a, e, theta) = (2. * AU, 0.3, 1.);
+        kepler_orbit_barycentric(
+            OrbitParameters { a, e, theta },
+            &mut secondary,
+            &mut primary,
+            G,
+        );
+
+        let total_mass = primary.mass + secondary.mass;
+        let weighted = |f: fn(&Body) -> (f64, f64)| {
+            let ((x1, y1), (x2, y2)) = (f(&primary), f(&secondary));
+            (
+                (primary.mass * x1 + secondary.mass * x2) / total_mass,
+                (primary.mass * y1 + secondary.mass * y2) / total_mass,
+            )
+        };
+
+        let barycenter = weighted(|body| body.pos());
+        assert!((barycenter.0 - center.0).abs() < 1e-6 * AU);
+        assert!((barycenter.1 - center.1).abs() < 1e-6 * AU);
+
+        // No net momentum in the frame of the former primary
+        let (vx, vy) = weighted(|body| body.velocity);
+        assert!((vx - drift.0).abs() < 1e-9 && (vy - drift.1).abs() < 1e-9);
+
+        // The bodies still are the requested distance apart
+        let (dx, dy) = (
+            secondary.pos().0 - primary.pos().0,
+            secondary.pos().1 - primary.pos().1,
+        );
+        let radius = a * (1. - e * e) / (1. + e * theta.cos());
+        assert!(((dx * dx + dy * dy).sqrt() - radius).abs() < 1e-6 * AU);
+    }
 }
@@ -0,0 +1,181 @@
+use crate::body::Body;
+
+/// The shape and orientation of the Keplerian ellipse of a body around its
+/// attractor, the inverse of [crate::physics::kepler_orbit]
+#[derive(Debug, Copy, Clone)]
+pub struct OrbitalElements {
+    /// Semi-major axis, meters
+    pub a: f64,
+    /// Eccentricity
+    pub e: f64,
+    /// Angle of the periapsis from the x axis, radians
+    pub periapsis_angle: f64,
+}
+
+impl OrbitalElements {
+    /// Elements of the orbit of `body` around `attractor`, as if they were
+    /// alone, from their current positions and velocities, under the gravity
+    /// constant `g`. `None` when the body is not bound to the attractor, its
+    /// orbit is then no ellipse.
+    pub fn of(body: &Body, attractor: &Body, g: f64) -> Option<Self> {
+        let mu = g * (body.mass + attractor.mass);
+
+        let ((x, y), (ax, ay)) = (body.pos(), attractor.pos());
+        let (rx, ry) = (x - ax, y - ay);
+        let (vx, vy) = (
+            body.velocity.0 - attractor.velocity.0,
+            body.velocity.1 - attractor.velocity.1,
+        );
+
+        let r = (rx.powf(2.) + ry.powf(2.)).sqrt();
+        let v_sq = vx.powf(2.) + vy.powf(2.);
+
+        let energy = v_sq / 2. - mu / r;
+        if r == 0. || energy >= 0. {
+            return None;
+        }
+
+        // Eccentricity vector, pointing to the periapsis
+        let radial = rx * vx + ry * vy;
+        let ex = ((v_sq - mu / r) * rx - radial * vx) / mu;
+        let ey = ((v_sq - mu / r) * ry - radial * vy) / mu;
+
+        Some(Self {
+            a: -mu / (2. * energy),
+            e: (ex.powf(2.) + ey.powf(2.)).sqrt(),
+            periapsis_angle: ey.atan2(ex),
+        })
+    }
+
+    /// `count` points evenly spread in true anomaly along the ellipse around
+    /// the attractor at `focus`, the first one repeated at the end to close
+    /// it.
+    pub fn ellipse_points(&self, focus: (f64, f64), count: usize) -> Vec<(f64, f64)> {
+        let p = self.a * (1. - self.e.powf(2.));
+        let (fx, fy) = focus;
+
+        let mut points = (0..count)
+            .map(|i| {
+                let theta = i as f64 / count as f64 * 2. * std::f64::consts::PI;
+                let radius = p / (1. + self.e * theta.cos());
+                let angle = theta + self.periapsis_angle;
+                (fx + angle.cos() * radius, fy + angle.sin() * radius)
+            })
+            .collect::<Vec<_>>();
+
+        if let Some(&first) = points.first() {
+            points.push(first);
+        }
+
+        points
+    }
+}
+
+/// Tisserand parameter of `body` relative to `planet`, both orbiting
+/// `attractor`, `T = a_p/a + 2 cos(i) sqrt(a/a_p (1 - e^2))`. It barely
+/// changes under the perturbations of the planet, so it tells apart the
+/// families of small bodies, such as the Jupiter family comets for `2 < T < 3`.
+///
+/// The simulation is planar: the inclination `i` is 0, and the `cos(i)` term
+/// drops out. `None` when either body is not bound to the attractor. The
+/// elements are taken under the gravity constant `g`.
+pub fn tisserand(body: &Body, planet: &Body, attractor: &Body, g: f64) -> Option<f64> {
+    let OrbitalElements { a, e, .. } = OrbitalElements::of(body, attractor, g)?;
+    let a_planet = OrbitalElements::of(planet, attractor, g)?.a;
+
+    Some(a_planet / a + 2. * (a / a_planet * (1. - e.powf(2.))).sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::body::Body;
+    use crate::constants::{AU, EARTH_MASS, SUN_MASS};
+    use crate::physics::orbit::{OrbitalElements, tisserand};
+    use crate::physics::{G, OrbitParameters, kepler_orbit};
+    use raylib::color::Color;
+
+    #[test]
+    fn test_elements_round_trip() {
+        let sun = Body::new(
+            SUN_MASS,
+            (AU, -AU),
+            1.,
+            1.,
+            Color::YELLOW,
+            (1000., 0.),
+            (0., 0.),
+        );
+        let mut earth = Body::new(
+            EARTH_MASS,
+            (0., 0.),
+            1.,
+            1.,
+            Color::BLUE,
+            (0., 0.),
+            (0., 0.),
+        );
+
+        let (a, e) = (1.5 * AU, 0.4);
+        kepler_orbit(OrbitParameters { a, e, theta: 1. }, &mut earth, &sun, G);
+
+        let elements = OrbitalElements::of(&earth, &sun, G).unwrap();
+        assert!((elements.a - a).abs() / a < 1e-9);
+        assert!((elements.e - e).abs() < 1e-9);
+        // kepler_orbit puts the periapsis on the x axis
+        assert!(elements.periapsis_angle.abs() < 1e-9);
+
+        // The ellipse goes through the body, at its true anomaly
+        let points = elements.ellipse_points(sun.pos(), 360);
+        let (x, y) = earth.pos();
+        let closest = points
+            .iter()
+            .map(|(px, py)| ((px - x).powf(2.) + (py - y).powf(2.)).sqrt())
+            .fold(f64::INFINITY, f64::min);
+        assert!(closest < 0.01 * AU);
+        assert_eq!(points.first(), points.last());
+
+        // Under a stronger gravity, the same orbit is faster
+        let g = 2. * G;
+        kepler_orbit(OrbitParameters { a, e, theta: 1. }, &mut earth, &sun, g);
+        let elements = OrbitalElements::of(&earth, &sun, g).unwrap();
+        assert!((elements.a - a).abs() / a < 1e-9);
+        assert!(OrbitalElements::of(&earth, &sun, G).is_none());
+
+        // Too fast to be bound
+        earth.velocity = (1e6, 0.);
+        assert!(OrbitalElements::of(&earth, &sun, G).is_none());
+    }
+
+    #[test]
+    fn test_tisserand() {
+        let sun = Body::new(
+            SUN_MASS,
+            (0., 0.),
+            1.,
+            1.,
+            Color::YELLOW,
+            (0., 0.),
+            (0., 0.),
+        );
+        let body = |a: f64, e: f64, theta: f64| {
+            let mut body = Body::new(1., (0., 0.), 1., 1., Color::GRAY, (0., 0.), (0., 0.));
+            kepler_orbit(OrbitParameters { a, e, theta }, &mut body, &sun, G);
+            body
+        };
+
+        let planet = body(5. * AU, 0., 0.);
+
+        // On the orbit of the planet
+        let t = tisserand(&body(5. * AU, 0., 2.), &planet, &sun, G).unwrap();
+        assert!((t - 3.).abs() < 1e-9);
+
+        let (a, e): (f64, f64) = (3. * AU, 0.6);
+        let expected = 5. / 3. + 2. * (3. / 5. * (1. - e * e)).sqrt();
+        let t = tisserand(&body(a, e, 1.), &planet, &sun, G).unwrap();
+        assert!((t - expected).abs() < 1e-9);
+
+        let mut escaping = body(a, e, 1.);
+        escaping.velocity = (1e6, 0.);
+        assert_eq!(tisserand(&escaping, &planet, &sun, G), None);
+    }
+}
@@ -0,0 +1,223 @@
+use crate::body::{Body, BodyId, OrbitalBodies};
+use crate::physics::{PhysicsConfig, dominant_attractor, specific_orbital_energy};
+use std::collections::HashMap;
+use std::f64::consts::PI;
+
+/// Orbital period of a body, as measured by a [PeriodAnalyzer]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PeriodEstimate {
+    /// Simulated seconds the body took to come back to its starting angle
+    Measured(f64),
+    /// The body has not completed an orbit yet, it covered this fraction of
+    /// one
+    Unknown(f64),
+    /// The body orbits nothing, or is not bound to its center
+    NotOrbiting,
+}
+
+/// Angle swept by a body around its center since it is tracked
+struct AngleTrack {
+    /// Body it orbits, see [dominant_attractor]
+    center: BodyId,
+    start_time: f64,
+    previous_angle: f64,
+    previous_time: f64,
+    /// Unwrapped angle covered since the start, in radians
+    swept: f64,
+    period: Option<f64>,
+}
+
+/// Measures the orbital period of the tier 0 bodies empirically, as the time
+/// they take to come back to their starting angle around the body they orbit,
+/// their center. Unlike the period given by the orbital elements, this
+/// accounts for the perturbations of the other bodies.
+pub struct PeriodAnalyzer {
+    tracks: HashMap<BodyId, AngleTrack>,
+}
+
+/// Angle of the body around the center, in radians
+fn angle_around(body: &Body, center: &Body) -> f64 {
+    let (bx, by) = body.pos();
+    let (cx, cy) = center.pos();
+    (by - cy).atan2(bx - cx)
+}
+
+impl PeriodAnalyzer {
+    pub fn new() -> Self {
+        Self {
+            tracks: HashMap::new(),
+        }
+    }
+
+    /// Follow the bodies after a step. The center of a body is the one it
+    /// orbits under `config` when it is first seen, see [dominant_attractor],
+    /// bodies appearing later are tracked from then on.
+    pub fn update(&mut self, bodies: &OrbitalBodies, time: f64, config: &PhysicsConfig) {
+        self.tracks.retain(|id, track| {
+            bodies.tier0.contains_key(id) && bodies.tier0.contains_key(&track.center)
+        });
+
+        for body in bodies.tier0.values() {
+            let center = match self.tracks.get(&body.id()) {
+                Some(track) => track.center,
+                None => match dominant_attractor(bodies, body.id(), config) {
+                    Some(center) => center,
+                    None => continue,
+                },
+            };
+            let angle = angle_around(body, &bodies.tier0[&center]);
+
+            let track = self.tracks.entry(body.id()).or_insert(AngleTrack {
+                center,
+                start_time: time,
+                previous_angle: angle,
+                previous_time: time,
+                swept: 0.,
+                period: None,
+            });
+
+            // Smallest signed change of angle, the step is much shorter than
+            // half an orbit
+            let delta = (angle - track.previous_angle + PI).rem_euclid(2. * PI) - PI;
+            let swept = track.swept + delta;
+
+            // Interpolate the moment the full turn was reached within the step
+            if track.period.is_none() && swept.abs() >= 2. * PI {
+                let missing = 2. * PI - track.swept.abs();
+                let fraction = missing / delta.abs();
+                let crossing = track.previous_time + fraction * (time - track.previous_time);
+                track.period = Some(crossing - track.start_time);
+            }
+
+            track.swept = swept;
+            track.previous_angle = angle;
+            track.previous_time = time;
+        }
+    }
+
+    /// The period estimate of each tier 0 body, sorted by id. Whether a body
+    /// orbits its center is taken under the gravity constant `g`.
+    pub fn report(&self, bodies: &OrbitalBodies, g: f64) -> Vec<(BodyId, PeriodEstimate)> {
+        let mut report = bodies
+            .tier0
+            .values()
+            .map(|body| {
+                let track = self.tracks.get(&body.id());
+                let center = track.and_then(|track| bodies.tier0.get(&track.center));
+
+                let estimate = match (center, track) {
+                    (Some(center), Some(track))
+                        if specific_orbital_energy(body, center, g) < 0. =>
+                    {
+                        match track.period {
+                            Some(period) => PeriodEstimate::Measured(period),
+                            None => PeriodEstimate::Unknown(track.swept.abs() / (2. * PI)),
+                        }
+                    }
+                    _ => PeriodEstimate::NotOrbiting,
+                };
+
+                (body.id(), estimate)
+            })
+            .collect::<Vec<_>>();
+
+        report.sort_by_key(|(id, _)| *id);
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::body::{Body, OrbitalBodies, bodies_to_map};
+    use crate::constants::{
+        EARTH_MASS, EARTH_SUN_VELOCITY, SUN_EARTH_DISTANCE, SUN_MASS, SUN_RADIUS,
+    };
+    use crate::physics::leapfrog::LeapfrogKDK;
+    use crate::physics::periods::{PeriodAnalyzer, PeriodEstimate};
+    use crate::physics::{G, Gravity, Kinematics, PhysicsConfig, update_acceleration};
+    use raylib::color::Color;
+
+    #[test]
+    fn test_earth_period() {
+        let sun = Body::new(
+            SUN_MASS,
+            (0., 0.),
+            SUN_RADIUS,
+            1.,
+            Color::YELLOW,
+            (0., 0.),
+            (0., 0.),
+        );
+        let circular_velocity = (G * (SUN_MASS + EARTH_MASS) / SUN_EARTH_DISTANCE).sqrt();
+        let earth = Body::new(
+            EARTH_MASS,
+            (0., SUN_EARTH_DISTANCE),
+            1.,
+            1.,
+            Color::BLUE,
+            (circular_velocity, 0.),
+            (0., 0.),
+        );
+        let comet = Body::new(
+            1.,
+            (0., -SUN_EARTH_DISTANCE),
+            1.,
+            1.,
+            Color::WHITE,
+            (3. * EARTH_SUN_VELOCITY, 0.),
+            (0., 0.),
+        );
+        let (sun_id, earth_id, comet_id) = (sun.id(), earth.id(), comet.id());
+
+        let mut bodies = OrbitalBodies::new(
+            bodies_to_map(vec![sun, earth, comet]),
+            bodies_to_map(vec![]),
+        );
+        update_acceleration(&mut bodies, &mut 0., &Gravity::Exact.into());
+
+        let config = PhysicsConfig::from(Gravity::Exact);
+        let mut analyzer = PeriodAnalyzer::new();
+        let dt = 3600.;
+        analyzer.update(&bodies, 0., &config);
+
+        let estimate = |analyzer: &PeriodAnalyzer, bodies: &OrbitalBodies, id| {
+            analyzer
+                .report(bodies, G)
+                .into_iter()
+                .find(|(body, _)| *body == id)
+                .unwrap()
+                .1
+        };
+
+        for step in 1..=24 * 400 {
+            LeapfrogKDK.step(&mut bodies, dt, &config);
+            analyzer.update(&bodies, step as f64 * dt, &config);
+
+            if step == 24 * 100 {
+                match estimate(&analyzer, &bodies, earth_id) {
+                    PeriodEstimate::Unknown(fraction) => assert!((fraction - 0.27).abs() < 0.01),
+                    other => panic!("expected an unknown period, got {other:?}"),
+                }
+            }
+        }
+
+        let expected = 2.
+            * std::f64::consts::PI
+            * (SUN_EARTH_DISTANCE.powf(3.) / (G * (SUN_MASS + EARTH_MASS))).sqrt();
+        match estimate(&analyzer, &bodies, earth_id) {
+            PeriodEstimate::Measured(period) => {
+                assert!((period - expected).abs() / expected < 1e-3)
+            }
+            other => panic!("expected a measured period, got {other:?}"),
+        }
+
+        assert_eq!(
+            estimate(&analyzer, &bodies, sun_id),
+            PeriodEstimate::NotOrbiting
+        );
+        assert_eq!(
+            estimate(&analyzer, &bodies, comet_id),
+            PeriodEstimate::NotOrbiting
+        );
+    }
+}
@@ -0,0 +1,211 @@
+use crate::body::{Body, BodyId, OrbitalBodies, TrailParameter};
+use crate::constants::{AU, SUN_MASS};
+use raylib::color::Color;
+use std::collections::{HashMap, VecDeque};
+
+/// Lightest body counted as a star heating the others, in Kg, about the
+/// limit of hydrogen burning
+pub const STAR_MIN_MASS: f64 = 0.08 * SUN_MASS;
+
+/// Distance to the nearest star beyond which the ices of a comet no longer
+/// sublimate, in meters
+pub const SUBLIMATION_MAX_DISTANCE: f64 = 3. * AU;
+
+/// Fraction of its mass a body can lose in a single step at most, so that a
+/// large step never leaves it with none
+const MAX_STEP_LOSS: f64 = 0.5;
+
+/// Speed the particles of a tail are pushed away from the star at, in m/s
+const TAIL_SPEED: f64 = 100.;
+
+/// Particles in the tail of a comet at most, see [CometTails]
+pub const MAX_TAIL_PARTICLES: usize = 64;
+
+/// Color of the particles of a tail
+const TAIL_COLOR: Color = Color::new(180, 220, 255, 96);
+
+/// Mass a body with a [Body::mass_loss_rate] loses over `dt` seconds,
+/// `distance` meters from the nearest star. The loss grows as the inverse
+/// square of the distance, like the light heating it, and stops beyond
+/// [SUBLIMATION_MAX_DISTANCE].
+fn mass_loss(body: &Body, distance: f64, dt: f64) -> f64 {
+    if distance > SUBLIMATION_MAX_DISTANCE || distance == 0. {
+        return 0.;
+    }
+
+    let loss = body.mass_loss_rate * (AU / distance).powf(2.) * dt.abs();
+    loss.min(body.mass * MAX_STEP_LOSS)
+}
+
+/// Take away the mass the bodies with a [Body::mass_loss_rate] lost to
+/// sublimation over a step of `dt` seconds, also when running backward: the
+/// loss cannot be undone. With `tail`, the mass lost becomes a faint tier 1
+/// particle, pushed away from the star, one per body and step, to hand to
+/// [CometTails::add]. It starts just past the surface of the body, which
+/// would otherwise take it back in the collisions of the next short step.
+///
+/// The mass lost without a tail takes its energy and momentum out of the
+/// system, and the push of the tail adds some: the drifts in the HUD then no
+/// longer only measure the accuracy of the integrator.
+///
+/// Returns the particles of the tails, with the body that lost them.
+pub fn sublimate(bodies: &mut OrbitalBodies, dt: f64, tail: bool) -> Vec<(BodyId, Body)> {
+    let stars = bodies
+        .tier0
+        .values()
+        .filter(|body| body.mass >= STAR_MIN_MASS)
+        .map(|star| (star.id(), star.pos()))
+        .collect::<Vec<_>>();
+    if stars.is_empty() {
+        return vec![];
+    }
+
+    let mut particles = vec![];
+    for body in bodies.iter_mut().filter(|body| body.mass_loss_rate > 0.) {
+        let (x, y) = body.pos();
+        let Some((distance, (sx, sy))) = stars
+            .iter()
+            .filter(|(id, _)| *id != body.id())
+            .map(|&(_, (sx, sy))| (((x - sx).powf(2.) + (y - sy).powf(2.)).sqrt(), (sx, sy)))
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+        else {
+            continue;
+        };
+
+        let loss = mass_loss(body, distance, dt);
+        if loss == 0. {
+            continue;
+        }
+        body.mass -= loss;
+
+        if tail {
+            let (vx, vy) = body.velocity;
+            let (ux, uy) = ((x - sx) / distance, (y - sy) / distance);
+            let offset = body.physical_radius + 1.;
+
+            let mut particle = Body::new(
+                loss,
+                (x + ux * offset, y + uy * offset),
+                1.,
+                1.,
+                TAIL_COLOR,
+                (vx + ux * TAIL_SPEED, vy + uy * TAIL_SPEED),
+                (0., 0.),
+            );
+            particle.trail_parameter = TrailParameter::NoTrail;
+            particle.group_id = body.group_id;
+            particles.push((body.id(), particle));
+        }
+    }
+
+    particles
+}
+
+/// The particles of the tail of each comet, at most [MAX_TAIL_PARTICLES]
+/// per comet so that the asteroids do not grow without bound on long runs
+#[derive(Debug, Default)]
+pub struct CometTails {
+    /// Particles of each comet still in the simulation, oldest first
+    particles: HashMap<BodyId, VecDeque<BodyId>>,
+}
+
+impl CometTails {
+    /// Add a particle of the tail of `comet` as a tier 1 body. With the tail
+    /// full, the oldest particle takes its mass and place instead, so that
+    /// the mass lost is still all there.
+    pub fn add(&mut self, bodies: &mut OrbitalBodies, comet: BodyId, particle: Body) {
+        let tail = self.particles.entry(comet).or_default();
+
+        // Particles merged into other bodies are no longer part of the tail
+        tail.retain(|id| bodies.tier1.contains_key(id));
+
+        if tail.len() >= MAX_TAIL_PARTICLES
+            && let Some(oldest) = tail.pop_front()
+            && let Some(recycled) = bodies.tier1.get_mut(&oldest)
+        {
+            recycled.mass += particle.mass;
+            recycled.set_pos(particle.pos());
+            recycled.velocity = particle.velocity;
+            tail.push_back(oldest);
+            return;
+        }
+
+        tail.push_back(particle.id());
+        bodies.tier1.insert(particle.id(), particle);
+    }
+
+    /// Forget all the tails, such as when the bodies are replaced
+    pub fn clear(&mut self) {
+        self.particles.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::body::{Body, OrbitalBodies, bodies_to_map};
+    use crate::constants::{AU, SUN_MASS};
+    use crate::physics::sublimation::{CometTails, MAX_TAIL_PARTICLES, sublimate};
+    use raylib::color::Color;
+
+    #[test]
+    fn test_mass_loss_by_distance() {
+        let body =
+            |mass: f64, x: f64| Body::new(mass, (x, 0.), 1., 1., Color::WHITE, (0., 0.), (0., 0.));
+        let comet = |x: f64| {
+            let mut comet = body(1e14, x);
+            comet.mass_loss_rate = 1000.;
+            comet
+        };
+        let (near, far, distant) = (comet(AU), comet(-2. * AU), comet(5. * AU));
+        let ids = [near.id(), far.id(), distant.id()];
+
+        let mut bodies = OrbitalBodies::new(
+            bodies_to_map(vec![body(SUN_MASS, 0.), near, far, distant]),
+            bodies_to_map(vec![]),
+        );
+
+        let particles = sublimate(&mut bodies, 3600., true);
+        let lost = ids.map(|id| 1e14 - bodies.get_by_id(id).unwrap().mass);
+
+        // An hour at 1000 Kg/s, a quarter of it twice as far, none out of reach
+        assert!((lost[0] - 3.6e6).abs() < 1e-3);
+        assert!((lost[1] - 3.6e6 / 4.).abs() < 1e-3);
+        assert_eq!(lost[2], 0.);
+
+        // The lost mass goes to the tails
+        assert_eq!(particles.len(), 2);
+        let tail_mass = particles
+            .iter()
+            .map(|(_, particle)| particle.mass)
+            .sum::<f64>();
+        assert!((tail_mass - lost[0] - lost[1]).abs() < 1e-3);
+        assert!(sublimate(&mut bodies, 3600., false).is_empty());
+    }
+
+    #[test]
+    fn test_tail_particles_capped() {
+        let body =
+            |mass: f64, x: f64| Body::new(mass, (x, 0.), 1., 1., Color::WHITE, (0., 0.), (0., 0.));
+        let mut comet = body(1e14, AU);
+        comet.mass_loss_rate = 1000.;
+        let comet_id = comet.id();
+
+        let mut bodies = OrbitalBodies::new(
+            bodies_to_map(vec![body(SUN_MASS, 0.), comet]),
+            bodies_to_map(vec![]),
+        );
+        let mass = bodies.total_mass();
+
+        let mut tails = CometTails::default();
+        for _ in 0..3 * MAX_TAIL_PARTICLES {
+            for (comet, particle) in sublimate(&mut bodies, 3600., true) {
+                assert_eq!(comet, comet_id);
+                tails.add(&mut bodies, comet, particle);
+            }
+        }
+
+        // The oldest particles are reused, and no mass went missing
+        assert_eq!(bodies.tier1.len(), MAX_TAIL_PARTICLES);
+        assert!((bodies.total_mass() - mass).abs() / mass < 1e-12);
+    }
+}
@@ -0,0 +1,137 @@
+use crate::body::OrbitalBodies;
+use crate::physics::{
+    Kinematics, KinematicsDiagnostic, PhysicsConfig, tier1_potential_energy,
+    update_tier1_acceleration,
+};
+use rayon::prelude::*;
+use std::collections::HashMap;
+
+/// Integrate each tier with its own method. Tier 0 is stepped on its own with
+/// an accurate integrator, then the tier 1 test particles are moved with a
+/// cheap symplectic Euler step in the field of the stepped tier 0.
+pub struct Tiered {
+    /// Integrator for the self-gravity of tier 0
+    tier0: Box<dyn Kinematics>,
+    name: &'static str,
+}
+
+impl Tiered {
+    pub fn new(tier0: Box<dyn Kinematics>, name: &'static str) -> Self {
+        Self { tier0, name }
+    }
+}
+
+impl Kinematics for Tiered {
+    fn step(
+        &self,
+        bodies: &mut OrbitalBodies,
+        dt: f64,
+        config: &PhysicsConfig,
+    ) -> KinematicsDiagnostic {
+        // Tier 1 does not pull on tier 0, so tier 0 can be stepped without it
+        let tier1 = std::mem::take(&mut bodies.tier1);
+        let tier0_diagnostic = self.tier0.step(bodies, dt, config);
+        bodies.tier1 = tier1;
+
+        // Drift, then kick with the tier 0 field at the end of the step, so
+        // that both tiers are taken at the same time
+        bodies
+            .tier1
+            .par_iter_mut()
+            .filter(|(_, body)| !body.fixed)
+            .for_each(|(_, body)| {
+                let (rx, ry) = body.pos();
+                let (vx, vy) = body.velocity;
+
+                body.set_pos((rx + vx * dt, ry + vy * dt));
+            });
+
+        update_tier1_acceleration(bodies, config, &mut HashMap::new());
+
+        bodies
+            .tier1
+            .par_iter_mut()
+            .filter(|(_, body)| !body.fixed)
+            .for_each(|(_, body)| {
+                let (ax, ay) = body.accel;
+                let (vx, vy) = body.velocity;
+
+                body.velocity = (vx + ax * dt, vy + ay * dt);
+            });
+
+        KinematicsDiagnostic {
+            kinetic_energy: bodies.total_kinetic_energy(),
+            potential_energy: tier0_diagnostic.potential_energy
+                + tier1_potential_energy(bodies, config),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::body::{Body, OrbitalBodies, bodies_to_map};
+    use crate::constants::{SUN_EARTH_DISTANCE, SUN_MASS};
+    use crate::physics::leapfrog::LeapfrogKDK;
+    use crate::physics::tiered::Tiered;
+    use crate::physics::{G, Gravity, Kinematics};
+    use raylib::color::Color;
+
+    #[test]
+    fn test_tier1_step_in_fixed_field() {
+        let sun = Body::new(
+            SUN_MASS,
+            (0., 0.),
+            1.,
+            1.,
+            Color::YELLOW,
+            (0., 0.),
+            (0., 0.),
+        );
+        let asteroid = Body::new(
+            1.,
+            (SUN_EARTH_DISTANCE, 0.),
+            1.,
+            1.,
+            Color::WHITE,
+            (0., 30_000.),
+            (0., 0.),
+        );
+        let (sun_id, asteroid_id) = (sun.id(), asteroid.id());
+
+        let mut bodies =
+            OrbitalBodies::new(bodies_to_map(vec![sun]), bodies_to_map(vec![asteroid]));
+
+        let dt = 3600.;
+        let tiered = Tiered::new(Box::new(LeapfrogKDK), "Tiered");
+
+        let mut expected_pos = (SUN_EARTH_DISTANCE, 0.);
+        let mut expected_velocity = (0., 30_000.);
+
+        for _ in 0..100 {
+            tiered.step(&mut bodies, dt, &Gravity::Exact.into());
+
+            // The sun is alone in tier 0, nothing moves it
+            assert_eq!(bodies.get_by_id(sun_id).unwrap().pos(), (0., 0.));
+
+            expected_pos = (
+                expected_pos.0 + expected_velocity.0 * dt,
+                expected_pos.1 + expected_velocity.1 * dt,
+            );
+            let r = (expected_pos.0.powf(2.) + expected_pos.1.powf(2.)).sqrt();
+            let factor = -G * SUN_MASS / r.powf(3.);
+            expected_velocity = (
+                expected_velocity.0 + factor * expected_pos.0 * dt,
+                expected_velocity.1 + factor * expected_pos.1 * dt,
+            );
+
+            let asteroid = bodies.get_by_id(asteroid_id).unwrap();
+            let (x, y) = asteroid.pos();
+            assert!((x - expected_pos.0).abs() <= 1e-9 * r);
+            assert!((y - expected_pos.1).abs() <= 1e-9 * r);
+        }
+    }
+}
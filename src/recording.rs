@@ -0,0 +1,124 @@
+/// Most frames a single call to [FrameRecorder::frames_due] saves, past a
+/// jump in time the others are skipped
+const MAX_FRAMES_PER_CALL: usize = 100;
+
+/// Decides when to save a video frame, so that each frame of the recording
+/// covers the same simulated interval however fast the physics runs.
+pub struct FrameRecorder {
+    /// Prefix of the saved frames, followed by the frame number
+    pub prefix: String,
+    /// Simulated seconds between two video frames
+    pub seconds_per_frame: f64,
+    /// Index of the next frame boundary to cross, at that many
+    /// `seconds_per_frame` of simulated time
+    next_boundary: i64,
+    /// Number of frames saved so far
+    frames: usize,
+}
+
+impl FrameRecorder {
+    pub fn new(prefix: String, seconds_per_frame: f64) -> Self {
+        assert!(seconds_per_frame > 0., "A video frame must cover some time");
+
+        Self {
+            prefix,
+            seconds_per_frame,
+            next_boundary: 0,
+            frames: 0,
+        }
+    }
+
+    /// Number of frames saved so far
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    /// Account for the simulation reaching `time`, and return the names of
+    /// the frames whose boundary was crossed since the last call, in order.
+    /// When a render frame covers several video frames, the same picture is
+    /// saved under each of their names, up to [MAX_FRAMES_PER_CALL]. Nothing
+    /// is due while time runs backward.
+    pub fn frames_due(&mut self, time: f64) -> Vec<String> {
+        let mut due = vec![];
+
+        while self.next_boundary as f64 * self.seconds_per_frame <= time {
+            if due.len() == MAX_FRAMES_PER_CALL {
+                self.resync(time);
+                break;
+            }
+
+            due.push(format!("{0}{1:06}.png", self.prefix, self.frames));
+            self.frames += 1;
+            self.next_boundary += 1;
+        }
+
+        due
+    }
+
+    /// Follow a jump of the simulated time to `time`, after a reset, a load
+    /// or a scrub: the next frame is saved at the first boundary after it
+    pub fn resync(&mut self, time: f64) {
+        self.next_boundary = (time / self.seconds_per_frame).floor() as i64 + 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::recording::{FrameRecorder, MAX_FRAMES_PER_CALL};
+
+    #[test]
+    fn test_frame_count_independent_of_steps() {
+        let seconds_per_frame = 86_400.;
+        let duration = 100. * seconds_per_frame;
+
+        for dt in [3_600., 43_200., 86_400., 200_000.] {
+            let mut recorder = FrameRecorder::new("frame_".to_string(), seconds_per_frame);
+            let mut time = 0.;
+            let mut names = recorder.frames_due(time);
+
+            while time < duration {
+                time = (time + dt).min(duration);
+                names.extend(recorder.frames_due(time));
+            }
+
+            assert_eq!(names.len(), 101);
+            assert_eq!(recorder.frames(), 101);
+            assert_eq!(names[100], "frame_000100.png");
+        }
+    }
+
+    #[test]
+    fn test_forward_jump() {
+        let seconds_per_frame = 10.;
+        let mut recorder = FrameRecorder::new("frame_".to_string(), seconds_per_frame);
+        assert_eq!(recorder.frames_due(0.).len(), 1);
+
+        // A scene loaded far ahead does not flood the disk, the frames after
+        // it follow the new time
+        let time = 1e6 * seconds_per_frame;
+        assert_eq!(recorder.frames_due(time).len(), MAX_FRAMES_PER_CALL);
+        assert_eq!(recorder.frames_due(time + seconds_per_frame).len(), 1);
+
+        // Nor when resynced first
+        let time = 2e6 * seconds_per_frame + 5.;
+        recorder.resync(time);
+        assert!(recorder.frames_due(time).is_empty());
+        assert_eq!(recorder.frames_due(time + seconds_per_frame).len(), 1);
+        assert_eq!(recorder.frames(), MAX_FRAMES_PER_CALL + 3);
+    }
+
+    #[test]
+    fn test_backward_jump() {
+        let seconds_per_frame = 10.;
+        let mut recorder = FrameRecorder::new("frame_".to_string(), seconds_per_frame);
+        assert_eq!(recorder.frames_due(95.).len(), 10);
+
+        // Back in time, the recording goes on from there with the next
+        // frame numbers
+        recorder.resync(32.);
+        assert!(recorder.frames_due(35.).is_empty());
+        assert_eq!(recorder.frames_due(40.), vec!["frame_000010.png"]);
+        assert_eq!(recorder.frames_due(55.).len(), 1);
+        assert_eq!(recorder.frames(), 12);
+    }
+}
@@ -0,0 +1,1208 @@
+use crate::body::{ASTEROID_COLOR, Body, BodyId, OrbitalBodies, TrailParameter, bodies_to_map};
+use crate::constants::{AU, SECONDS_PER_DAY};
+use crate::physics::DragLaw;
+use crate::physics::constraint::Constraint;
+use raylib::color::Color;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+
+/// Draw radius, in pixels, of tier 0 bodies loaded from a table
+const SCENE_DRAW_RADIUS: f64 = 6.;
+
+/// Colors handed out, in order, to the tier 0 bodies of a table
+const SCENE_PALETTE: [Color; 6] = [
+    Color::YELLOW,
+    Color::BLUE,
+    Color::RED,
+    Color::GRAY,
+    Color::ORANGE,
+    Color::GREEN,
+];
+
+/// Colors a `Color` column can name, the ones of raylib
+const NAMED_COLORS: [(&str, Color); 25] = [
+    ("lightgray", Color::LIGHTGRAY),
+    ("gray", Color::GRAY),
+    ("darkgray", Color::DARKGRAY),
+    ("yellow", Color::YELLOW),
+    ("gold", Color::GOLD),
+    ("orange", Color::ORANGE),
+    ("pink", Color::PINK),
+    ("red", Color::RED),
+    ("maroon", Color::MAROON),
+    ("green", Color::GREEN),
+    ("lime", Color::LIME),
+    ("darkgreen", Color::DARKGREEN),
+    ("skyblue", Color::SKYBLUE),
+    ("blue", Color::BLUE),
+    ("darkblue", Color::DARKBLUE),
+    ("purple", Color::PURPLE),
+    ("violet", Color::VIOLET),
+    ("darkpurple", Color::DARKPURPLE),
+    ("beige", Color::BEIGE),
+    ("brown", Color::BROWN),
+    ("darkbrown", Color::DARKBROWN),
+    ("white", Color::WHITE),
+    ("black", Color::BLACK),
+    ("magenta", Color::MAGENTA),
+    ("raywhite", Color::RAYWHITE),
+];
+
+/// Column holding the epoch in a Horizons vector table
+pub const HORIZONS_DEFAULT_EPOCH_COLUMN: &str = "JDTDB";
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(std::io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl Display for SceneError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SceneError::Io(err) => write!(f, "could not access scene: {err}"),
+            SceneError::Parse { line, message } => write!(f, "line {line}: {message}"),
+        }
+    }
+}
+
+impl From<std::io::Error> for SceneError {
+    fn from(err: std::io::Error) -> Self {
+        SceneError::Io(err)
+    }
+}
+
+/// Options controlling how a Horizons export is read
+pub struct HorizonsOptions<'a> {
+    /// Header of the column holding the epoch of each row
+    pub epoch_column: &'a str,
+    /// Only keep the rows at this epoch. When `None`, all rows must share
+    /// the same epoch, as mixing them would not give a consistent state.
+    pub epoch: Option<&'a str>,
+}
+
+impl Default for HorizonsOptions<'_> {
+    fn default() -> Self {
+        Self {
+            epoch_column: HORIZONS_DEFAULT_EPOCH_COLUMN,
+            epoch: None,
+        }
+    }
+}
+
+/// Split a header like `VX (km/s)` into its name and unit
+fn split_header(header: &str) -> (String, Option<String>) {
+    let header = header.trim();
+    match (header.find('('), header.rfind(')')) {
+        (Some(open), Some(close)) if open < close => (
+            header[..open].trim().to_lowercase(),
+            Some(header[open + 1..close].trim().to_lowercase()),
+        ),
+        _ => (header.to_lowercase(), None),
+    }
+}
+
+/// Factor converting a distance unit to meters
+fn distance_factor(unit: &str) -> Option<f64> {
+    match unit {
+        "m" => Some(1.),
+        "km" => Some(1000.),
+        "au" => Some(AU),
+        _ => None,
+    }
+}
+
+/// Factor converting a velocity unit to meters per second
+fn velocity_factor(unit: &str) -> Option<f64> {
+    match unit {
+        "m/s" => Some(1.),
+        "km/s" => Some(1000.),
+        "au/d" | "au/day" => Some(AU / SECONDS_PER_DAY),
+        _ => None,
+    }
+}
+
+/// Factor converting a mass unit to Kg
+fn mass_factor(unit: &str) -> Option<f64> {
+    match unit {
+        "kg" => Some(1.),
+        _ => None,
+    }
+}
+
+/// Factor converting a mass loss rate unit to Kg/s
+fn mass_rate_factor(unit: &str) -> Option<f64> {
+    match unit {
+        "kg/s" => Some(1.),
+        _ => None,
+    }
+}
+
+/// Factor converting a draw radius unit to pixels
+fn pixel_factor(unit: &str) -> Option<f64> {
+    match unit {
+        "px" => Some(1.),
+        _ => None,
+    }
+}
+
+/// A numeric column of a table, with the factor converting it to SI units
+struct Column {
+    index: usize,
+    factor: f64,
+}
+
+/// A data row of a table
+struct Row<'a> {
+    line: usize,
+    fields: Vec<Cow<'a, str>>,
+}
+
+impl<'a> Row<'a> {
+    fn error(&self, message: String) -> SceneError {
+        SceneError::Parse {
+            line: self.line,
+            message,
+        }
+    }
+
+    fn field(&self, index: usize) -> Result<&str, SceneError> {
+        self.fields
+            .get(index)
+            .map(|field| field.as_ref())
+            .ok_or_else(|| self.error(format!("expected at least {0} fields", index + 1)))
+    }
+
+    /// The field of an optional column, `None` when the column is missing or
+    /// the field is empty
+    fn optional_field(&self, index: Option<usize>) -> Result<Option<&str>, SceneError> {
+        let field = index.map(|index| self.field(index)).transpose()?;
+        Ok(field.filter(|raw| !raw.is_empty()))
+    }
+
+    /// The color of an optional column, see [parse_color]
+    fn optional_color(&self, index: Option<usize>) -> Result<Option<Color>, SceneError> {
+        self.optional_field(index)?
+            .map(|raw| parse_color(raw).map_err(|message| self.error(message)))
+            .transpose()
+    }
+
+    fn value(&self, column: &Column) -> Result<f64, SceneError> {
+        let raw = self.field(column.index)?;
+        raw.parse::<f64>()
+            .map(|v| v * column.factor)
+            .map_err(|_| self.error(format!("`{raw}` is not a number")))
+    }
+
+    /// The value of an optional numeric column, `None` when the column is
+    /// missing or the field is empty
+    fn optional_value(&self, column: Option<&Column>) -> Result<Option<f64>, SceneError> {
+        match column {
+            Some(column) if self.optional_field(Some(column.index))?.is_some() => {
+                self.value(column).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Split a row on its commas, trimming the fields. A field in double quotes
+/// keeps its commas and spaces, with `""` for a quote, see [format_name].
+fn split_fields(row: &str) -> Vec<Cow<'_, str>> {
+    let mut fields = vec![];
+    let mut rest = row;
+
+    loop {
+        let field = rest.trim_start();
+        let Some(quoted) = field.strip_prefix('"') else {
+            match field.split_once(',') {
+                Some((field, next)) => {
+                    fields.push(Cow::Borrowed(field.trim()));
+                    rest = next;
+                    continue;
+                }
+                None => {
+                    fields.push(Cow::Borrowed(field.trim()));
+                    return fields;
+                }
+            }
+        };
+
+        let mut value = String::new();
+        let mut end = quoted.len();
+        let mut chars = quoted.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            match (c, chars.peek()) {
+                ('"', Some((_, '"'))) => {
+                    value.push('"');
+                    chars.next();
+                }
+                ('"', _) => {
+                    end = i + 1;
+                    break;
+                }
+                _ => value.push(c),
+            }
+        }
+        fields.push(Cow::Owned(value));
+
+        // Anything between the closing quote and the next comma is dropped
+        match quoted[end..].split_once(',') {
+            Some((_, next)) => rest = next,
+            None => return fields,
+        }
+    }
+}
+
+/// A comma separated table, with a header row naming each column and its
+/// unit in parentheses, as Horizons does (`X (km)`, `VX (km/s)`). Lines
+/// starting with `#`, as well as the `$$SOE` and `$$EOE` markers, are ignored,
+/// and so are the `@` directives of saved scenes.
+struct Table<'a> {
+    header_line: usize,
+    headers: Vec<(String, Option<String>)>,
+    rows: Vec<Row<'a>>,
+}
+
+impl<'a> Table<'a> {
+    fn parse(content: &'a str) -> Result<Self, SceneError> {
+        let mut lines = content
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|(_, line)| {
+                !line.is_empty()
+                    && !line.starts_with('#')
+                    && !line.starts_with("$$")
+                    && !line.starts_with('@')
+            });
+
+        let (header_line, header) = lines.next().ok_or(SceneError::Parse {
+            line: 0,
+            message: "missing header".to_string(),
+        })?;
+
+        Ok(Self {
+            header_line,
+            headers: header.split(',').map(split_header).collect(),
+            rows: lines
+                .map(|(line, row)| Row {
+                    line,
+                    fields: split_fields(row),
+                })
+                .collect(),
+        })
+    }
+
+    fn error(&self, message: String) -> SceneError {
+        SceneError::Parse {
+            line: self.header_line,
+            message,
+        }
+    }
+
+    /// Locate a column by its name, whatever its unit
+    fn index(&self, name: &str) -> Result<usize, SceneError> {
+        self.headers
+            .iter()
+            .position(|(header, _)| header == name)
+            .ok_or_else(|| self.error(format!("missing column `{name}`")))
+    }
+
+    /// Locate a numeric column and validate its unit
+    fn column(
+        &self,
+        name: &str,
+        unit_factor: fn(&str) -> Option<f64>,
+    ) -> Result<Column, SceneError> {
+        let index = self.index(name)?;
+
+        let unit = self.headers[index]
+            .1
+            .as_deref()
+            .ok_or_else(|| self.error(format!("column `{name}` has no unit")))?;
+
+        let factor = unit_factor(unit)
+            .ok_or_else(|| self.error(format!("unsupported unit `{unit}` for column `{name}`")))?;
+
+        Ok(Column { index, factor })
+    }
+
+    /// Like [Table::column], for a column that may be left out
+    fn optional_column(
+        &self,
+        name: &str,
+        unit_factor: fn(&str) -> Option<f64>,
+    ) -> Result<Option<Column>, SceneError> {
+        match self.index(name) {
+            Ok(_) => self.column(name, unit_factor).map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+}
+
+/// Parse a color, either one of [NAMED_COLORS] or its `R G B A` components
+/// separated by spaces, the alpha being optional
+fn parse_color(raw: &str) -> Result<Color, String> {
+    let components = raw
+        .split_whitespace()
+        .map(str::parse::<u8>)
+        .collect::<Result<Vec<_>, _>>();
+
+    match components.as_deref() {
+        Ok([r, g, b]) => return Ok(Color::new(*r, *g, *b, 255)),
+        Ok([r, g, b, a]) => return Ok(Color::new(*r, *g, *b, *a)),
+        _ => {}
+    }
+
+    let name = raw.to_lowercase();
+    NAMED_COLORS
+        .iter()
+        .find(|(known, _)| *known == name)
+        .map(|(_, color)| *color)
+        .ok_or_else(|| {
+            let names = NAMED_COLORS.map(|(known, _)| known).join(", ");
+            format!("unknown color `{raw}`, expected `R G B A` or one of {names}")
+        })
+}
+
+/// Write a color the way [parse_color] reads it, by name when it has one
+fn format_color(color: Color) -> String {
+    match NAMED_COLORS.iter().find(|(_, named)| *named == color) {
+        Some((name, _)) => name.to_string(),
+        None => format!("{0} {1} {2} {3}", color.r, color.g, color.b, color.a),
+    }
+}
+
+/// Parse the drag of a body, its law, `linear` or `quadratic`, and its
+/// coefficient separated by a space, see [Body::drag_coefficient]
+fn parse_drag(raw: &str) -> Result<(DragLaw, f64), String> {
+    let error = || format!("`{raw}` is not a drag, expected `linear <k>` or `quadratic <k>`");
+
+    let (law, coefficient) = raw.split_once(' ').ok_or_else(error)?;
+    let law = match law {
+        "linear" => DragLaw::Linear,
+        "quadratic" => DragLaw::Quadratic,
+        _ => return Err(error()),
+    };
+    let coefficient = coefficient.trim().parse::<f64>().map_err(|_| error())?;
+
+    Ok((law, coefficient))
+}
+
+/// Write the drag of a body the way [parse_drag] reads it, empty without drag
+fn format_drag(body: &Body) -> String {
+    if body.drag_coefficient == 0. {
+        return String::new();
+    }
+
+    let law = match body.drag_law {
+        DragLaw::Linear => "linear",
+        DragLaw::Quadratic => "quadratic",
+    };
+    format!("{law} {0}", body.drag_coefficient)
+}
+
+/// Write the label of a body as a field read back by [split_fields], empty
+/// without a label. Quoted when it would otherwise be split, trimmed, or
+/// taken for a comment or a directive.
+fn format_name(body: &Body) -> String {
+    let Some(label) = &body.label else {
+        return String::new();
+    };
+
+    if label.contains([',', '"']) || label.starts_with(['#', '$', '@']) || label.trim() != label {
+        format!("\"{0}\"", label.replace('"', "\"\""))
+    } else {
+        label.clone()
+    }
+}
+
+/// The columns describing the physical state of a body
+struct BodyColumns {
+    name: usize,
+    /// Optional column overriding the color bodies get by default. Left
+    /// empty, tier 1 bodies take the one of the theme, see
+    /// [Body::theme_color].
+    color: Option<usize>,
+    /// Optional column giving the trails their own color, see
+    /// [Body::trail_color]
+    trail_color: Option<usize>,
+    /// Optional column placing bodies in a gravity group, see
+    /// [Body::group_id], group 0 when left out
+    group: Option<usize>,
+    /// Optional column overriding the draw radius bodies get by default
+    draw_radius: Option<Column>,
+    /// Optional column pinning bodies in place, `true` or `false`, see
+    /// [Body::fixed]
+    fixed: Option<usize>,
+    /// Optional column slowing bodies down, see [parse_drag]
+    drag: Option<usize>,
+    /// Optional column making comets sublimate, see [Body::mass_loss_rate],
+    /// none when left out
+    mass_loss: Option<Column>,
+    mass: Column,
+    radius: Column,
+    x: Column,
+    y: Column,
+    vx: Column,
+    vy: Column,
+}
+
+impl BodyColumns {
+    fn find(table: &Table) -> Result<Self, SceneError> {
+        Ok(Self {
+            name: table.index("name")?,
+            color: table.index("color").ok(),
+            trail_color: table.index("trail color").ok(),
+            group: table.index("group").ok(),
+            draw_radius: table.optional_column("draw radius", pixel_factor)?,
+            fixed: table.index("fixed").ok(),
+            drag: table.index("drag").ok(),
+            mass_loss: table.optional_column("mass loss", mass_rate_factor)?,
+            mass: table.column("mass", mass_factor)?,
+            radius: table.column("radius", distance_factor)?,
+            x: table.column("x", distance_factor)?,
+            y: table.column("y", distance_factor)?,
+            vx: table.column("vx", velocity_factor)?,
+            vy: table.column("vy", velocity_factor)?,
+        })
+    }
+
+    fn body(&self, row: &Row, draw_radius: f64, color: Color) -> Result<Body, SceneError> {
+        let name = row.field(self.name)?;
+
+        let color = row.optional_color(self.color)?.unwrap_or(color);
+
+        let group_id = match row.optional_field(self.group)? {
+            Some(raw) => raw
+                .parse::<usize>()
+                .map_err(|_| row.error(format!("`{raw}` is not a group")))?,
+            None => 0,
+        };
+
+        let fixed = match row.optional_field(self.fixed)? {
+            Some(raw) => raw
+                .parse::<bool>()
+                .map_err(|_| row.error(format!("`{raw}` is not `true` or `false`")))?,
+            None => false,
+        };
+
+        let (drag_law, drag_coefficient) = match row.optional_field(self.drag)? {
+            Some(raw) => parse_drag(raw).map_err(|message| row.error(message))?,
+            None => (DragLaw::default(), 0.),
+        };
+
+        let draw_radius = row
+            .optional_value(self.draw_radius.as_ref())?
+            .unwrap_or(draw_radius);
+
+        let mut body = Body::new(
+            row.value(&self.mass)?,
+            (row.value(&self.x)?, row.value(&self.y)?),
+            row.value(&self.radius)?,
+            draw_radius,
+            color,
+            (row.value(&self.vx)?, row.value(&self.vy)?),
+            (0., 0.),
+        );
+
+        body.group_id = group_id;
+        body.trail_color = row.optional_color(self.trail_color)?;
+        body.fixed = fixed;
+        body.drag_law = drag_law;
+        body.drag_coefficient = drag_coefficient;
+        body.mass_loss_rate = row.optional_value(self.mass_loss.as_ref())?.unwrap_or(0.);
+
+        // No name, no label: the body is known by its id
+        Ok(match name {
+            "" => body,
+            name => body.with_label(name),
+        })
+    }
+
+    /// Body of a row of a table with a `Tier` column, in the tier it goes in.
+    /// Tier 1 bodies are drawn like asteroids.
+    fn tiered_body(
+        &self,
+        row: &Row,
+        tier_index: usize,
+        tier0_count: usize,
+    ) -> Result<(usize, Body), SceneError> {
+        match row.field(tier_index)? {
+            "0" => {
+                let color = SCENE_PALETTE[tier0_count % SCENE_PALETTE.len()];
+                Ok((0, self.body(row, SCENE_DRAW_RADIUS, color)?))
+            }
+            "1" => {
+                let mut body = self.body(row, 1., ASTEROID_COLOR)?;
+                body.trail_parameter = TrailParameter::NoTrail;
+                body.theme_color = row.optional_field(self.color)?.is_none();
+                Ok((1, body))
+            }
+            tier => Err(row.error(format!("unknown tier `{tier}`"))),
+        }
+    }
+}
+
+/// Parse a Horizons-style vector table into tier 0 bodies.
+///
+/// See [Table] for the format. The expected columns are `Name`, `Mass (kg)`,
+/// `Radius`, `X`, `Y`, `VX`, `VY` and the epoch column. Optional `Color` and
+/// `Trail color` columns set the colors of the bodies, see [parse_color], an
+/// optional `Group` column their gravity group, see [Body::group_id], and
+/// optional `Draw radius (px)`, `Fixed`, `Drag` and `Mass loss (kg/s)` columns
+/// how they are drawn and move, see [Body::fixed], [parse_drag] and
+/// [Body::mass_loss_rate].
+pub fn parse_horizons(content: &str, options: &HorizonsOptions) -> Result<Vec<Body>, SceneError> {
+    let table = Table::parse(content)?;
+
+    let epoch_column = options.epoch_column.to_lowercase();
+    let epoch_index = table
+        .headers
+        .iter()
+        .position(|(name, unit)| {
+            let full = match unit {
+                Some(unit) => format!("{name} ({unit})"),
+                None => name.clone(),
+            };
+            full == epoch_column || *name == epoch_column
+        })
+        .ok_or_else(|| table.error(format!("missing epoch column `{0}`", options.epoch_column)))?;
+
+    let columns = BodyColumns::find(&table)?;
+
+    let mut bodies = vec![];
+    let mut seen_epoch: Option<&str> = None;
+
+    for row in &table.rows {
+        let epoch = row.field(epoch_index)?;
+        match (options.epoch, seen_epoch) {
+            (Some(wanted), _) if wanted != epoch => continue,
+            (None, Some(seen)) if seen != epoch => {
+                return Err(row.error(format!("mixed epochs `{seen}` and `{epoch}`")));
+            }
+            _ => seen_epoch = Some(epoch),
+        }
+
+        let color = SCENE_PALETTE[bodies.len() % SCENE_PALETTE.len()];
+        bodies.push(columns.body(row, SCENE_DRAW_RADIUS, color)?);
+    }
+
+    if bodies.is_empty() {
+        return Err(table.error(match options.epoch {
+            Some(epoch) => format!("no bodies at epoch `{epoch}`"),
+            None => "no bodies".to_string(),
+        }));
+    }
+
+    Ok(bodies)
+}
+
+/// Parse a table of bodies to add to a running simulation.
+///
+/// The format is the one of [parse_horizons], without the epoch column but
+/// with a `Tier` column, `0` or `1`, placing each body. Tier 1 bodies are
+/// drawn like asteroids. A body with an empty name has no label.
+pub fn parse_bodies(content: &str) -> Result<OrbitalBodies, SceneError> {
+    let table = Table::parse(content)?;
+    let tier_index = table.index("tier")?;
+    let columns = BodyColumns::find(&table)?;
+
+    let mut tier0 = vec![];
+    let mut tier1 = vec![];
+
+    for row in &table.rows {
+        match columns.tiered_body(row, tier_index, tier0.len())? {
+            (0, body) => tier0.push(body),
+            (_, body) => tier1.push(body),
+        }
+    }
+
+    Ok(OrbitalBodies::new(
+        bodies_to_map(tier0),
+        bodies_to_map(tier1),
+    ))
+}
+
+/// Write the bodies as a table read by [parse_bodies], to keep a system
+/// built by hand. Unlike [format_scene], there are no ids, time or camera:
+/// the table is meant to be edited, and loaded as a scene of its own or
+/// added to another one. The springs between bodies, tying them by id, are
+/// not written.
+pub fn format_bodies(bodies: &OrbitalBodies) -> String {
+    format_table(bodies, false)
+}
+
+/// Write the bodies as a table with a `Tier` column, read back by
+/// [BodyColumns], tier 0 first and by id. With `with_ids`, each row starts
+/// with the id of its body, as [parse_scene] expects.
+fn format_table(bodies: &OrbitalBodies, with_ids: bool) -> String {
+    let mut content = match with_ids {
+        true => String::from("Id, "),
+        false => String::new(),
+    };
+    content += "Name, Tier, Group, Color, Trail color, Draw radius (px), Fixed, Drag, \
+        Mass loss (kg/s), Mass (kg), Radius (m), X (m), Y (m), VX (m/s), VY (m/s)\n";
+
+    let tiers = [(0, &bodies.tier0), (1, &bodies.tier1)];
+    for (tier, map) in tiers {
+        let mut ids = map.keys().copied().collect::<Vec<_>>();
+        ids.sort();
+
+        for id in ids {
+            let body = &map[&id];
+            let (x, y) = body.pos();
+            let (vx, vy) = body.velocity;
+            if with_ids {
+                content += &format!("{id}, ");
+            }
+            content += &format!(
+                "{0}, {tier}, {1}, {2}, {3}, {4}, {5}, {6}, {7}, {8}, {9}, {x}, {y}, {vx}, {vy}\n",
+                format_name(body),
+                body.group_id,
+                match body.theme_color {
+                    true => String::new(),
+                    false => format_color(body.color),
+                },
+                body.trail_color.map(format_color).unwrap_or_default(),
+                body.draw_radius,
+                body.fixed,
+                format_drag(body),
+                body.mass_loss_rate,
+                body.mass,
+                body.physical_radius
+            );
+        }
+    }
+
+    content
+}
+
+/// What the camera looks at in a saved scene
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SavedTarget {
+    /// The body with this id
+    Body(BodyId),
+    /// A fixed point of the universe
+    Point((f64, f64)),
+}
+
+/// Camera of a saved scene
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SavedCamera {
+    pub target: SavedTarget,
+    pub scale: f64,
+}
+
+/// A snapshot of the simulation, restored as it was saved
+pub struct SavedScene {
+    pub bodies: OrbitalBodies,
+    /// Simulated time, in seconds
+    pub time: f64,
+    pub camera: SavedCamera,
+}
+
+/// Write the scene in the format read by [parse_scene]. The springs tying
+/// bodies that are gone, merged away or moved out of tier 0, are left out.
+pub fn format_scene(bodies: &OrbitalBodies, time: f64, camera: &SavedCamera) -> String {
+    let mut content = String::from("# Saved scene\n");
+
+    content += &format!("@time, {time}\n");
+    content += &match camera.target {
+        SavedTarget::Body(id) => format!("@camera, body, {id}, {0}\n", camera.scale),
+        SavedTarget::Point((x, y)) => format!("@camera, point, {x}, {y}, {0}\n", camera.scale),
+    };
+
+    for constraint in &bodies.constraints {
+        let (first, second) = constraint.bodies;
+        if bodies.tier0.contains_key(&first) && bodies.tier0.contains_key(&second) {
+            content += &format!(
+                "@spring, {first}, {second}, {0}, {1}\n",
+                constraint.stiffness, constraint.rest_length
+            );
+        }
+    }
+
+    content += &format_table(bodies, true);
+
+    content
+}
+
+/// Parse a `@` directive of a saved scene into its numeric values
+fn parse_directive(line: usize, values: &[&str]) -> Result<Vec<f64>, SceneError> {
+    values
+        .iter()
+        .map(|raw| {
+            raw.parse::<f64>().map_err(|_| SceneError::Parse {
+                line,
+                message: format!("`{raw}` is not a number"),
+            })
+        })
+        .collect()
+}
+
+/// Parse a scene written by [format_scene].
+///
+/// The bodies keep the ids they were saved with, so that the camera, and
+/// anything else tracking a body by its id, still finds it after loading.
+/// They replace all the existing bodies. Each `@spring, <id>, <id>,
+/// <stiffness (N/m)>, <rest length (m)>` directive ties two tier 0 bodies,
+/// see [Constraint].
+pub fn parse_scene(content: &str) -> Result<SavedScene, SceneError> {
+    let mut time = None;
+    let mut camera = None;
+    let mut constraints = vec![];
+
+    for (line, directive) in content
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| line.starts_with('@'))
+    {
+        let fields = directive.split(',').map(str::trim).collect::<Vec<_>>();
+        let error = |message: &str| SceneError::Parse {
+            line,
+            message: message.to_string(),
+        };
+
+        match fields.as_slice() {
+            ["@time", value] => time = Some(parse_directive(line, &[*value])?[0]),
+            ["@camera", "body", id, scale] => {
+                let id = id.parse().map_err(|_| error("invalid body id"))?;
+                camera = Some(SavedCamera {
+                    target: SavedTarget::Body(id),
+                    scale: parse_directive(line, &[*scale])?[0],
+                });
+            }
+            ["@camera", "point", values @ ..] if values.len() == 3 => {
+                let values = parse_directive(line, values)?;
+                camera = Some(SavedCamera {
+                    target: SavedTarget::Point((values[0], values[1])),
+                    scale: values[2],
+                });
+            }
+            ["@spring", first, second, values @ ..] if values.len() == 2 => {
+                let first = first.parse().map_err(|_| error("invalid body id"))?;
+                let second = second.parse().map_err(|_| error("invalid body id"))?;
+                let values = parse_directive(line, values)?;
+                constraints.push((
+                    line,
+                    Constraint {
+                        bodies: (first, second),
+                        stiffness: values[0],
+                        rest_length: values[1],
+                    },
+                ));
+            }
+            _ => return Err(error("unknown directive")),
+        }
+    }
+
+    let table = Table::parse(content)?;
+    let id_index = table.index("id")?;
+    let tier_index = table.index("tier")?;
+    let columns = BodyColumns::find(&table)?;
+
+    let mut tier0 = vec![];
+    let mut tier1 = vec![];
+    let mut seen_ids = HashSet::new();
+
+    for row in &table.rows {
+        let raw_id = row.field(id_index)?;
+        let id = raw_id
+            .parse::<BodyId>()
+            .map_err(|_| row.error(format!("`{raw_id}` is not a body id")))?;
+
+        if !seen_ids.insert(id) {
+            return Err(row.error(format!("duplicate body id {id}")));
+        }
+
+        let (tier, mut body) = columns.tiered_body(row, tier_index, tier0.len())?;
+        body.restore_id(id);
+
+        match tier {
+            0 => tier0.push(body),
+            _ => tier1.push(body),
+        }
+    }
+
+    let mut bodies = OrbitalBodies::new(bodies_to_map(tier0), bodies_to_map(tier1));
+    for (line, constraint) in constraints {
+        let (first, second) = constraint.bodies;
+        if let Some(id) = [first, second]
+            .into_iter()
+            .find(|id| !bodies.tier0.contains_key(id))
+        {
+            return Err(SceneError::Parse {
+                line,
+                message: format!("spring on {id}, not a tier 0 body"),
+            });
+        }
+        bodies.constraints.push(constraint);
+    }
+
+    Ok(SavedScene {
+        bodies,
+        time: time.ok_or_else(|| table.error("missing `@time`".to_string()))?,
+        camera: camera.ok_or_else(|| table.error("missing `@camera`".to_string()))?,
+    })
+}
+
+/// Save the scene to disk, see [format_scene]
+pub fn save_scene(
+    path: impl AsRef<Path>,
+    bodies: &OrbitalBodies,
+    time: f64,
+    camera: &SavedCamera,
+) -> Result<(), SceneError> {
+    std::fs::write(path, format_scene(bodies, time, camera))?;
+    Ok(())
+}
+
+/// Export the bodies to disk, see [format_bodies]
+pub fn save_bodies(path: impl AsRef<Path>, bodies: &OrbitalBodies) -> Result<(), SceneError> {
+    std::fs::write(path, format_bodies(bodies))?;
+    Ok(())
+}
+
+/// Load a saved scene from disk, see [parse_scene]
+pub fn load_scene(path: impl AsRef<Path>) -> Result<SavedScene, SceneError> {
+    let content = std::fs::read_to_string(path)?;
+    parse_scene(&content)
+}
+
+/// Load a table of bodies from disk, see [parse_bodies]
+pub fn load_bodies(path: impl AsRef<Path>) -> Result<OrbitalBodies, SceneError> {
+    let content = std::fs::read_to_string(path)?;
+    parse_bodies(&content)
+}
+
+/// Load a Horizons-style export from disk. All bodies are tier 0.
+pub fn load_horizons(
+    path: impl AsRef<Path>,
+    options: &HorizonsOptions,
+) -> Result<OrbitalBodies, SceneError> {
+    let content = std::fs::read_to_string(path)?;
+    let bodies = parse_horizons(&content, options)?;
+
+    Ok(OrbitalBodies::new(bodies_to_map(bodies), HashMap::new()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::body::{Body, OrbitalBodies, bodies_to_map};
+    use crate::constants::{AU, EARTH_MASS, EARTH_RADIUS, SUN_MASS, SUN_RADIUS};
+    use crate::physics::DragLaw;
+    use crate::physics::constraint::Constraint;
+    use crate::scene::{
+        HorizonsOptions, SavedCamera, SavedTarget, format_bodies, format_scene, parse_bodies,
+        parse_horizons, parse_scene,
+    };
+    use raylib::color::Color;
+
+    const SAMPLE: &str = "\
+# Hand written sample, not real data
+Name, JDTDB, Mass (kg), Radius (km), X (km), Y (km), VX (km/s), VY (km/s),
+$$SOE
+Sun, 2451545.0, 1.989e30, 695700, 0, 0, 0, 0,
+Earth, 2451545.0, 5.972e24, 6378, -2.5e7, 1.45e8, -29.8, -5.2,
+Mars, 2451545.0, 6.39e23, 3389.5, 2.08e8, -2.0e6, 1.2, 26.3,
+Earth, 2451546.0, 5.972e24, 6378, -2.8e7, 1.44e8, -29.6, -5.7,
+$$EOE
+";
+
+    #[test]
+    fn test_parse_horizons() {
+        let bodies = parse_horizons(
+            SAMPLE,
+            &HorizonsOptions {
+                epoch: Some("2451545.0"),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(bodies.len(), 3);
+
+        let earth = &bodies[1];
+        assert_eq!(earth.velocity, (-29_800., -5_200.));
+        assert_eq!(earth.pos(), (-2.5e10, 1.45e11));
+        assert_eq!(earth.physical_radius, 6_378_000.);
+
+        let mars = &bodies[2];
+        assert_eq!(mars.velocity, (1_200., 26_300.));
+    }
+
+    #[test]
+    fn test_parse_horizons_mixed_epochs() {
+        assert!(parse_horizons(SAMPLE, &HorizonsOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_parse_horizons_units() {
+        let au_per_day = "\
+Name, JDTDB, Mass (kg), Radius (km), X (au), Y (au), VX (au/d), VY (au/d)
+Earth, 0, 5.972e24, 6378, 1, 0, 0, 0.0172
+";
+        let bodies = parse_horizons(au_per_day, &HorizonsOptions::default()).unwrap();
+        assert_eq!(bodies[0].pos(), (AU, 0.));
+        assert!((bodies[0].velocity.1 - 29_785.).abs() < 10.);
+
+        let bad_unit = au_per_day.replace("X (au)", "X (parsec)");
+        assert!(parse_horizons(&bad_unit, &HorizonsOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_append_parsed_bodies() {
+        let comets = "\
+Name, Tier, Mass (kg), Radius (m), X (au), Y (au), VX (km/s), VY (km/s)
+Big comet, 0, 1e15, 5000, 3, 0, 0, 10
+Small comet, 1, 1e12, 500, 3.1, 0, 0, 11
+Smaller comet, 1, 1e11, 50, 3.2, 0, 0, 12
+";
+        let parsed = parse_bodies(comets).unwrap();
+        assert_eq!(parsed.tier_counts(), (1, 2));
+
+        let mut bodies = OrbitalBodies::new(bodies_to_map(vec![]), bodies_to_map(vec![]));
+        assert_eq!(bodies.append(parsed), 3);
+        assert_eq!(bodies.append(parse_bodies(comets).unwrap()), 3);
+        assert_eq!(bodies.tier_counts(), (2, 4));
+
+        assert!(parse_bodies(&comets.replace(", 1, 1e12", ", 2, 1e12")).is_err());
+    }
+
+    #[test]
+    fn test_parse_colors() {
+        let comets = "\
+Name, Tier, Color, Mass (kg), Radius (m), X (au), Y (au), VX (km/s), VY (km/s)
+Red comet, 0, Red, 1e15, 5000, 3, 0, 0, 10
+Teal comet, 0, 0 128 128 200, 1e15, 5000, 4, 0, 0, 9
+Plain comet, 0, , 1e15, 5000, 5, 0, 0, 8
+";
+        let parsed = parse_bodies(comets).unwrap();
+        let mut colors = parsed
+            .tier0
+            .values()
+            .map(|body| (body.pos().0, body.color))
+            .collect::<Vec<_>>();
+        colors.sort_by(|(x1, _), (x2, _)| x1.total_cmp(x2));
+
+        assert_eq!(colors[0].1, Color::RED);
+        assert_eq!(colors[1].1, Color::new(0, 128, 128, 200));
+        // No color, the one of the palette
+        assert_eq!(colors[2].1, Color::RED);
+
+        let error = parse_bodies(&comets.replace("Red,", "Teal,"))
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(error.contains("unknown color `Teal`"));
+        assert!(error.contains("skyblue"));
+    }
+
+    #[test]
+    fn test_export_bodies_round_trip() {
+        let comets = "\
+Name, Tier, Color, Mass (kg), Radius (m), X (au), Y (au), VX (km/s), VY (km/s)
+Red comet, 0, Red, 1e15, 5000, 3, 0, 0, 10
+Teal comet, 0, 0 128 128 200, 1e15, 5000, 4, 0, 0, 9
+Small comet, 1, , 1e12, 500, 3.1, 0.5, -1, 11
+";
+        let mut loaded = parse_bodies(comets).unwrap();
+        assert!(loaded.iter().all(|body| body.mass_loss_rate == 0.));
+
+        // Built by hand: without a name, or with one hard to write
+        let body = |x: f64| {
+            Body::new(
+                1e12,
+                (x * AU, 0.),
+                500.,
+                1.,
+                Color::GOLD,
+                (0., 1.),
+                (0., 0.),
+            )
+        };
+        let mut added = [
+            body(5.),
+            body(6.),
+            body(7.).with_label("Comet, the second"),
+            body(8.).with_label("#3 \"the quoted\""),
+        ];
+        added[1].group_id = 2;
+        added[2].trail_color = Some(Color::new(10, 20, 30, 40));
+        added[3].trail_color = Some(Color::SKYBLUE);
+        for (i, body) in added.into_iter().enumerate() {
+            match i {
+                0 => loaded.tier0.insert(body.id(), body),
+                _ => loaded.tier1.insert(body.id(), body),
+            };
+        }
+
+        let reloaded = parse_bodies(&format_bodies(&loaded)).unwrap();
+        assert_eq!(reloaded.tier_counts(), (3, 4));
+
+        let state = |bodies: &OrbitalBodies| {
+            let mut state = bodies
+                .iter()
+                .map(|body| {
+                    let tier0 = bodies.tier0.contains_key(&body.id());
+                    let (x, y) = body.pos();
+                    let (vx, vy) = body.velocity;
+                    let color = |color: Color| (color.r, color.g, color.b, color.a);
+                    let colors = (
+                        color(body.color),
+                        body.theme_color,
+                        body.trail_color.map(color),
+                    );
+                    let physical = [body.mass, body.physical_radius, x, y, vx, vy];
+                    (body.label.clone(), tier0, body.group_id, colors, physical)
+                })
+                .collect::<Vec<_>>();
+            state.sort_by(|a, b| a.4[2].total_cmp(&b.4[2]));
+            state
+        };
+        assert_eq!(state(&reloaded), state(&loaded));
+    }
+
+    #[test]
+    fn test_scene_round_trip() {
+        let mut sun = Body::new(
+            SUN_MASS,
+            (0., 0.),
+            SUN_RADIUS,
+            20.,
+            Color::YELLOW,
+            (0., 0.),
+            (0., 0.),
+        )
+        .with_label("Sun");
+        sun.fixed = true;
+        let sun_id = sun.id();
+        let mut earth = Body::new(
+            EARTH_MASS,
+            (AU, 0.),
+            EARTH_RADIUS,
+            10.,
+            Color::BLUE,
+            (0., 29_780.),
+            (0., 0.),
+        );
+        earth.trail_color = Some(Color::SKYBLUE);
+        let earth_id = earth.id();
+        let mut comet = Body::new(
+            1e14,
+            (2. * AU, 0.),
+            1.,
+            2.5,
+            Color::GRAY,
+            (0., 0.),
+            (0., 0.),
+        )
+        .with_label("Comet, periodic");
+        comet.drag_law = DragLaw::Linear;
+        comet.drag_coefficient = 1e-9;
+        comet.mass_loss_rate = 1000.;
+        let comet_id = comet.id();
+
+        let bodies = OrbitalBodies::new(
+            bodies_to_map(vec![sun, earth, comet]),
+            bodies_to_map(vec![]),
+        );
+        let camera = SavedCamera {
+            target: SavedTarget::Body(earth_id),
+            scale: 1.5e-9,
+        };
+        let content = format_scene(&bodies, 86_400., &camera);
+
+        // Bodies created in between would have taken the saved ids
+        let _churn = (0..10)
+            .map(|_| Body::new(1., (0., 0.), 1., 1., Color::WHITE, (0., 0.), (0., 0.)))
+            .collect::<Vec<_>>();
+
+        let saved = parse_scene(&content).unwrap();
+        assert_eq!(saved.time, 86_400.);
+        assert_eq!(saved.camera, camera);
+        assert_eq!(saved.bodies.tier_counts(), (3, 0));
+
+        let SavedTarget::Body(followed) = saved.camera.target else {
+            panic!("expected to follow a body");
+        };
+        let loaded = saved.bodies.get_by_id(followed).unwrap();
+        let original = bodies.get_by_id(earth_id).unwrap();
+        assert_eq!(loaded.pos(), original.pos());
+        assert_eq!(loaded.velocity, original.velocity);
+        assert_eq!(loaded.mass, original.mass);
+        assert_eq!(saved.bodies.get_by_id(sun_id).unwrap().name(), "Sun");
+        assert_eq!(loaded.label, None);
+        assert_eq!(
+            saved.bodies.get_by_id(comet_id).unwrap().name(),
+            "Comet, periodic"
+        );
+
+        // Drawn and moving as they were, not as the bodies of a new table
+        for original in bodies.iter() {
+            let loaded = saved.bodies.get_by_id(original.id()).unwrap();
+            assert_eq!(loaded.color, original.color);
+            assert_eq!(loaded.trail_color, original.trail_color);
+            assert_eq!(loaded.draw_radius, original.draw_radius);
+            assert_eq!(loaded.fixed, original.fixed);
+            assert_eq!(loaded.drag_law, original.drag_law);
+            assert_eq!(loaded.drag_coefficient, original.drag_coefficient);
+            assert_eq!(loaded.mass_loss_rate, original.mass_loss_rate);
+        }
+        assert_eq!(
+            saved.bodies.get_by_id(comet_id).unwrap().mass_loss_rate,
+            1000.
+        );
+
+        let newer = Body::new(1., (0., 0.), 1., 1., Color::WHITE, (0., 0.), (0., 0.));
+        assert!(saved.bodies.get_by_id(newer.id()).is_none());
+
+        let duplicated = content.replace(&format!("{earth_id}, ,"), &format!("{sun_id}, ,"));
+        assert!(parse_scene(&duplicated).is_err());
+    }
+
+    #[test]
+    fn test_scene_springs() {
+        let body = |x: f64| Body::new(1e3, (x, 0.), 1., 1., Color::WHITE, (0., 0.), (0., 0.));
+        let (first, second, asteroid) = (body(0.), body(10.), body(20.));
+        let (first_id, second_id, asteroid_id) = (first.id(), second.id(), asteroid.id());
+
+        let spring = Constraint {
+            bodies: (first_id, second_id),
+            stiffness: 2.5,
+            rest_length: 8.,
+        };
+        let mut bodies = OrbitalBodies::new(
+            bodies_to_map(vec![first, second]),
+            bodies_to_map(vec![asteroid]),
+        );
+        bodies.constraints = vec![
+            spring,
+            Constraint {
+                bodies: (first_id, asteroid_id),
+                ..spring
+            },
+        ];
+        let camera = SavedCamera {
+            target: SavedTarget::Point((0., 0.)),
+            scale: 1.,
+        };
+        let content = format_scene(&bodies, 0., &camera);
+
+        // The spring to the asteroid pulls nothing, it is left out
+        let saved = parse_scene(&content).unwrap();
+        assert_eq!(saved.bodies.constraints, vec![spring]);
+
+        let dangling = content.replace(
+            &format!("@spring, {first_id}, {second_id},"),
+            &format!("@spring, {first_id}, {asteroid_id},"),
+        );
+        assert!(parse_scene(&dangling).is_err());
+        assert!(parse_scene(&content.replace(", 2.5, 8", ", 2.5")).is_err());
+    }
+}
@@ -0,0 +1,842 @@
+use crate::body::{BodyId, OrbitalBodies, TrailSampling};
+use crate::constants::{AU, SECONDS_PER_DAY};
+use crate::physics::collision_stats::CollisionStats;
+use crate::physics::collisions::{CollisionEvent, MergeCondition, handle_collisions};
+use crate::physics::sublimation::{CometTails, sublimate};
+use crate::physics::{
+    Kinematics, KinematicsDiagnostic, PhysicsConfig, coincident_pair, step_frozen_tier1, thaw_tier1,
+};
+use std::fmt::{Display, Formatter};
+use std::ops::ControlFlow;
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
+
+/// Memory the trails use at most by default, in bytes
+pub const TRAIL_BUDGET_DEFAULT: usize = 64 * 1024 * 1024;
+
+/// A set of bodies advanced by an integrator, without any rendering. This is
+/// the entry point to drive the physics from outside the window loop.
+pub struct Simulation {
+    /// The bodies being simulated
+    pub bodies: OrbitalBodies,
+    /// Integrator used for each step
+    pub kinematics: Box<dyn Kinematics>,
+    /// How to merge colliding bodies, `None` disables collisions
+    pub merge_condition: Option<MergeCondition>,
+    /// Tunables of the gravity and the collisions
+    pub config: PhysicsConfig,
+    /// When the trails of the bodies get a new point
+    pub trail_sampling: TrailSampling,
+    /// Memory the trails of all the bodies use at most, in bytes, see
+    /// [OrbitalBodies::apply_trail_budget]
+    pub trail_budget: usize,
+    /// Where to report the merges of bodies, see [CollisionEvent]
+    pub collision_events: Option<Sender<CollisionEvent>>,
+    /// Merges since the start, to study the accretion rates
+    pub collision_stats: CollisionStats,
+    /// Turn the mass the comets lose into tails of tier 1 particles, see
+    /// [sublimate]
+    pub comet_tails: bool,
+    /// Particles of the tail of each comet, see [CometTails]
+    tails: CometTails,
+    /// Trail budget the trails were last sized for, `None` once bodies
+    /// came or went since, see [OrbitalBodies::apply_trail_budget]
+    trail_budget_applied: Option<usize>,
+    /// Simulated time elapsed since the start, in seconds
+    time: f64,
+    /// Simulated time the trails were last sampled at
+    last_trail_time: f64,
+    /// Energy of the system the drift is measured against
+    energy_baseline: KinematicsDiagnostic,
+    /// Diagnostic of the last step
+    last_diagnostic: KinematicsDiagnostic,
+    /// Total momentum the drift is measured against, see
+    /// [OrbitalBodies::total_momentum], and the sum of the magnitudes of the
+    /// momenta it is relative to
+    momentum_baseline: ((f64, f64), f64),
+    /// Kinetic energy lost in the merges since the baseline, in J, up to the
+    /// ones of the step before the last
+    dissipated_energy: f64,
+    /// Kinetic energy lost in the merges of the last step, in J. The
+    /// diagnostic of that step was measured before them, so it only counts
+    /// from the next one.
+    pending_dissipation: f64,
+    /// First body found with a non-finite state after the last step
+    divergence: Option<Divergence>,
+    /// First pair of bodies found at the very same place since the start,
+    /// whose potential energy is left out, see [coincident_pair]
+    coincident: Option<(BodyId, BodyId)>,
+    /// Whether the tier 1 bodies were frozen during the last step, see
+    /// [PhysicsConfig::freeze_tier1]
+    tier1_frozen: bool,
+}
+
+/// A body whose position or velocity is no longer finite, after the
+/// integration blew up
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Divergence {
+    pub body: BodyId,
+    /// Name of the quantity that went bad, `position` or `velocity`
+    pub quantity: &'static str,
+    pub value: (f64, f64),
+}
+
+/// The first body with a non-finite position or velocity, if any
+fn find_divergence(bodies: &OrbitalBodies) -> Option<Divergence> {
+    let finite = |(x, y): (f64, f64)| x.is_finite() && y.is_finite();
+
+    bodies.iter().find_map(|body| {
+        let (quantity, value) = [("position", body.pos()), ("velocity", body.velocity)]
+            .into_iter()
+            .find(|(_, value)| !finite(*value))?;
+
+        Some(Divergence {
+            body: body.id(),
+            quantity,
+            value,
+        })
+    })
+}
+
+/// State of the energy of a [Simulation]
+#[derive(Copy, Clone)]
+pub struct SimulationDiagnostics {
+    /// Energy of the system after the last step
+    pub energy: KinematicsDiagnostic,
+    /// Relative drift of the total energy since the baseline
+    pub energy_drift: f64,
+    /// Change of the total momentum since the baseline, relative to the sum
+    /// of the magnitudes of the momenta of the bodies then. About zero for
+    /// a closed system.
+    pub momentum_drift: f64,
+    /// Like `energy_drift`, with the energy lost in the merges added back:
+    /// the error of the integrator alone
+    pub corrected_energy_drift: f64,
+}
+
+/// Report of a [Simulation::run], printed as a block of lines for logging
+/// the results of an experiment
+#[derive(Debug, Copy, Clone)]
+pub struct RunSummary {
+    /// Name of the integrator used
+    pub integrator: &'static str,
+    /// Number of steps taken
+    pub steps: usize,
+    /// Simulated time covered by the run, in seconds
+    pub simulated_time: f64,
+    /// Wall-clock time spent running
+    pub wall_time: Duration,
+    /// Relative drift of the total energy between the first and the last
+    /// step of the run
+    pub energy_drift: f64,
+    /// Total energy after the first and the last step of the run, in J
+    pub initial_energy: f64,
+    pub final_energy: f64,
+    /// Largest number of bodies seen during the run
+    pub peak_body_count: usize,
+    /// Number of bodies left at the end of the run
+    pub body_count: usize,
+    /// Number of merges and shatters during the run
+    pub collisions: usize,
+    /// Position and velocity of the center of mass at the end of the run,
+    /// see [OrbitalBodies::barycenter]
+    pub barycenter: Option<((f64, f64), (f64, f64))>,
+}
+
+impl Display for RunSummary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Integrator:     {}", self.integrator)?;
+        writeln!(f, "Steps:          {}", self.steps)?;
+        writeln!(
+            f,
+            "Simulated time: {:.2} days",
+            self.simulated_time / SECONDS_PER_DAY
+        )?;
+        writeln!(f, "Wall time:      {:.3} s", self.wall_time.as_secs_f64())?;
+        writeln!(
+            f,
+            "Energy:         {:.6e} J -> {:.6e} J ({:+.6}%)",
+            self.initial_energy,
+            self.final_energy,
+            self.energy_drift * 100.
+        )?;
+        writeln!(f, "Collisions:     {}", self.collisions)?;
+        writeln!(
+            f,
+            "Bodies:         {} (peak {})",
+            self.body_count, self.peak_body_count
+        )?;
+        match self.barycenter {
+            Some(((x, y), (vx, vy))) => write!(
+                f,
+                "Barycenter:     ({:.6}, {:.6}) AU, ({:.3}, {:.3}) m/s",
+                x / AU,
+                y / AU,
+                vx,
+                vy
+            ),
+            None => write!(f, "Barycenter:     none"),
+        }
+    }
+}
+
+/// Energy of the bodies, measured with a negligible step so that it is
+/// computed the same way the integrator does
+fn measure_energy(
+    bodies: &mut OrbitalBodies,
+    kinematics: &dyn Kinematics,
+    config: &PhysicsConfig,
+) -> KinematicsDiagnostic {
+    kinematics.step(bodies, 0.01, config)
+}
+
+/// The total momentum of the bodies, and the sum of the magnitudes of the
+/// momenta of the bodies it counts, see [SimulationDiagnostics::momentum_drift]
+fn momentum_baseline(bodies: &OrbitalBodies) -> ((f64, f64), f64) {
+    let scale = bodies
+        .tier0
+        .values()
+        .filter(|body| !body.fixed)
+        .map(|body| body.mass * body.actual_velocity())
+        .sum();
+
+    (bodies.total_momentum(), scale)
+}
+
+impl Simulation {
+    pub fn new(mut bodies: OrbitalBodies, kinematics: Box<dyn Kinematics>) -> Self {
+        let config = PhysicsConfig::default();
+        let energy_baseline = measure_energy(&mut bodies, kinematics.as_ref(), &config);
+        let momentum_baseline = momentum_baseline(&bodies);
+
+        let mut simulation = Self {
+            bodies,
+            kinematics,
+            merge_condition: Some(MergeCondition::default()),
+            trail_sampling: TrailSampling::Time(config.base_dt),
+            trail_budget: TRAIL_BUDGET_DEFAULT,
+            collision_events: None,
+            collision_stats: CollisionStats::default(),
+            comet_tails: false,
+            tails: CometTails::default(),
+            trail_budget_applied: None,
+            config,
+            time: 0.,
+            last_trail_time: 0.,
+            momentum_baseline,
+            energy_baseline,
+            last_diagnostic: energy_baseline,
+            dissipated_energy: 0.,
+            pending_dissipation: 0.,
+            divergence: None,
+            coincident: None,
+            tier1_frozen: false,
+        };
+        simulation.find_coincident();
+        simulation
+    }
+
+    /// Simulated time elapsed since the start, in seconds
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    /// Set the simulated time, for when the bodies are put back in an
+    /// earlier state such as when scrubbing the history
+    pub fn set_time(&mut self, time: f64) {
+        self.time = time;
+        self.last_trail_time = time;
+    }
+
+    /// Advance the simulation by a single step of `dt` seconds. With
+    /// [PhysicsConfig::freeze_tier1], only the tier 0 bodies move.
+    pub fn advance(&mut self, dt: f64) -> KinematicsDiagnostic {
+        let kinematics = self.kinematics.as_ref();
+
+        let diagnostic = if self.config.freeze_tier1 {
+            step_frozen_tier1(kinematics, &mut self.bodies, dt, &self.config)
+        } else {
+            if self.tier1_frozen {
+                thaw_tier1(&mut self.bodies, &self.config);
+            }
+            kinematics.step(&mut self.bodies, dt, &self.config)
+        };
+        self.tier1_frozen = self.config.freeze_tier1;
+        self.dissipated_energy += std::mem::take(&mut self.pending_dissipation);
+        let mut new_bodies = false;
+
+        if let Some(merge_condition) = self.merge_condition {
+            let outcome = handle_collisions(
+                &mut self.bodies,
+                merge_condition,
+                &self.config,
+                dt,
+                self.collision_events.as_ref(),
+                Some(&mut self.collision_stats),
+            );
+            self.pending_dissipation = outcome.dissipated_energy;
+
+            if !outcome.merged_into.is_empty() || outcome.shattered > 0 {
+                self.trail_budget_applied = None;
+            }
+            new_bodies = outcome.shattered > 0;
+        }
+
+        for (comet, particle) in sublimate(&mut self.bodies, dt, self.comet_tails) {
+            self.tails.add(&mut self.bodies, comet, particle);
+            self.trail_budget_applied = None;
+            new_bodies = true;
+        }
+
+        // The fragments and the tail particles may start on top of a body
+        if new_bodies {
+            self.find_coincident();
+        }
+
+        self.time += dt;
+        self.collision_stats.end_step(self.time);
+        self.sample_trails();
+        self.last_diagnostic = diagnostic;
+        self.divergence = find_divergence(&self.bodies);
+        diagnostic
+    }
+
+    /// The body that went non-finite in the last step, at which point the
+    /// simulation is meaningless: the step is too large for the integrator
+    pub fn divergence(&self) -> Option<Divergence> {
+        self.divergence
+    }
+
+    /// Look for bodies at the very same place, as bodies come, and report the
+    /// first pair found
+    fn find_coincident(&mut self) {
+        if self.coincident.is_some() {
+            return;
+        }
+
+        self.coincident = coincident_pair(&self.bodies, &self.config);
+        if let Some((body1, body2)) = self.coincident {
+            eprintln!(
+                "Bodies #{body1} and #{body2} are at the same position, skipping their potential \
+                 energy (not reported again)"
+            );
+        }
+    }
+
+    /// Add a point to the trails if it is due, whatever the number of steps
+    /// it took to get there
+    fn sample_trails(&mut self) {
+        // The shares only change with the bodies or the budget
+        if self.trail_budget_applied != Some(self.trail_budget) {
+            self.bodies.apply_trail_budget(self.trail_budget);
+            self.trail_budget_applied = Some(self.trail_budget);
+        }
+
+        match self.trail_sampling {
+            TrailSampling::Time(interval) => {
+                // Either way, to also sample when running backwards
+                if (self.time - self.last_trail_time).abs() >= interval {
+                    self.bodies.sample_trails(0.);
+                    self.last_trail_time = self.time;
+                }
+            }
+            TrailSampling::Distance(distance) => self.bodies.sample_trails(distance),
+        }
+    }
+
+    /// Kinetic energy lost in the collisions of bodies since the baseline,
+    /// in J
+    pub fn dissipated_energy(&self) -> f64 {
+        self.dissipated_energy + self.pending_dissipation
+    }
+
+    /// Energy after the last step, and how far it drifted from the baseline
+    pub fn diagnostics(&self) -> SimulationDiagnostics {
+        let delta = self.last_diagnostic - self.energy_baseline;
+        let baseline = self.energy_baseline.total();
+
+        let ((px, py), (baseline_px, baseline_py)) =
+            (self.bodies.total_momentum(), self.momentum_baseline.0);
+        let momentum_delta = ((px - baseline_px).powf(2.) + (py - baseline_py).powf(2.)).sqrt();
+
+        SimulationDiagnostics {
+            energy: self.last_diagnostic,
+            energy_drift: delta / baseline,
+            momentum_drift: match self.momentum_baseline.1 {
+                0. => 0.,
+                scale => momentum_delta / scale,
+            },
+            corrected_energy_drift: (delta + self.dissipated_energy) / baseline,
+        }
+    }
+
+    /// Measure the energy the drift is relative to again, for when bodies
+    /// are added. Their trails are sized again at the next step.
+    pub fn rebaseline(&mut self) {
+        self.trail_budget_applied = None;
+        self.energy_baseline =
+            measure_energy(&mut self.bodies, self.kinematics.as_ref(), &self.config);
+        self.last_diagnostic = self.energy_baseline;
+        self.momentum_baseline = momentum_baseline(&self.bodies);
+        self.dissipated_energy = 0.;
+        self.pending_dissipation = 0.;
+        self.find_coincident();
+    }
+
+    /// Start over from time zero with new bodies, such as the scene built
+    /// again. The integrator and physics choices are kept.
+    pub fn reset(&mut self, bodies: OrbitalBodies) {
+        self.bodies = bodies;
+        self.time = 0.;
+        self.last_trail_time = 0.;
+        self.divergence = None;
+        self.coincident = None;
+        self.collision_stats = CollisionStats::default();
+        self.tails.clear();
+        self.rebaseline();
+    }
+
+    /// Run `steps` steps of `dt` seconds each.
+    ///
+    /// After each step, `callback` is invoked with the bodies, the diagnostic
+    /// of the step and the simulated time. The bodies are only borrowed
+    /// immutably: the callback can observe the system but not alter it while
+    /// it runs. Returning [ControlFlow::Break] stops the run early.
+    ///
+    /// Returns a summary of the run, see [RunSummary] to print it.
+    pub fn run<F>(&mut self, steps: usize, dt: f64, mut callback: F) -> RunSummary
+    where
+        F: FnMut(&OrbitalBodies, KinematicsDiagnostic, f64) -> ControlFlow<()>,
+    {
+        let start = Instant::now();
+        let mut summary = RunSummary {
+            integrator: self.kinematics.name(),
+            steps: 0,
+            simulated_time: 0.,
+            wall_time: Duration::ZERO,
+            energy_drift: 0.,
+            initial_energy: self.last_diagnostic.total(),
+            final_energy: self.last_diagnostic.total(),
+            peak_body_count: self.bodies.len(),
+            body_count: self.bodies.len(),
+            collisions: 0,
+            barycenter: None,
+        };
+        let mut first_diagnostic = None;
+        let collisions = |stats: &CollisionStats| stats.merges + stats.shatters;
+        let initial_collisions = collisions(&self.collision_stats);
+
+        for _ in 0..steps {
+            let diagnostic = self.advance(dt);
+
+            let first = *first_diagnostic.get_or_insert(diagnostic);
+            summary.energy_drift = (diagnostic - first) / first.total();
+            summary.initial_energy = first.total();
+            summary.final_energy = diagnostic.total();
+            summary.collisions = collisions(&self.collision_stats) - initial_collisions;
+            summary.steps += 1;
+            summary.simulated_time += dt;
+            summary.peak_body_count = summary.peak_body_count.max(self.bodies.len());
+
+            if callback(&self.bodies, diagnostic, self.time).is_break() {
+                break;
+            }
+        }
+
+        summary.wall_time = start.elapsed();
+        summary.body_count = self.bodies.len();
+        summary.barycenter = self.bodies.barycenter();
+        summary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::body::{Body, OrbitalBodies, TrailSampling, bodies_to_map};
+    use crate::constants::{
+        AU, EARTH_MASS, EARTH_RADIUS, EARTH_SUN_VELOCITY, SUN_EARTH_DISTANCE, SUN_MASS,
+    };
+    use crate::physics::collisions::MergeCondition;
+    use crate::physics::leapfrog::Leapfrog;
+    use crate::simulation::Simulation;
+    use raylib::color::Color;
+    use ringbuffer::RingBuffer;
+    use std::ops::ControlFlow;
+
+    fn sun_and_earth_bodies() -> OrbitalBodies {
+        let sun = Body::new(
+            SUN_MASS,
+            (0., 0.),
+            1.,
+            1.,
+            Color::YELLOW,
+            (0., 0.),
+            (0., 0.),
+        );
+        let earth = Body::new(
+            EARTH_MASS,
+            (0., SUN_EARTH_DISTANCE),
+            1.,
+            1.,
+            Color::BLUE,
+            (EARTH_SUN_VELOCITY, 0.),
+            (0., 0.),
+        );
+
+        OrbitalBodies::new(bodies_to_map(vec![sun, earth]), bodies_to_map(vec![]))
+    }
+
+    fn sun_and_earth() -> Simulation {
+        Simulation::new(sun_and_earth_bodies(), Box::new(Leapfrog))
+    }
+
+    #[test]
+    fn test_comet_tail_outside_comet() {
+        let sun = Body::new(
+            SUN_MASS,
+            (0., 0.),
+            1.,
+            1.,
+            Color::YELLOW,
+            (0., 0.),
+            (0., 0.),
+        );
+        let mut comet = Body::new(
+            1e14,
+            (AU, 0.),
+            5500.,
+            1.,
+            Color::WHITE,
+            (0., 30_000.),
+            (0., 0.),
+        );
+        comet.mass_loss_rate = 1000.;
+
+        let mut simulation = Simulation::new(
+            OrbitalBodies::new(bodies_to_map(vec![sun, comet]), bodies_to_map(vec![])),
+            Box::new(Leapfrog),
+        );
+        simulation.comet_tails = true;
+        simulation.merge_condition = Some(MergeCondition::Overlap);
+
+        // Steps far shorter than it takes the tail to leave the comet
+        for _ in 0..10 {
+            simulation.advance(1.);
+        }
+
+        // None of the particles fell back into the comet
+        assert_eq!(simulation.bodies.tier1.len(), 10);
+    }
+
+    #[test]
+    fn test_trail_budget_after_merge() {
+        let body = |mass: f64, pos: (f64, f64), radius: f64, velocity: (f64, f64)| {
+            Body::new(mass, pos, radius, 1., Color::WHITE, velocity, (0., 0.))
+        };
+        let mut comet = body(1e14, (AU, 0.), 5500., (0., 30_000.));
+        comet.mass_loss_rate = 1000.;
+        let bodies = vec![
+            body(SUN_MASS, (0., 0.), 1., (0., 0.)),
+            comet,
+            // Touching at the end of the second step only
+            body(1., (0., 2. * AU), 1., (0., 0.)),
+            body(1., (20., 2. * AU), 1., (-10., 0.)),
+        ];
+
+        let mut simulation = Simulation::new(
+            OrbitalBodies::new(bodies_to_map(bodies), bodies_to_map(vec![])),
+            Box::new(Leapfrog),
+        );
+        simulation.comet_tails = true;
+        simulation.advance(1.);
+        simulation.advance(1.);
+
+        // The second step merged two bodies and added a particle, as many
+        // bodies as before, yet the new particle got no room for a trail
+        assert_eq!(simulation.bodies.tier0.len(), 3);
+        assert_eq!(simulation.bodies.tier1.len(), 2);
+        for particle in simulation.bodies.tier1.values() {
+            assert_eq!(particle.pos_list.capacity(), 1);
+        }
+    }
+
+    #[test]
+    fn test_advance() {
+        let mut simulation = sun_and_earth();
+        let start = simulation.bodies.iter().map(Body::pos).collect::<Vec<_>>();
+
+        for _ in 0..24 {
+            simulation.advance(3600.);
+        }
+
+        assert_eq!(simulation.time(), 24. * 3600.);
+        assert_ne!(
+            simulation.bodies.iter().map(Body::pos).collect::<Vec<_>>(),
+            start
+        );
+        assert!(simulation.diagnostics().energy_drift.abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut simulation = sun_and_earth();
+        for _ in 0..100 {
+            simulation.advance(86_400.);
+        }
+
+        simulation.reset(sun_and_earth_bodies());
+
+        assert_eq!(simulation.time(), 0.);
+        assert_eq!(simulation.bodies.len(), 2);
+        assert_eq!(simulation.diagnostics().energy_drift, 0.);
+    }
+
+    #[test]
+    fn test_coincident_after_reset() {
+        let coincident = || {
+            let body = || Body::new(1., (AU, 0.), 0., 1., Color::WHITE, (0., 0.), (0., 0.));
+            OrbitalBodies::new(bodies_to_map(vec![body(), body()]), bodies_to_map(vec![]))
+        };
+
+        let mut simulation = Simulation::new(coincident(), Box::new(Leapfrog));
+        assert!(simulation.coincident.is_some());
+
+        simulation.reset(sun_and_earth_bodies());
+        assert_eq!(simulation.coincident, None);
+
+        // Found again in the new scene
+        simulation.reset(coincident());
+        assert!(simulation.coincident.is_some());
+    }
+
+    #[test]
+    fn test_divergence() {
+        let mut simulation = sun_and_earth();
+        simulation.advance(86_400.);
+        assert_eq!(simulation.divergence(), None);
+
+        // A body so fast it leaves the representable universe in a step
+        let runaway = Body::new(
+            EARTH_MASS,
+            (0., 0.),
+            1.,
+            1.,
+            Color::BLUE,
+            (f64::MAX, 0.),
+            (0., 0.),
+        );
+        simulation.reset(OrbitalBodies::new(
+            bodies_to_map(vec![runaway]),
+            bodies_to_map(vec![]),
+        ));
+        simulation.advance(86_400.);
+
+        let divergence = simulation.divergence().unwrap();
+        assert!(simulation.bodies.get_by_id(divergence.body).is_some());
+        assert!(!(divergence.value.0.is_finite() && divergence.value.1.is_finite()));
+    }
+
+    #[test]
+    fn test_corrected_energy_drift() {
+        let mut bodies = sun_and_earth_bodies();
+        let earth = bodies
+            .tier0
+            .values_mut()
+            .find(|body| body.mass == EARTH_MASS)
+            .unwrap();
+        earth.physical_radius = EARTH_RADIUS;
+        let (x, y) = earth.pos();
+
+        // Light and fast, merging into the Earth rather than shattering it
+        let impactor = Body::new(
+            1e18,
+            (x + EARTH_RADIUS, y),
+            1e6,
+            1.,
+            Color::GRAY,
+            (EARTH_SUN_VELOCITY - 1e7, 0.),
+            (0., 0.),
+        );
+        bodies.tier0.insert(impactor.id(), impactor);
+
+        let mut simulation = Simulation::new(bodies, Box::new(Leapfrog));
+        simulation.advance(1e-3);
+        assert_eq!(simulation.bodies.len(), 2);
+        assert!(simulation.dissipated_energy() > 0.);
+
+        // Measured before the merge, nothing to correct yet
+        let diagnostics = simulation.diagnostics();
+        assert!(diagnostics.energy_drift.abs() < 1e-9);
+        assert!(diagnostics.corrected_energy_drift.abs() < 1e-9);
+
+        // After it, the energy lost is made up for
+        simulation.advance(1e-3);
+        let diagnostics = simulation.diagnostics();
+        assert!(diagnostics.energy_drift.abs() > 1e-3);
+        assert!(diagnostics.corrected_energy_drift.abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_momentum_drift() {
+        let mut bodies = sun_and_earth_bodies();
+        let mars = Body::new(
+            EARTH_MASS / 10.,
+            (-1.5 * AU, 0.),
+            1.,
+            1.,
+            Color::RED,
+            (0., 24_000.),
+            (0., 0.),
+        );
+        bodies.tier0.insert(mars.id(), mars);
+        let start = bodies.total_momentum();
+
+        let mut simulation = Simulation::new(bodies, Box::new(Leapfrog {}));
+        for _ in 0..24 * 100 {
+            simulation.advance(3600.);
+        }
+
+        // The pulls are equal and opposite, whatever the orbits did
+        let (px, py) = simulation.bodies.total_momentum();
+        let scale = EARTH_MASS * EARTH_SUN_VELOCITY;
+        assert!((px - start.0).abs() / scale < 1e-12);
+        assert!((py - start.1).abs() / scale < 1e-12);
+        assert!(simulation.diagnostics().momentum_drift < 1e-12);
+    }
+
+    #[test]
+    fn test_freeze_tier1() {
+        let mut bodies = sun_and_earth_bodies();
+        let asteroid = Body::new(
+            1.,
+            (2. * AU, 0.),
+            1.,
+            1.,
+            Color::GRAY,
+            (0., 20_000.),
+            (0., 0.),
+        );
+        let asteroid_id = asteroid.id();
+        bodies.tier1 = bodies_to_map(vec![asteroid]);
+
+        let mut simulation = Simulation::new(bodies, Box::new(Leapfrog {}));
+        simulation.config.freeze_tier1 = true;
+
+        let earth_pos = |simulation: &Simulation| {
+            simulation
+                .bodies
+                .tier0
+                .values()
+                .find(|body| body.mass == EARTH_MASS)
+                .unwrap()
+                .pos()
+        };
+        let asteroid = |simulation: &Simulation| {
+            let asteroid = simulation.bodies.get_by_id(asteroid_id).unwrap();
+            (asteroid.pos(), asteroid.velocity)
+        };
+
+        let (earth_before, asteroid_before) = (earth_pos(&simulation), asteroid(&simulation));
+        for _ in 0..10 {
+            simulation.advance(3600.);
+        }
+        assert_ne!(earth_pos(&simulation), earth_before);
+        assert_eq!(asteroid(&simulation), asteroid_before);
+
+        // Thawed, it is pulled again, from where the sun is now
+        simulation.config.freeze_tier1 = false;
+        simulation.advance(3600.);
+        let (pos, velocity) = asteroid(&simulation);
+        assert_ne!(pos, asteroid_before.0);
+        assert_ne!(velocity, asteroid_before.1);
+    }
+
+    #[test]
+    fn test_run_callback_count() {
+        let mut simulation = sun_and_earth();
+        let mut calls = 0;
+
+        let summary = simulation.run(25, 3600., |_, _, _| {
+            calls += 1;
+            ControlFlow::Continue(())
+        });
+
+        assert_eq!(summary.steps, 25);
+        assert_eq!(calls, 25);
+        assert_eq!(simulation.time(), 25. * 3600.);
+    }
+
+    #[test]
+    fn test_run_abort() {
+        let mut simulation = sun_and_earth();
+
+        let summary = simulation.run(25, 3600., |_, _, time| {
+            if time >= 10. * 3600. {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+
+        assert_eq!(summary.steps, 10);
+    }
+
+    #[test]
+    fn test_run_summary() {
+        let mut simulation = sun_and_earth();
+        let dt = 3600.;
+
+        let summary = simulation.run(100, dt, |_, _, _| ControlFlow::Continue(()));
+
+        assert_eq!(summary.simulated_time, summary.steps as f64 * dt);
+        assert_eq!(summary.peak_body_count, 2);
+        assert!(summary.energy_drift.abs() < 1e-6);
+        assert_eq!(summary.integrator, simulation.kinematics.name());
+        assert_eq!(summary.body_count, 2);
+        assert_eq!(summary.collisions, 0);
+
+        // The sun starts at rest, the barycenter drifts with the momentum of
+        // the earth
+        let ((x, y), (vx, vy)) = summary.barycenter.unwrap();
+        let expected = simulation.bodies.barycenter().unwrap();
+        assert_eq!(((x, y), (vx, vy)), expected);
+        assert!((vx - EARTH_SUN_VELOCITY * EARTH_MASS / (SUN_MASS + EARTH_MASS)).abs() < 1e-3);
+
+        let report = summary.to_string();
+        assert!(report.contains("Steps:          100"));
+        assert!(report.contains("Collisions:     0"));
+    }
+
+    #[test]
+    fn test_trail_sampling_rate() {
+        let trail = |dt: f64, sampling: TrailSampling| {
+            let mut simulation = sun_and_earth();
+            simulation.trail_sampling = sampling;
+            simulation.run((100. * 86_400. / dt) as usize, dt, |_, _, _| {
+                ControlFlow::Continue(())
+            });
+
+            let earth = simulation
+                .bodies
+                .iter()
+                .find(|body| body.mass == EARTH_MASS)
+                .unwrap();
+            earth.pos_list.iter().copied().collect::<Vec<_>>()
+        };
+
+        // A point a day, taken every step or every other step
+        let daily = TrailSampling::Time(86_400.);
+        let (fine, coarse) = (trail(3_600., daily), trail(7_200., daily));
+        assert_eq!(fine.len(), 100);
+        assert_eq!(fine.len(), coarse.len());
+        for (a, b) in fine.iter().zip(&coarse) {
+            assert!(((a.0 - b.0).powf(2.) + (a.1 - b.1).powf(2.)).sqrt() < 1e-6 * AU);
+        }
+
+        // The earth moves about 0.017 AU a day
+        let spaced = TrailSampling::Distance(0.05 * AU);
+        let (fine, coarse) = (trail(3_600., spaced), trail(7_200., spaced));
+        assert!(fine.len().abs_diff(coarse.len()) <= 1);
+        assert!((30..=36).contains(&fine.len()));
+    }
+}